@@ -0,0 +1,11 @@
+//! Builds with the runtime loaded ABIs of `src/bindings` when the forge generated `out/bindings` are missing,
+//! so a plain `cargo build` works without foundry. The `runtime-abi` feature forces them.
+fn main() {
+    println!("cargo:rerun-if-changed=out/bindings/src/lib.rs");
+    println!("cargo:rustc-check-cfg=cfg(runtime_abi)");
+    if std::env::var_os("CARGO_FEATURE_RUNTIME_ABI").is_some()
+        || !std::path::Path::new("out/bindings/src/lib.rs").exists()
+    {
+        println!("cargo:rustc-cfg=runtime_abi");
+    }
+}
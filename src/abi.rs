@@ -0,0 +1,355 @@
+//! Dynamic decoding of Portfolio, NormalStrategy and ERC20 calldata and logs.
+//! The ABIs of the generated bindings are converted to alloy's json abi, so any ABI can be decoded the same way.
+use alloy_dyn_abi::{DynSolType, DynSolValue};
+use alloy_json_abi::{Event, Function, JsonAbi, Param};
+use alloy_primitives::B256;
+use anyhow;
+use ethers::utils::hex;
+use serde_json::{json, Map, Value};
+
+/// ABIs of every contract the cli talks to.
+pub fn known_abis() -> Result<Vec<JsonAbi>, anyhow::Error> {
+    let abis = [
+        serde_json::to_string(&*crate::bindings::i_portfolio::IPORTFOLIO_ABI)?,
+        serde_json::to_string(&*crate::bindings::normal_strategy::NORMALSTRATEGY_ABI)?,
+        serde_json::to_string(&*crate::tokens::IERC20PERMIT_ABI)?,
+    ];
+
+    abis.iter()
+        .map(|abi| Ok(serde_json::from_str::<JsonAbi>(abi)?))
+        .collect()
+}
+
+/// A decoded function call or event, with its values keyed by parameter name.
+#[derive(Debug, Clone)]
+pub struct Decoded {
+    pub name: String,
+    pub fields: Map<String, Value>,
+    /// Calls nested in a `multicall`.
+    pub calls: Vec<Decoded>,
+}
+
+impl Decoded {
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({ "name": self.name, "fields": self.fields });
+        if !self.calls.is_empty() {
+            value["calls"] = Value::Array(self.calls.iter().map(Decoded::to_json).collect());
+        }
+        value
+    }
+}
+
+/// Decodes calldata against the functions of `abis`, expanding the entries of a `multicall`.
+pub fn decode_call(abis: &[JsonAbi], data: &[u8]) -> Result<Decoded, anyhow::Error> {
+    if data.len() < 4 {
+        return Err(anyhow::anyhow!("Calldata is shorter than a selector"));
+    }
+
+    let function = abis
+        .iter()
+        .flat_map(|abi| abi.functions())
+        .find(|function| function.selector().as_slice() == &data[..4])
+        .ok_or(anyhow::anyhow!(
+            "Unknown selector 0x{}",
+            hex::encode(&data[..4])
+        ))?;
+
+    let values = decode_params(&function.inputs, &data[4..])?;
+    let mut calls = Vec::new();
+    if function.name == "multicall" {
+        if let Some(DynSolValue::Array(entries)) = values.first() {
+            for entry in entries {
+                if let DynSolValue::Bytes(entry) = entry {
+                    calls.push(decode_call(abis, entry)?);
+                }
+            }
+        }
+    }
+
+    Ok(Decoded {
+        name: signature(function),
+        fields: named(&function.inputs, &values),
+        calls,
+    })
+}
+
+/// Decodes a log against the events of `abis`.
+pub fn decode_log(abis: &[JsonAbi], topics: &[B256], data: &[u8]) -> Option<Decoded> {
+    let topic = topics.first()?;
+    let event = abis.iter().flat_map(|abi| abi.events()).find(|event| {
+        !event.anonymous
+            && event.selector().as_slice() == topic.as_slice()
+            && event.inputs.iter().filter(|input| input.indexed).count() == topics.len() - 1
+    })?;
+
+    let (indexed, body): (Vec<_>, Vec<_>) = event.inputs.iter().partition(|input| input.indexed);
+    let body_params: Vec<Param> = body.iter().map(|input| event_param(input)).collect();
+    let mut body_values = decode_params(&body_params, data).ok()?.into_iter();
+    let mut topic_values = indexed
+        .iter()
+        .zip(topics.iter().skip(1))
+        .map(|(input, topic)| decode_topic(&event_param(input), topic));
+
+    let mut fields = Map::new();
+    for input in &event.inputs {
+        let value = if input.indexed {
+            topic_values.next()?
+        } else {
+            body_values.next()?
+        };
+        fields.insert(input.name.clone(), to_json(&value, &input.components));
+    }
+
+    Some(Decoded {
+        name: event_signature(event),
+        fields,
+        calls: vec![],
+    })
+}
+
+/// Canonical solidity type of a parameter, expanding tuples into their components.
+pub fn param_type(ty: &str, components: &[Param]) -> String {
+    match ty.strip_prefix("tuple") {
+        Some(suffix) => format!(
+            "({}){}",
+            components
+                .iter()
+                .map(|component| param_type(&component.ty, &component.components))
+                .collect::<Vec<_>>()
+                .join(","),
+            suffix
+        ),
+        None => ty.to_string(),
+    }
+}
+
+/// Decodes abi encoded parameters into one value per parameter.
+pub fn decode_params(params: &[Param], data: &[u8]) -> Result<Vec<DynSolValue>, anyhow::Error> {
+    let types = params
+        .iter()
+        .map(|param| param_type(&param.ty, &param.components).parse::<DynSolType>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match DynSolType::Tuple(types).decode_params(data)? {
+        DynSolValue::Tuple(values) => Ok(values),
+        value => Ok(vec![value]),
+    }
+}
+
+/// Indexed dynamic values are only stored as their hash, so those are returned as raw words.
+fn decode_topic(param: &Param, topic: &B256) -> DynSolValue {
+    let ty = param_type(&param.ty, &param.components);
+    let is_dynamic = ty == "string" || ty == "bytes" || ty.ends_with(']') || ty.starts_with('(');
+
+    match ty.parse::<DynSolType>() {
+        Ok(ty) if !is_dynamic => ty
+            .decode_single(topic.as_slice())
+            .unwrap_or(DynSolValue::FixedBytes(*topic, 32)),
+        _ => DynSolValue::FixedBytes(*topic, 32),
+    }
+}
+
+fn event_param(input: &alloy_json_abi::EventParam) -> Param {
+    Param {
+        name: input.name.clone(),
+        ty: input.ty.clone(),
+        components: input.components.clone(),
+        internal_type: input.internal_type.clone(),
+    }
+}
+
+fn signature(function: &Function) -> String {
+    format!(
+        "{}({})",
+        function.name,
+        function
+            .inputs
+            .iter()
+            .map(|input| param_type(&input.ty, &input.components))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+fn event_signature(event: &Event) -> String {
+    format!(
+        "{}({})",
+        event.name,
+        event
+            .inputs
+            .iter()
+            .map(|input| param_type(&input.ty, &input.components))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+fn named(params: &[Param], values: &[DynSolValue]) -> Map<String, Value> {
+    params
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(i, (param, value))| {
+            let name = if param.name.is_empty() {
+                i.to_string()
+            } else {
+                param.name.clone()
+            };
+            (name, to_json(value, &param.components))
+        })
+        .collect()
+}
+
+/// Converts a decoded value to json, naming tuple fields after their components.
+/// Integers are kept as decimal strings so they can be scaled without losing precision.
+pub fn to_json(value: &DynSolValue, components: &[Param]) -> Value {
+    match value {
+        DynSolValue::Address(address) => json!(address.to_string()),
+        DynSolValue::Bool(value) => json!(value),
+        DynSolValue::Int(value, _) => json!(value.to_string()),
+        DynSolValue::Uint(value, _) => json!(value.to_string()),
+        DynSolValue::FixedBytes(value, size) => {
+            json!(format!("0x{}", hex::encode(&value[..*size])))
+        }
+        DynSolValue::Bytes(value) => json!(format!("0x{}", hex::encode(value))),
+        DynSolValue::String(value) => json!(value),
+        DynSolValue::Array(values) | DynSolValue::FixedArray(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| to_json(value, components))
+                .collect(),
+        ),
+        DynSolValue::Tuple(values) if values.len() == components.len() => {
+            Value::Object(named(components, values))
+        }
+        DynSolValue::Tuple(values) => {
+            Value::Array(values.iter().map(|value| to_json(value, &[])).collect())
+        }
+        value => json!(format!("{:?}", value)),
+    }
+}
+
+/// Calldata of a call to `signature` with `tokens`, for the tests building calls by hand.
+#[cfg(test)]
+pub(crate) fn encode_call(signature: &str, tokens: &[ethers::abi::Token]) -> ethers::types::Bytes {
+    let mut data = ethers::utils::id(signature).to_vec();
+    data.extend(ethers::abi::encode(tokens));
+    data.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::{encode, Token},
+        types::{Address, Bytes, H256, U256},
+        utils::keccak256,
+    };
+
+    const ABI: &str = r#"[
+        {"type": "function", "name": "multicall", "stateMutability": "payable",
+         "inputs": [{"name": "data", "type": "bytes[]"}],
+         "outputs": [{"name": "results", "type": "bytes[]"}]},
+        {"type": "function", "name": "swap", "stateMutability": "payable",
+         "inputs": [{"name": "args", "type": "tuple", "components": [
+            {"name": "input", "type": "uint128"},
+            {"name": "output", "type": "uint128"},
+            {"name": "useMax", "type": "bool"},
+            {"name": "poolId", "type": "uint64"},
+            {"name": "sellAsset", "type": "bool"}]}],
+         "outputs": []},
+        {"type": "function", "name": "claimFee", "stateMutability": "nonpayable",
+         "inputs": [{"name": "token", "type": "address"}, {"name": "amount", "type": "uint256"}],
+         "outputs": []},
+        {"type": "event", "name": "Swap", "anonymous": false, "inputs": [
+            {"name": "poolId", "type": "uint64", "indexed": true},
+            {"name": "price", "type": "uint256", "indexed": false},
+            {"name": "tokenIn", "type": "address", "indexed": true},
+            {"name": "input", "type": "uint256", "indexed": false}]}
+    ]"#;
+
+    fn abis() -> Vec<JsonAbi> {
+        vec![serde_json::from_str(ABI).unwrap()]
+    }
+
+    fn swap() -> Bytes {
+        encode_call(
+            "swap((uint128,uint128,bool,uint64,bool))",
+            &[Token::Tuple(vec![
+                Token::Uint(U256::from(1_000)),
+                Token::Uint(U256::from(900)),
+                Token::Bool(false),
+                Token::Uint(U256::from(1_099_511_627_777u64)),
+                Token::Bool(true),
+            ])],
+        )
+    }
+
+    #[test]
+    fn names_order_tuple_fields() {
+        let decoded = decode_call(&abis(), &swap()).unwrap();
+        assert_eq!(decoded.name, "swap((uint128,uint128,bool,uint64,bool))");
+        let order = &decoded.fields["args"];
+        assert_eq!(order["input"], "1000");
+        assert_eq!(order["output"], "900");
+        assert_eq!(order["useMax"], false);
+        assert_eq!(order["poolId"], "1099511627777");
+        assert_eq!(order["sellAsset"], true);
+    }
+
+    #[test]
+    fn expands_nested_multicalls() {
+        let claim = encode_call(
+            "claimFee(address,uint256)",
+            &[
+                Token::Address(Address::repeat_byte(1)),
+                Token::Uint(U256::from(5)),
+            ],
+        );
+        let inner = encode_call(
+            "multicall(bytes[])",
+            &[Token::Array(vec![Token::Bytes(claim.to_vec())])],
+        );
+        let outer = encode_call(
+            "multicall(bytes[])",
+            &[Token::Array(vec![
+                Token::Bytes(swap().to_vec()),
+                Token::Bytes(inner.to_vec()),
+            ])],
+        );
+
+        let decoded = decode_call(&abis(), &outer).unwrap();
+        assert_eq!(decoded.calls.len(), 2);
+        assert!(decoded.calls[0].name.starts_with("swap("));
+        assert_eq!(decoded.calls[1].calls.len(), 1);
+        assert_eq!(decoded.calls[1].calls[0].name, "claimFee(address,uint256)");
+        assert_eq!(decoded.calls[1].calls[0].fields["amount"], "5");
+        assert_eq!(
+            decoded.to_json()["calls"][1]["calls"][0]["fields"]["amount"],
+            "5"
+        );
+        assert!(decode_call(&abis(), &[0xde, 0xad, 0xbe, 0xef]).is_err());
+    }
+
+    #[test]
+    fn decodes_indexed_and_body_event_params() {
+        let token_in = Address::repeat_byte(2);
+        let topics = [
+            B256::from(keccak256("Swap(uint64,uint256,address,uint256)")),
+            B256::from(H256::from_low_u64_be(7).0),
+            B256::from(H256::from(token_in).0),
+        ];
+        let data = encode(&[Token::Uint(U256::from(1_500)), Token::Uint(U256::from(3))]);
+
+        let decoded = decode_log(&abis(), &topics, &data).unwrap();
+        assert_eq!(decoded.name, "Swap(uint64,uint256,address,uint256)");
+        assert_eq!(decoded.fields["poolId"], "7");
+        assert_eq!(decoded.fields["price"], "1500");
+        assert_eq!(
+            decoded.fields["tokenIn"].as_str().unwrap().to_lowercase(),
+            format!("{:?}", token_in)
+        );
+        assert_eq!(decoded.fields["input"], "3");
+        assert!(decode_log(&abis(), &topics[..2], &data).is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! Liquidity positions of an account, with the greeks of their NormalStrategy pools.
+use super::greeks::{self, Risk, Scenario};
+use super::info::PoolState;
+use super::list::created_pools;
+use super::utils::read_block;
+use super::{compat, AccountArgs, Config};
+use crate::bindings::i_portfolio::IPortfolio;
+use colored::Colorize;
+use ethers::{prelude::*, utils::format_ether};
+use std::sync::Arc;
+
+/// Handles the "Account" command.
+pub async fn main(cfg: &Config, args: &AccountArgs) -> Result<(), anyhow::Error> {
+    let account = args.account.parse::<Address>()?;
+    let shock = args
+        .scenario
+        .as_deref()
+        .map(str::parse::<Scenario>)
+        .transpose()?;
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, provider.clone());
+    compat::detect(cfg, &portfolio)
+        .await?
+        .require_all(&["positions", "pools", "getSpotPrice"])?;
+
+    let block = read_block(cfg, &*provider).await?;
+    let pool_ids = match args.pool {
+        Some(pool_id) => vec![pool_id],
+        None => created_pools(cfg, provider.clone())
+            .await?
+            .iter()
+            .map(|event| event.pool_id)
+            .collect(),
+    };
+
+    let account_msg = format!(
+        "{} {} {} {}",
+        "Positions of".yellow(),
+        format!("{:?}", account).bold().magenta(),
+        "at block".yellow(),
+        block.to_string().bold().yellow()
+    );
+    println!("{}", account_msg.on_black());
+
+    let mut found = 0;
+    for pool_id in pool_ids {
+        let liquidity = portfolio
+            .positions(account, pool_id)
+            .block(block)
+            .call()
+            .await?;
+        if liquidity == 0 {
+            continue;
+        }
+        found += 1;
+
+        let state = PoolState::fetch_at(cfg, provider.clone(), pool_id, block).await?;
+        let risk = Risk::fetch(provider.clone(), &state).await?;
+        let position = risk.greeks(liquidity);
+        let shocked = shock.map(|shock| risk.revalue(&shock, liquidity));
+
+        let position_msg = format!(
+            "
+        {} {}
+        - liquidity: {}
+        - share of pool: {}
+        - spot price: {}",
+            "Pool".yellow().bold(),
+            pool_id.to_string().bold().magenta(),
+            format_ether(U256::from(liquidity)).yellow(),
+            format!(
+                "{:.4}%",
+                liquidity as f64 / state.pool.liquidity.max(1) as f64 * 100.0
+            )
+            .yellow(),
+            risk.price.to_string().yellow(),
+        );
+        println!("{}", position_msg.on_black());
+        greeks::print(
+            "Greeks:",
+            &position,
+            args.scenario.as_deref().zip(shocked.as_ref()),
+        );
+    }
+
+    if found == 0 {
+        println!("{}", "   - no liquidity positions".purple().on_black());
+    }
+    Ok(())
+}
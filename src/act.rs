@@ -1,6 +1,10 @@
 use ethers::abi::{Function, Token};
 
-use super::actions::{self, Actions};
+use super::actions::{
+    self, allocate::AllocateArgs, claim::ClaimArgs, deallocate::DeallocateArgs, Actions,
+};
+use super::batch::{self, Step};
+use super::utils::signer_client;
 use super::ActArgs;
 use super::Config;
 
@@ -9,10 +13,39 @@ pub async fn main(cfg: &Config, args: &ActArgs) -> Result<(), anyhow::Error> {
     let action = match &args.function {
         action if action == "swap" => {
             let id: u64 = args.pool_id.parse::<u64>()?;
-            actions::swap::main(cfg, id, &args.args).await?
+            actions::swap::main(cfg, id, &args.args, args.verbose.unwrap_or(false)).await?
+        }
+        action if action == "allocate" || action == "deallocate" || action == "claim" => {
+            let step = single_step(args)?;
+            let client = signer_client(cfg).await?;
+            batch::execute(cfg, client, &[step], false, args.verbose.unwrap_or(false)).await?;
         }
         _ => unimplemented!("not implemented yet"),
     };
 
     Ok(())
 }
+
+/// Parses the cli arguments of a non-swap action into a batch step.
+fn single_step(args: &ActArgs) -> Result<Step, anyhow::Error> {
+    let cli_args = args.args.clone().ok_or(anyhow::anyhow!(
+        "Missing input arguments, try passing --args <args> to the {} action.",
+        args.function
+    ))?;
+
+    let step = match args.function.as_str() {
+        "allocate" => Step::Allocate {
+            pool_id: args.pool_id.parse::<u64>()?,
+            args: AllocateArgs::from_cli(cli_args)?,
+        },
+        "deallocate" => Step::Deallocate {
+            pool_id: args.pool_id.parse::<u64>()?,
+            args: DeallocateArgs::from_cli(cli_args)?,
+        },
+        _ => Step::Claim {
+            args: ClaimArgs::from_cli(cli_args)?,
+        },
+    };
+
+    Ok(step)
+}
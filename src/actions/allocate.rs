@@ -0,0 +1,61 @@
+/// Handles allocate actions
+use crate::bindings::i_portfolio::AllocateCall;
+use clap::Args;
+use ethers::{prelude::*, utils::parse_ether};
+use serde::{Deserialize, Serialize};
+
+/// Arguments for adding liquidity to a pool.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AllocateArgs {
+    /// Liquidity to allocate, in wad.
+    #[arg(long, default_value_t)]
+    pub liquidity: f64,
+    /// Maximum amount of asset tokens to pay.
+    #[arg(long, default_value_t)]
+    pub max_asset: f64,
+    /// Maximum amount of quote tokens to pay.
+    #[arg(long, default_value_t)]
+    pub max_quote: f64,
+    /// Allocates the max liquidity the signer's balance affords.
+    #[arg(long)]
+    pub use_max: bool,
+}
+
+impl AllocateArgs {
+    /// Parses `<liquidity> <max_asset> <max_quote> [use_max]`.
+    pub fn from_cli(args: Vec<String>) -> Result<Self, anyhow::Error> {
+        if args.len() < 3 {
+            return Err(anyhow::anyhow!(
+                "Missing input argument, expected <liquidity> <max_asset> <max_quote> [use_max]"
+            ));
+        }
+
+        Ok(Self {
+            liquidity: args[0].parse::<f64>()?,
+            max_asset: args[1].parse::<f64>()?,
+            max_quote: args[2].parse::<f64>()?,
+            use_max: match args.get(3) {
+                Some(use_max) => use_max.parse::<bool>()?,
+                None => false,
+            },
+        })
+    }
+
+    /// Builds the `allocate` call, crediting the liquidity to `recipient`.
+    /// Pool id zero allocates to the pool created last, e.g. earlier in the same multicall.
+    pub fn to_call(&self, pool_id: u64, recipient: Address) -> Result<AllocateCall, anyhow::Error> {
+        if !self.use_max && self.liquidity <= 0.0 {
+            return Err(anyhow::anyhow!("Liquidity must be positive"));
+        }
+
+        Ok(AllocateCall {
+            use_max: self.use_max,
+            recipient,
+            pool_id,
+            delta_liquidity: parse_ether(self.liquidity)?.as_u128(),
+            max_delta_asset: parse_ether(self.max_asset)?.as_u128(),
+            max_delta_quote: parse_ether(self.max_quote)?.as_u128(),
+        })
+    }
+}
@@ -0,0 +1,250 @@
+/// Handles token approvals for Portfolio actions
+use crate::tokens::IERC20Permit;
+use crate::utils::Client;
+use crate::{tx, Config};
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{
+    abi::{self, Token},
+    prelude::*,
+    utils::keccak256,
+};
+use inquire::Select;
+use std::{fmt, sync::Arc};
+
+/// Seconds a permit signature stays valid for.
+const PERMIT_VALIDITY: u64 = 20 * 60;
+
+/// Ways to give Portfolio access to the signer's tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ApprovalKind {
+    Exact,
+    Unlimited,
+    Permit,
+}
+
+impl ApprovalKind {
+    /// Parses the `approval` policy of the config or of the `--approval` flag.
+    fn parse(policy: &str) -> Result<Self, anyhow::Error> {
+        match policy {
+            "exact" => Ok(ApprovalKind::Exact),
+            "unlimited" => Ok(ApprovalKind::Unlimited),
+            "permit" => Ok(ApprovalKind::Permit),
+            _ => Err(anyhow::anyhow!(
+                "Invalid approval policy {}, expected exact, unlimited or permit",
+                policy
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ApprovalKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalKind::Exact => write!(f, "Approve exact amount"),
+            ApprovalKind::Unlimited => write!(f, "Approve unlimited amount"),
+            ApprovalKind::Permit => write!(f, "Sign EIP-2612 permit"),
+        }
+    }
+}
+
+/// Fails unless an approval policy is set, for the commands running unattended, which can't be prompted.
+pub fn require_policy(cfg: &Config, command: &str) -> Result<(), anyhow::Error> {
+    if cfg.approval.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} runs unattended and can't ask how to approve tokens: set `approval = \"exact\"`, \"unlimited\" or \"permit\" in portfolio.toml, or pass --approval",
+            command
+        ));
+    }
+    ApprovalKind::parse(&cfg.approval).map(|_| ())
+}
+
+/// Checks the signer's allowance of `token` for `spender` and approves it if it is short of `amount`,
+/// as the `approval` policy says or as asked when none is set. `amount` is in units of the token, see `wad_to_units`.
+/// Approvals and permits are sent and awaited, so the action is estimated against the new allowance.
+pub async fn ensure_allowance(
+    cfg: &Config,
+    client: &Arc<Client>,
+    token: Address,
+    spender: Address,
+    amount: U256,
+) -> Result<(), anyhow::Error> {
+    let erc20 = IERC20Permit::new(token, client.clone());
+    let owner = client.address();
+
+    let allowance = erc20
+        .allowance(owner, spender)
+        .call()
+        .await
+        .context("approve.rs: Failed to get allowance")?;
+    let symbol = erc20
+        .symbol()
+        .call()
+        .await
+        .unwrap_or_else(|_| token.to_string());
+
+    if allowance >= amount {
+        let allowance_msg = format!(
+            "{} {} {}",
+            "Allowance for".green(),
+            symbol.bold().green(),
+            "is sufficient".green()
+        );
+        println!("{}", allowance_msg.on_black());
+        return Ok(());
+    }
+
+    let short_msg = format!(
+        "{} {} {} {} {} {}",
+        "Allowance for".yellow(),
+        symbol.bold().yellow(),
+        "is".yellow(),
+        allowance.to_string().bold().yellow(),
+        "but the action needs".yellow(),
+        amount.to_string().bold().yellow(),
+    );
+    println!("{}", short_msg.on_black());
+
+    let kind = match cfg.approval.as_str() {
+        "" => {
+            let mut options = vec![ApprovalKind::Exact, ApprovalKind::Unlimited];
+            if supports_permit(&erc20, owner).await {
+                options.push(ApprovalKind::Permit);
+            }
+            Select::new("How do you want to approve?", options).prompt()?
+        }
+        policy => {
+            let kind = ApprovalKind::parse(policy)?;
+            if kind == ApprovalKind::Permit && !supports_permit(&erc20, owner).await {
+                return Err(anyhow::anyhow!(
+                    "{} does not support EIP-2612 permits, set another approval policy",
+                    symbol
+                ));
+            }
+            let policy_msg = format!(
+                "{} {}",
+                "Approval policy:".yellow(),
+                kind.to_string().bold().yellow()
+            );
+            println!("{}", policy_msg.on_black());
+            kind
+        }
+    };
+    match kind {
+        ApprovalKind::Exact => approve(cfg, client, &erc20, spender, amount).await?,
+        ApprovalKind::Unlimited => approve(cfg, client, &erc20, spender, U256::MAX).await?,
+        ApprovalKind::Permit => permit(cfg, client, &erc20, spender, amount).await?,
+    }
+
+    Ok(())
+}
+
+/// Tokens implementing EIP-2612 expose both `nonces` and `DOMAIN_SEPARATOR`.
+async fn supports_permit(erc20: &IERC20Permit<Client>, owner: Address) -> bool {
+    erc20.nonces(owner).call().await.is_ok() && erc20.domain_separator().call().await.is_ok()
+}
+
+/// Sends an `approve` transaction and waits for it to be mined.
+async fn approve(
+    cfg: &Config,
+    client: &Arc<Client>,
+    erc20: &IERC20Permit<Client>,
+    spender: Address,
+    amount: U256,
+) -> Result<(), anyhow::Error> {
+    let pending_msg = format!("{}", "Sending approval... please be patient".yellow());
+    println!("{}", pending_msg.on_black());
+
+    let call = erc20.approve(spender, amount);
+    let pending = tx::send(cfg, client, call.tx)
+        .await
+        .context("approve.rs: Failed to send approval")?;
+    mined(pending).await?;
+
+    println!("{}", "Approved".green().on_black());
+    Ok(())
+}
+
+/// Signs an EIP-2612 permit for `amount` and submits it, waiting for it to be mined.
+/// Portfolio's `multicall` only calls Portfolio, so the permit can't be bundled with the action.
+async fn permit(
+    cfg: &Config,
+    client: &Arc<Client>,
+    erc20: &IERC20Permit<Client>,
+    spender: Address,
+    amount: U256,
+) -> Result<(), anyhow::Error> {
+    let owner = client.address();
+    let nonce = erc20.nonces(owner).call().await?;
+    let domain_separator = erc20.domain_separator().call().await?;
+    let deadline = U256::from(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs()
+            + PERMIT_VALIDITY,
+    );
+
+    let digest = permit_digest(domain_separator, owner, spender, amount, nonce, deadline);
+    let signature = client.signer().sign_hash(H256::from(digest))?;
+
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    signature.r.to_big_endian(&mut r);
+    signature.s.to_big_endian(&mut s);
+
+    let call = erc20.permit(owner, spender, amount, deadline, signature.v as u8, r, s);
+    let pending = tx::send(cfg, client, call.tx)
+        .await
+        .context("approve.rs: Failed to send permit")?;
+    mined(pending).await
+}
+
+/// Waits for an approval to be mined, even with `confirmations = 0`, since the action that follows relies on it.
+async fn mined(pending: PendingTransaction<'_, Ws>) -> Result<(), anyhow::Error> {
+    let tx_hash = pending.tx_hash();
+    let receipt = pending
+        .await
+        .context("approve.rs: Failed to confirm approval")?
+        .ok_or(anyhow::anyhow!(
+            "Approval {:?} was dropped from the mempool",
+            tx_hash
+        ))?;
+    if receipt.status != Some(U64::from(1)) {
+        return Err(anyhow::anyhow!("Approval {:?} reverted", tx_hash));
+    }
+    let mined_msg = format!(
+        "{} {}",
+        "Approval mined in tx".green(),
+        format!("{:?}", tx_hash).bold().green()
+    );
+    println!("{}", mined_msg.on_black());
+    Ok(())
+}
+
+/// Computes the EIP-712 digest of a `Permit` message for the token's domain.
+fn permit_digest(
+    domain_separator: [u8; 32],
+    owner: Address,
+    spender: Address,
+    value: U256,
+    nonce: U256,
+    deadline: U256,
+) -> [u8; 32] {
+    let typehash = keccak256(
+        "Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)",
+    );
+    let struct_hash = keccak256(abi::encode(&[
+        Token::FixedBytes(typehash.to_vec()),
+        Token::Address(owner),
+        Token::Address(spender),
+        Token::Uint(value),
+        Token::Uint(nonce),
+        Token::Uint(deadline),
+    ]));
+
+    let mut message = Vec::with_capacity(66);
+    message.extend_from_slice(&[0x19, 0x01]);
+    message.extend_from_slice(&domain_separator);
+    message.extend_from_slice(&struct_hash);
+    keccak256(message)
+}
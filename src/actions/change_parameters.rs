@@ -0,0 +1,42 @@
+/// Handles changeParameters actions
+use super::create_pool::{MAX_FEE, MIN_FEE};
+use crate::bindings::i_portfolio::ChangeParametersCall;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+/// Arguments for changing the fees of a controlled pool.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangeParametersArgs {
+    /// New swap fee paid by the controller, in basis points.
+    #[arg(long, default_value_t)]
+    pub priority_fee_bps: u16,
+    /// New swap fee, in basis points.
+    #[arg(long, default_value_t)]
+    pub fee_bps: u16,
+}
+
+impl ChangeParametersArgs {
+    /// Builds the `changeParameters` call.
+    pub fn to_call(&self, pool_id: u64) -> Result<ChangeParametersCall, anyhow::Error> {
+        if pool_id == 0 {
+            return Err(anyhow::anyhow!("Invalid pool id"));
+        }
+        if !(MIN_FEE..=MAX_FEE).contains(&self.fee_bps) {
+            return Err(anyhow::anyhow!(
+                "Fee must be between {} and {} basis points",
+                MIN_FEE,
+                MAX_FEE
+            ));
+        }
+        if self.priority_fee_bps > self.fee_bps {
+            return Err(anyhow::anyhow!("Priority fee must not exceed the fee"));
+        }
+
+        Ok(ChangeParametersCall {
+            pool_id,
+            priority_fee_basis_points: self.priority_fee_bps,
+            fee_basis_points: self.fee_bps,
+        })
+    }
+}
@@ -0,0 +1,50 @@
+/// Handles claim actions
+use crate::bindings::i_portfolio::ClaimFeeCall;
+use clap::Args;
+use ethers::{prelude::*, utils::parse_ether};
+use serde::{Deserialize, Serialize};
+
+/// Arguments for claiming accrued protocol fees of a token.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClaimArgs {
+    /// Token to claim fees in.
+    #[arg(long, default_value_t)]
+    pub token: String,
+    /// Amount of fees to claim, zero claims everything.
+    #[arg(long, default_value_t)]
+    pub amount: f64,
+}
+
+impl ClaimArgs {
+    /// Parses `<token> [amount]`.
+    pub fn from_cli(args: Vec<String>) -> Result<Self, anyhow::Error> {
+        if args.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Missing input argument, expected <token> [amount]"
+            ));
+        }
+
+        Ok(Self {
+            token: args[0].clone(),
+            amount: match args.get(1) {
+                Some(amount) => amount.parse::<f64>()?,
+                None => 0.0,
+            },
+        })
+    }
+
+    /// Builds the `claimFee` call. Portfolio treats an amount of `type(uint256).max` as the full balance.
+    pub fn to_call(&self) -> Result<ClaimFeeCall, anyhow::Error> {
+        let amount = if self.amount == 0.0 {
+            U256::MAX
+        } else {
+            parse_ether(self.amount)?
+        };
+
+        Ok(ClaimFeeCall {
+            token: self.token.parse::<Address>()?,
+            amount,
+        })
+    }
+}
@@ -0,0 +1,33 @@
+/// Handles createPair actions
+use crate::bindings::i_portfolio::CreatePairCall;
+use clap::Args;
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Arguments for creating a pair of tokens pools can be created for.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CreatePairArgs {
+    /// Asset token of the pair.
+    #[arg(long, default_value_t)]
+    pub asset: String,
+    /// Quote token of the pair.
+    #[arg(long, default_value_t)]
+    pub quote: String,
+}
+
+impl CreatePairArgs {
+    /// Builds the `createPair` call.
+    pub fn to_call(&self) -> Result<CreatePairCall, anyhow::Error> {
+        let asset = self.asset.parse::<Address>()?;
+        let quote = self.quote.parse::<Address>()?;
+        if asset.is_zero() || quote.is_zero() {
+            return Err(anyhow::anyhow!("Pair tokens must not be the zero address"));
+        }
+        if asset == quote {
+            return Err(anyhow::anyhow!("Pair tokens must be different"));
+        }
+
+        Ok(CreatePairCall { asset, quote })
+    }
+}
@@ -0,0 +1,116 @@
+/// Handles createPool actions
+use crate::bindings::{i_portfolio::CreatePoolCall, normal_strategy::NormalStrategy};
+use clap::Args;
+use ethers::{prelude::*, utils::parse_ether};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Portfolio's bounds of a pool's fee, in basis points.
+pub const MIN_FEE: u16 = 1;
+pub const MAX_FEE: u16 = 1000;
+
+/// Arguments for creating a NormalStrategy pool.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CreatePoolArgs {
+    /// Pair of the pool, zero uses the pair created last, e.g. earlier in the same multicall.
+    #[arg(long, default_value_t)]
+    pub pair_id: u32,
+    /// NormalStrategy contract computing the pool's reserves.
+    #[arg(long, default_value_t)]
+    pub strategy: String,
+    /// Strike price, in quote tokens per asset token.
+    #[arg(long, default_value_t)]
+    pub strike: f64,
+    /// Implied volatility, in basis points.
+    #[arg(long, default_value_t)]
+    pub volatility_bps: u32,
+    /// Time until the pool expires, in days.
+    #[arg(long, default_value_t)]
+    pub duration_days: u32,
+    /// Keeps the pool's time to expiry constant.
+    #[arg(long)]
+    pub is_perpetual: bool,
+    /// Initial price, defaults to the strike.
+    #[arg(long, default_value_t)]
+    pub price: f64,
+    /// Swap fee, in basis points.
+    #[arg(long, default_value_t)]
+    pub fee_bps: u16,
+    /// Swap fee paid by the controller, in basis points.
+    #[arg(long, default_value_t)]
+    pub priority_fee_bps: u16,
+    /// Controller allowed to change the pool's fees, none if empty.
+    #[arg(long, default_value_t)]
+    pub controller: String,
+}
+
+impl CreatePoolArgs {
+    /// Checks the strategy parameters and fees against Portfolio's bounds.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.strike <= 0.0 {
+            return Err(anyhow::anyhow!("Strike must be positive"));
+        }
+        if self.volatility_bps == 0 {
+            return Err(anyhow::anyhow!("Volatility must be positive"));
+        }
+        if !self.is_perpetual && self.duration_days == 0 {
+            return Err(anyhow::anyhow!("Duration must be positive"));
+        }
+        if !(MIN_FEE..=MAX_FEE).contains(&self.fee_bps) {
+            return Err(anyhow::anyhow!(
+                "Fee must be between {} and {} basis points",
+                MIN_FEE,
+                MAX_FEE
+            ));
+        }
+        if self.priority_fee_bps > self.fee_bps {
+            return Err(anyhow::anyhow!("Priority fee must not exceed the fee"));
+        }
+        Ok(())
+    }
+
+    /// Builds the `createPool` call, computing the initial reserves with the strategy.
+    pub async fn to_call<M: Middleware + 'static>(
+        &self,
+        client: Arc<M>,
+    ) -> Result<CreatePoolCall, anyhow::Error> {
+        self.validate()?;
+
+        let controller = if self.controller.is_empty() {
+            Address::zero()
+        } else {
+            self.controller.parse::<Address>()?
+        };
+        let strategy = self.strategy.parse::<Address>()?;
+        let strike = parse_ether(self.strike)?;
+        let price = if self.price > 0.0 {
+            parse_ether(self.price)?
+        } else {
+            strike
+        };
+
+        let (strategy_args, reserve_x_per_wad, reserve_y_per_wad) =
+            NormalStrategy::new(strategy, client)
+                .get_strategy_data(
+                    strike,
+                    U256::from(self.volatility_bps),
+                    U256::from(self.duration_days) * 24 * 60 * 60,
+                    self.is_perpetual,
+                    price,
+                )
+                .call()
+                .await?;
+
+        Ok(CreatePoolCall {
+            pair_id: self.pair_id,
+            reserve_x_per_wad,
+            reserve_y_per_wad,
+            fee_basis_points: self.fee_bps,
+            priority_fee_basis_points: self.priority_fee_bps,
+            controller,
+            strategy,
+            strategy_args,
+        })
+    }
+}
@@ -0,0 +1,62 @@
+/// Handles deallocate actions
+use crate::bindings::i_portfolio::DeallocateCall;
+use clap::Args;
+use ethers::utils::parse_ether;
+use serde::{Deserialize, Serialize};
+
+/// Arguments for removing liquidity from a pool.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeallocateArgs {
+    /// Liquidity to remove, in wad.
+    #[arg(long, default_value_t)]
+    pub liquidity: f64,
+    /// Minimum amount of asset tokens to receive.
+    #[arg(long, default_value_t)]
+    pub min_asset: f64,
+    /// Minimum amount of quote tokens to receive.
+    #[arg(long, default_value_t)]
+    pub min_quote: f64,
+    /// Removes the signer's entire position.
+    #[arg(long)]
+    pub use_max: bool,
+}
+
+impl DeallocateArgs {
+    /// Parses `<liquidity> <min_asset> <min_quote> [use_max]`.
+    pub fn from_cli(args: Vec<String>) -> Result<Self, anyhow::Error> {
+        if args.len() < 3 {
+            return Err(anyhow::anyhow!(
+                "Missing input argument, expected <liquidity> <min_asset> <min_quote> [use_max]"
+            ));
+        }
+
+        Ok(Self {
+            liquidity: args[0].parse::<f64>()?,
+            min_asset: args[1].parse::<f64>()?,
+            min_quote: args[2].parse::<f64>()?,
+            use_max: match args.get(3) {
+                Some(use_max) => use_max.parse::<bool>()?,
+                None => false,
+            },
+        })
+    }
+
+    /// Builds the `deallocate` call.
+    pub fn to_call(&self, pool_id: u64) -> Result<DeallocateCall, anyhow::Error> {
+        if pool_id == 0 {
+            return Err(anyhow::anyhow!("Invalid pool id"));
+        }
+        if !self.use_max && self.liquidity <= 0.0 {
+            return Err(anyhow::anyhow!("Liquidity must be positive"));
+        }
+
+        Ok(DeallocateCall {
+            use_max: self.use_max,
+            pool_id,
+            delta_liquidity: parse_ether(self.liquidity)?.as_u128(),
+            min_delta_asset: parse_ether(self.min_asset)?.as_u128(),
+            min_delta_quote: parse_ether(self.min_quote)?.as_u128(),
+        })
+    }
+}
@@ -1,9 +1,22 @@
 use serde::{Deserialize, Serialize};
 
+pub mod allocate;
+pub mod approve;
+pub mod change_parameters;
+pub mod claim;
+pub mod create_pair;
+pub mod create_pool;
+pub mod deallocate;
 pub mod swap;
 
 /// Actions that can be performed on a Portfolio contract.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Actions {
     Swap,
+    Allocate,
+    Deallocate,
+    Claim,
+    CreatePair,
+    CreatePool,
+    ChangeParameters,
 }
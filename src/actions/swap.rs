@@ -1,20 +1,36 @@
 /// Handles swap actions
-use crate::Config;
+use super::approve;
+use crate::bindings::{
+    i_portfolio::IPortfolio, i_portfolio_actions::SwapCall, shared_types::Order,
+};
+use crate::compat::{self, Compat};
+use crate::utils::{signer_client, wad_to_units, Client, PoolId};
+use crate::{tx, Config};
 use anyhow::{self, Context};
-use bindings::{i_portfolio::IPortfolio, i_portfolio_actions::SwapCall, shared_types::Order};
+use clap::Args;
 use colored::Colorize;
 use ethers::{
     prelude::*,
     utils::{format_ether, parse_ether},
 };
-use std::sync::Arc;
-
-#[derive(Clone)]
-struct SwapArgs {
-    sell_asset: bool,
-    amount: f64,
-    price: f64,
-    slippage: f64,
+use serde::{Deserialize, Serialize};
+
+/// Arguments for swapping tokens in a pool.
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwapArgs {
+    /// Sells the asset token for the quote token if true.
+    #[arg(long)]
+    pub sell_asset: bool,
+    /// Amount of input tokens to sell.
+    #[arg(long, default_value_t)]
+    pub amount: f64,
+    /// Desired price of the trade.
+    #[arg(long, default_value_t)]
+    pub price: f64,
+    /// Tolerated slippage from the quoted output, as a fraction.
+    #[arg(long, default_value_t)]
+    pub slippage: f64,
 }
 
 impl Default for SwapArgs {
@@ -38,7 +54,7 @@ impl SwapArgs {
         }
     }
 
-    fn from_cli(args: Vec<String>) -> Result<Self, anyhow::Error> {
+    pub fn from_cli(args: Vec<String>) -> Result<Self, anyhow::Error> {
         if args.len() < 2 {
             return Err(anyhow::anyhow!("Missing input argument"));
         }
@@ -69,11 +85,38 @@ impl SwapArgs {
         Ok(swap_args)
     }
 
+    /// Builds an order of `swapper` quoted against the current pool state, lowering the output by the slippage tolerance.
+    pub async fn quote_order<M: Middleware + 'static>(
+        &self,
+        contract: &IPortfolio<M>,
+        pool_id: u64,
+        swapper: Address,
+    ) -> Result<Order, anyhow::Error> {
+        let mut order = parse_args(pool_id, self.clone())?;
+
+        let amount_out: U256 = contract
+            .get_amount_out(pool_id, order.sell_asset, order.input.into(), swapper)
+            .await
+            .context("swap.rs: Failed to get amount out")?;
+
+        let min_amount_out = amount_out
+            .checked_mul(parse_ether(1.0 - self.slippage)?)
+            .ok_or(anyhow::anyhow!("Overflow"))?
+            .checked_div(parse_ether(1.0)?)
+            .ok_or(anyhow::anyhow!("Overflow"))?;
+        order.output = min_amount_out.as_u128();
+
+        Ok(order)
+    }
+
     async fn prepare(
         &self,
-        contract: &IPortfolio<SignerMiddleware<Provider<Ws>, LocalWallet>>,
+        contract: &IPortfolio<Client>,
+        compat: &Compat,
         pool_id: u64,
     ) -> Result<Order, anyhow::Error> {
+        compat.require("getAmountOut")?;
+
         let preview_msg = format!(
             "\n{}",
             "Previewing swap... please be patient\n".yellow().bold()
@@ -110,10 +153,16 @@ impl SwapArgs {
         // Overwrite the output amount.
         swap_call.output = amount_out.as_u128();
 
-        let (success, _, _) = contract
-            .simulate_swap(swap_call.clone(), current_timestamp.into(), signer_address)
-            .await
-            .context("swap.rs: Failed to simulate swap")?;
+        // Versions without `simulateSwap` are only checked by the node's gas estimate.
+        let success = if compat.capabilities.has("simulateSwap") {
+            let (success, _, _) = contract
+                .simulate_swap(swap_call.clone(), current_timestamp.into(), signer_address)
+                .await
+                .context("swap.rs: Failed to simulate swap")?;
+            success
+        } else {
+            true
+        };
 
         let (bid, ask) = compute_bid_ask(spot_price, self.slippage)?;
         let mark_price =
@@ -173,35 +222,17 @@ pub async fn main(
     cfg: &Config,
     pool_id: u64,
     args: &Option<Vec<String>>,
+    verbose: bool,
 ) -> Result<(), anyhow::Error> {
-    let client = Arc::new({
-        let ws_provider = Provider::<Ws>::connect(&cfg.rpc_url).await?;
+    let client = signer_client(cfg).await?;
 
-        let chain_id = ws_provider.get_chainid().await?;
-        let wallet = std::env::var("PRIVATE_KEY")
-            .context("swap.rs: PRIVATE_KEY env var not set")?
-            .parse::<LocalWallet>()?
-            .with_chain_id(chain_id.as_u64());
-
-        SignerMiddleware::new(ws_provider, wallet)
-    });
-
-    let portfolio: IPortfolio<SignerMiddleware<Provider<Ws>, _>> =
+    let portfolio: IPortfolio<Client> =
         IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client);
-    let version = portfolio.version().call().await?;
-    let version_msg = format!(
-        "
-        {} {} {}
-        ",
-        "Portfolio version:".yellow(),
-        version.to_string().bold().yellow(),
-        "\n"
-    );
-    println!("{}", version_msg.on_black());
+    let compat = compat::detect(cfg, &portfolio).await?;
 
     if let Some(args) = args {
         let swap_args = SwapArgs::from_cli(args.clone())?;
-        do_swap(&portfolio, pool_id, swap_args).await?;
+        do_swap(cfg, &portfolio, &compat, pool_id, swap_args, verbose).await?;
     } else {
         println!("Missing input arguments, try passing --args <sell_asset> <amount> <price> <slippage> to the swap action.");
     }
@@ -225,23 +256,41 @@ fn parse_args(pool_id: u64, swap_args: SwapArgs) -> Result<Order, anyhow::Error>
 
 /// Gracefully executes a swap transaction on Portfolio and propagates any errors.
 async fn do_swap(
-    portfolio: &IPortfolio<SignerMiddleware<Provider<Ws>, LocalWallet>>,
+    cfg: &Config,
+    portfolio: &IPortfolio<Client>,
+    compat: &Compat,
     pool_id: u64,
     args: SwapArgs,
+    verbose: bool,
 ) -> Result<(), anyhow::Error> {
-    let mut swap_args = args.prepare(portfolio, pool_id).await?;
-    let mut result = portfolio
-        .swap(swap_args.clone())
+    let (asset, asset_decimals, quote, quote_decimals) = portfolio
+        .pairs(PoolId(pool_id).pair_id())
+        .call()
+        .await
+        .context("swap.rs: Failed to get pair")?;
+    let (token_in, decimals) = if args.sell_asset {
+        (asset, asset_decimals)
+    } else {
+        (quote, quote_decimals)
+    };
+    approve::ensure_allowance(
+        cfg,
+        &portfolio.client(),
+        token_in,
+        portfolio.address(),
+        wad_to_units(parse_ether(args.amount)?, decimals),
+    )
+    .await?;
+
+    let mut swap_args = args.prepare(portfolio, compat, pool_id).await?;
+    let client = portfolio.client();
+    let call = portfolio.swap(swap_args.clone());
+    let pending = tx::send(cfg, &client, call.tx)
         .await
         .context("swap.rs: Failed to execute swap")?;
+    tx::track(cfg, &client, pending, verbose).await?;
 
-    let success_msg = format!(
-        "{} {} {} {:#?}",
-        "Swap successful".bold().green(),
-        "🤑\n",
-        "Result:\n".purple(),
-        result
-    );
+    let success_msg = format!("{} {}", "Swap successful".bold().green(), "🤑\n");
     println!("{}", success_msg.on_black());
     Ok(())
 }
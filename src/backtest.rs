@@ -0,0 +1,273 @@
+//! Backtests of NormalStrategy pool configurations against historical or generated prices.
+use super::actions::create_pool::CreatePoolArgs;
+use super::greeks::YEAR;
+use super::history::parse_interval;
+use super::sim::{self, Outcome, Pool, Summary};
+use super::BacktestArgs;
+use colored::Colorize;
+
+/// Handles the "Backtest" command.
+pub fn main(args: &BacktestArgs) -> Result<(), anyhow::Error> {
+    let pool = CreatePoolArgs::from(&args.pool);
+    let initial = if pool.price > 0.0 {
+        pool.price
+    } else {
+        pool.strike
+    };
+
+    match &args.prices {
+        Some(path) => {
+            let (prices, step) = read_candles(&std::fs::read_to_string(path)?)?;
+            let step = match step {
+                Some(step) => step,
+                None => parse_interval(&args.path.step)?,
+            };
+            let dt = step as f64 / YEAR;
+            let pool = Pool::from_args(&pool, args.liquidity, prices[0])?;
+            let outcome = sim::run(pool, &prices, dt);
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&outcome)?);
+            } else {
+                print_outcome(path, &outcome);
+            }
+        }
+        None => {
+            let (dt, paths) = args.path.generate(&pool, initial)?;
+            let pool = Pool::from_args(&pool, args.liquidity, initial)?;
+            let outcomes: Vec<Outcome> = paths
+                .iter()
+                .map(|prices| sim::run(pool.clone(), prices, dt))
+                .collect();
+            let summary = Summary::of(&outcomes);
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                print_summary(&summary, outcomes.first().map(|outcome| outcome.steps));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads prices from a csv, from its `price` or `close` column if it has a header, or its last column.
+pub fn read_prices(csv: &str) -> Result<Vec<f64>, anyhow::Error> {
+    let mut lines = csv
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let header = lines
+        .peek()
+        .ok_or(anyhow::anyhow!("The price csv is empty"))?
+        .to_lowercase();
+    let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+    let column = match columns
+        .iter()
+        .position(|column| *column == "price" || *column == "close")
+    {
+        Some(column) => {
+            lines.next();
+            column
+        }
+        None if columns
+            .last()
+            .and_then(|last| last.parse::<f64>().ok())
+            .is_some() =>
+        {
+            columns.len() - 1
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "The price csv needs a price or close column"
+            ))
+        }
+    };
+
+    let prices = lines
+        .enumerate()
+        .map(|(i, line)| {
+            line.split(',')
+                .nth(column)
+                .and_then(|price| price.trim().parse::<f64>().ok())
+                .filter(|price| *price > 0.0)
+                .ok_or(anyhow::anyhow!("Invalid price on line {}: {}", i + 1, line))
+        })
+        .collect::<Result<Vec<f64>, _>>()?;
+    if prices.len() < 2 {
+        return Err(anyhow::anyhow!("The price csv needs at least two prices"));
+    }
+    Ok(prices)
+}
+
+/// Reads prices like [`read_prices`], spaced by the `start` column of the csv if it has one, with the seconds between them.
+/// `history --csv` skips the candles of intervals without swaps, in which the price did not move,
+/// so the previous price is repeated over them.
+pub fn read_candles(csv: &str) -> Result<(Vec<f64>, Option<u64>), anyhow::Error> {
+    let prices = read_prices(csv)?;
+    let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().unwrap_or_default().to_lowercase();
+    let Some(column) = header
+        .split(',')
+        .map(str::trim)
+        .position(|column| column == "start")
+    else {
+        return Ok((prices, None));
+    };
+
+    let starts = lines
+        .enumerate()
+        .map(|(i, line)| {
+            line.split(',')
+                .nth(column)
+                .and_then(|start| parse_start(start.trim()))
+                .ok_or(anyhow::anyhow!("Invalid start on line {}: {}", i + 1, line))
+        })
+        .collect::<Result<Vec<u64>, _>>()?;
+    let mut step = u64::MAX;
+    for (i, pair) in starts.windows(2).enumerate() {
+        if pair[1] <= pair[0] {
+            return Err(anyhow::anyhow!(
+                "The starts of the price csv must increase, line {} does not",
+                i + 2
+            ));
+        }
+        step = step.min(pair[1] - pair[0]);
+    }
+
+    let mut filled = vec![prices[0]];
+    for (i, pair) in starts.windows(2).enumerate() {
+        let gap = pair[1] - pair[0];
+        if gap % step != 0 {
+            return Err(anyhow::anyhow!(
+                "Line {} starts {}s after the previous one, which is not a multiple of the {}s step",
+                i + 2,
+                gap,
+                step
+            ));
+        }
+        filled.extend(std::iter::repeat(prices[i]).take((gap / step - 1) as usize));
+        filled.push(prices[i + 1]);
+    }
+    Ok((filled, Some(step)))
+}
+
+/// Reads a start as unix seconds, or as a `%Y-%m-%d %H:%M` UTC date.
+fn parse_start(start: &str) -> Option<u64> {
+    start.parse::<u64>().ok().or_else(|| {
+        chrono::NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M")
+            .ok()
+            .and_then(|time| u64::try_from(time.timestamp()).ok())
+    })
+}
+
+fn print_outcome(path: &str, outcome: &Outcome) {
+    let outcome_msg = format!(
+        "{} {}
+        - steps: {}
+        - swaps: {}
+        - volume: {}
+        - final reserves: {}
+        - final price: {}
+        {}
+        - deposit: {}
+        - lp value: {}
+        - hold value: {}
+        - fees: {}
+        - divergence: {}
+        - net vs hold: {}
+        - lp return: {}",
+        "Backtest of".yellow().bold(),
+        path.bold().magenta(),
+        outcome.steps.to_string().yellow(),
+        outcome.swaps.to_string().yellow(),
+        format!(
+            "{} asset / {} quote",
+            outcome.volume_asset, outcome.volume_quote
+        )
+        .yellow(),
+        format!("{} asset / {} quote", outcome.reserve_x, outcome.reserve_y).yellow(),
+        outcome.last_price.to_string().yellow(),
+        "Results (quote):".yellow().bold(),
+        outcome.deposit.to_string().yellow(),
+        outcome.value.to_string().yellow(),
+        outcome.hold.to_string().yellow(),
+        outcome.fees.to_string().green(),
+        outcome.divergence.to_string().red(),
+        signed(outcome.net),
+        format!("{:.2}%", outcome.lp_return * 100.0).bold().yellow(),
+    );
+    println!("{}", outcome_msg.on_black());
+}
+
+fn print_summary(summary: &Summary, steps: Option<usize>) {
+    let percent = |value: f64| format!("{:.2}%", value * 100.0);
+    let summary_msg = format!(
+        "{} {} {} {}
+        - mean lp return: {}
+        - median lp return: {}
+        - 5th percentile lp return: {}
+        - mean fees: {}
+        - mean divergence: {}
+        - mean net vs hold: {}
+        - mean swaps: {}",
+        "Backtest over".yellow().bold(),
+        summary.paths.to_string().bold().magenta(),
+        "paths of".yellow().bold(),
+        format!("{} steps", steps.unwrap_or_default())
+            .bold()
+            .magenta(),
+        percent(summary.mean_return).yellow(),
+        percent(summary.median_return).yellow(),
+        percent(summary.tail_return).yellow(),
+        percent(summary.fee_capture).green(),
+        percent(summary.mean_divergence).red(),
+        percent(summary.mean_excess).bold().yellow(),
+        format!("{:.1}", summary.mean_swaps).yellow(),
+    );
+    println!("{}", summary_msg.on_black());
+}
+
+fn signed(value: f64) -> colored::ColoredString {
+    if value < 0.0 {
+        value.to_string().red()
+    } else {
+        format!("+{}", value).green()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_prices_from_columns() {
+        let candles = "start,open,high,low,close,spot_close,volume,quote_volume,swaps\n\
+            2023-01-01 00:00,1,2,1,1.5,1.5,1,1,1\n\
+            2023-01-01 01:00,1.5,2,1,1.8,1.8,1,1,1\n";
+        assert_eq!(read_prices(candles).unwrap(), vec![1.5, 1.8]);
+        assert_eq!(read_prices("1,100\n2,101\n").unwrap(), vec![100.0, 101.0]);
+        assert!(read_prices("time,price\n1,100\n2,abc\n").is_err());
+    }
+
+    #[test]
+    fn spaces_candles_by_their_start() {
+        let candles = "start,open,high,low,close,spot_close,volume,quote_volume,swaps\n\
+            2023-01-01 00:00,1,2,1,1.5,1.5,1,1,1\n\
+            2023-01-01 01:00,1.5,2,1,1.8,1.8,1,1,1\n\
+            2023-01-01 04:00,1.8,2,1,1.2,1.2,1,1,1\n";
+        assert_eq!(
+            read_candles(candles).unwrap(),
+            (vec![1.5, 1.8, 1.8, 1.8, 1.2], Some(3_600))
+        );
+        assert_eq!(
+            read_candles("start,close\n0,1\n86400,2\n").unwrap(),
+            (vec![1.0, 2.0], Some(86_400))
+        );
+        assert_eq!(
+            read_candles("time,price\n1,100\n2,101\n").unwrap(),
+            (vec![100.0, 101.0], None)
+        );
+        assert!(read_candles("start,close\n0,1\n3600,2\n5400,3\n").is_ok());
+        assert!(read_candles("start,close\n0,1\n3600,2\n9000,3\n").is_err());
+        assert!(read_candles("start,close\n3600,1\n0,2\n").is_err());
+    }
+}
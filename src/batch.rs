@@ -0,0 +1,511 @@
+use super::actions::{
+    allocate::AllocateArgs, approve, change_parameters::ChangeParametersArgs, claim::ClaimArgs,
+    create_pair::CreatePairArgs, create_pool::CreatePoolArgs, deallocate::DeallocateArgs,
+    swap::SwapArgs,
+};
+use super::events::Pair;
+use super::tokens::IERC20Permit;
+use super::utils::{signer_client, wad_to_units, Client, PoolId};
+use super::{compat, tx, BatchArgs, Config};
+use crate::bindings::i_portfolio::{IPortfolio, IPortfolioCalls, IPORTFOLIO_ABI};
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{
+    abi::AbiEncode,
+    prelude::*,
+    utils::{format_units, parse_ether},
+};
+use figment::{
+    providers::{Format, Json, Toml},
+    Figment,
+};
+use inquire::Confirm;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+
+/// A single action of a batch plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    /// Quoted against the pool before the batch, so it can't follow a swap or deallocate of the same pool.
+    Swap {
+        pool_id: u64,
+        #[serde(flatten)]
+        args: SwapArgs,
+    },
+    Allocate {
+        pool_id: u64,
+        #[serde(flatten)]
+        args: AllocateArgs,
+    },
+    Deallocate {
+        pool_id: u64,
+        #[serde(flatten)]
+        args: DeallocateArgs,
+    },
+    Claim {
+        #[serde(flatten)]
+        args: ClaimArgs,
+    },
+    CreatePair {
+        #[serde(flatten)]
+        args: CreatePairArgs,
+    },
+    CreatePool {
+        #[serde(flatten)]
+        args: CreatePoolArgs,
+    },
+    ChangeParameters {
+        pool_id: u64,
+        #[serde(flatten)]
+        args: ChangeParametersArgs,
+    },
+}
+
+/// List of actions executed atomically through `multicall`.
+///
+/// ### Example
+/// ```toml
+/// [[steps]]
+/// action = "swap"
+/// pool_id = 1099511627777
+/// sell_asset = true
+/// amount = 1.5
+/// slippage = 0.01
+///
+/// [[steps]]
+/// action = "allocate"
+/// pool_id = 1099511627777
+/// liquidity = 10.0
+/// max_asset = 5.0
+/// max_quote = 10000.0
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    /// Loads a plan from a `.json` file, or from toml otherwise.
+    pub fn load(path: &str) -> Result<Self, figment::Error> {
+        let figment = if path.ends_with(".json") {
+            Figment::new().merge(Json::file(path))
+        } else {
+            Figment::new().merge(Toml::file(path))
+        };
+        figment.extract()
+    }
+}
+
+impl Step {
+    /// Name of the Portfolio function the step calls.
+    pub fn function(&self) -> &'static str {
+        match self {
+            Step::Swap { .. } => "swap",
+            Step::Allocate { .. } => "allocate",
+            Step::Deallocate { .. } => "deallocate",
+            Step::Claim { .. } => "claimFee",
+            Step::CreatePair { .. } => "createPair",
+            Step::CreatePool { .. } => "createPool",
+            Step::ChangeParameters { .. } => "changeParameters",
+        }
+    }
+
+    /// Builds the Portfolio call of the step for `account`, quoting swaps against the current pool state.
+    pub async fn to_call<M: Middleware + 'static>(
+        &self,
+        portfolio: &IPortfolio<M>,
+        account: Address,
+    ) -> Result<IPortfolioCalls, anyhow::Error> {
+        if let Some(call) = self.to_offline_call(account) {
+            return call;
+        }
+
+        let call = match self {
+            Step::Swap { pool_id, args } => {
+                IPortfolioCalls::Swap(crate::bindings::i_portfolio::SwapCall {
+                    args: args.quote_order(portfolio, *pool_id, account).await?,
+                })
+            }
+            Step::CreatePool { args } => {
+                IPortfolioCalls::CreatePool(args.to_call(portfolio.client()).await?)
+            }
+            _ => unreachable!("only swaps and pool creations read the chain"),
+        };
+
+        Ok(call)
+    }
+
+    /// Builds the Portfolio call of the step without reading the chain.
+    /// `None` for swaps and pool creations, which are quoted against the chain state.
+    pub fn to_offline_call(
+        &self,
+        account: Address,
+    ) -> Option<Result<IPortfolioCalls, anyhow::Error>> {
+        let call = match self {
+            Step::Swap { .. } | Step::CreatePool { .. } => return None,
+            Step::Allocate { pool_id, args } => args
+                .to_call(*pool_id, account)
+                .map(IPortfolioCalls::Allocate),
+            Step::Deallocate { pool_id, args } => {
+                args.to_call(*pool_id).map(IPortfolioCalls::Deallocate)
+            }
+            Step::Claim { args } => args.to_call().map(IPortfolioCalls::ClaimFee),
+            Step::CreatePair { args } => args.to_call().map(IPortfolioCalls::CreatePair),
+            Step::ChangeParameters { pool_id, args } => args
+                .to_call(*pool_id)
+                .map(IPortfolioCalls::ChangeParameters),
+        };
+
+        Some(call)
+    }
+
+    /// Pool the step trades or provides liquidity in, `0` being the pool created last in the batch.
+    fn pool_id(&self) -> Option<u64> {
+        match self {
+            Step::Swap { pool_id, .. }
+            | Step::Allocate { pool_id, .. }
+            | Step::Deallocate { pool_id, .. } => Some(*pool_id),
+            _ => None,
+        }
+    }
+
+    /// Upper bound of the tokens the step can pull from the signer, in units of each token,
+    /// given the `pair` of the step's pool.
+    fn max_debits(&self, pair: Option<Pair>) -> Result<Vec<(Address, U256)>, anyhow::Error> {
+        let Some(pair) = pair else {
+            return Ok(vec![]);
+        };
+        let debits = match self {
+            Step::Swap { args, .. } => {
+                let (token_in, decimals) = if args.sell_asset {
+                    (pair.asset, pair.asset_decimals)
+                } else {
+                    (pair.quote, pair.quote_decimals)
+                };
+                vec![(token_in, wad_to_units(parse_ether(args.amount)?, decimals))]
+            }
+            Step::Allocate { args, .. } => vec![
+                (
+                    pair.asset,
+                    wad_to_units(parse_ether(args.max_asset)?, pair.asset_decimals),
+                ),
+                (
+                    pair.quote,
+                    wad_to_units(parse_ether(args.max_quote)?, pair.quote_decimals),
+                ),
+            ],
+            _ => vec![],
+        };
+
+        Ok(debits)
+    }
+
+    /// Formats the simulated return data of the step, with amounts in units of the `pair` of its pool.
+    fn format_result(&self, result: &Bytes, pair: Option<Pair>) -> Result<String, anyhow::Error> {
+        let tokens = IPORTFOLIO_ABI
+            .function(self.function())?
+            .decode_output(result)?;
+        let pair = pair.unwrap_or_default();
+        let amount = |i: usize, decimals: u8| -> String {
+            tokens
+                .get(i)
+                .cloned()
+                .and_then(|token| token.into_uint())
+                .and_then(|amount| format_units(amount, decimals as u32).ok())
+                .unwrap_or_default()
+        };
+        let id = || -> String {
+            tokens
+                .first()
+                .cloned()
+                .and_then(|token| token.into_uint())
+                .map(|id| id.to_string())
+                .unwrap_or_default()
+        };
+
+        let msg = match self {
+            Step::Swap { args, .. } => {
+                let (input_decimals, output_decimals) = if args.sell_asset {
+                    (pair.asset_decimals, pair.quote_decimals)
+                } else {
+                    (pair.quote_decimals, pair.asset_decimals)
+                };
+                format!(
+                    "input: {} output: {}",
+                    amount(1, input_decimals),
+                    amount(2, output_decimals)
+                )
+            }
+            Step::Allocate { .. } => format!(
+                "pays asset: {} quote: {}",
+                amount(0, pair.asset_decimals),
+                amount(1, pair.quote_decimals)
+            ),
+            Step::Deallocate { .. } => format!(
+                "receives asset: {} quote: {}",
+                amount(0, pair.asset_decimals),
+                amount(1, pair.quote_decimals)
+            ),
+            Step::Claim { .. } => "claims fees".to_string(),
+            Step::CreatePair { .. } => format!("creates pair: {}", id()),
+            Step::CreatePool { .. } => format!("creates pool: {}", id()),
+            Step::ChangeParameters { .. } => "changes fees".to_string(),
+        };
+
+        Ok(msg)
+    }
+}
+
+/// Refuses a swap on a pool an earlier swap or deallocate of the batch moved, as every swap is quoted against
+/// the state before the batch and its minimum output would be stale. Split such plans into several batches.
+fn check_swaps(steps: &[Step]) -> Result<(), anyhow::Error> {
+    let mut moved = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            Step::Swap { pool_id, .. } if moved.contains(pool_id) => {
+                return Err(anyhow::anyhow!(
+                    "Step #{} swaps in pool {}, which an earlier step of the batch moves, and would be quoted against the pool before it. Send it in another batch",
+                    i,
+                    pool_id
+                ))
+            }
+            Step::Swap { pool_id, .. } | Step::Deallocate { pool_id, .. } => moved.push(*pool_id),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Handles the "Batch" command.
+pub async fn main(cfg: &Config, args: &BatchArgs) -> Result<(), anyhow::Error> {
+    let plan = Plan::load(&args.plan).context("batch.rs: Failed to load plan")?;
+    let client = signer_client(cfg).await?;
+    execute(cfg, client, &plan.steps, args.yes, args.verbose).await?;
+    Ok(())
+}
+
+/// Simulates the steps as one `multicall`, prints the expected deltas of each step and sends it.
+/// Returns its receipt, none if it was declined or `confirmations` is 0.
+pub async fn execute(
+    cfg: &Config,
+    client: Arc<Client>,
+    steps: &[Step],
+    skip_confirm: bool,
+    verbose: bool,
+) -> Result<Option<TransactionReceipt>, anyhow::Error> {
+    let Some((pending, _)) = send(cfg, &client, steps, skip_confirm).await? else {
+        return Ok(None);
+    };
+    let receipt = tx::track(cfg, &client, pending, verbose).await?;
+
+    let success_msg = format!("{} {}", "Batch successful".bold().green(), "🤑");
+    println!("{}", success_msg.on_black());
+    Ok(receipt)
+}
+
+/// Simulates the steps as one `multicall` and broadcasts it with its nonce, none if it is declined.
+/// An error means nothing was broadcast.
+pub async fn send<'a>(
+    cfg: &Config,
+    client: &'a Arc<Client>,
+    steps: &[Step],
+    skip_confirm: bool,
+) -> Result<Option<(PendingTransaction<'a, Ws>, U256)>, anyhow::Error> {
+    if steps.is_empty() {
+        return Err(anyhow::anyhow!("Batch has no steps"));
+    }
+
+    check_swaps(steps)?;
+
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let compat = compat::detect(cfg, &portfolio).await?;
+    for step in steps {
+        compat.require(step.function())?;
+    }
+
+    let preview_msg = format!(
+        "\n{} {} {}",
+        "Previewing batch of".yellow().bold(),
+        steps.len().to_string().yellow().bold(),
+        "steps... please be patient\n".yellow().bold()
+    );
+    println!("{}", preview_msg.on_black());
+
+    // Portfolio settles every token once at the end of the multicall, so approvals cover the sum of all steps.
+    let mut debits = HashMap::<Address, U256>::new();
+    let mut pairs = Vec::with_capacity(steps.len());
+    let (mut created_pair, mut created_pool) = (None, None);
+    for step in steps {
+        match step {
+            Step::CreatePair { args } => {
+                let call = args.to_call()?;
+                created_pair = Some(new_pair(client, call.asset, call.quote).await?);
+            }
+            Step::CreatePool { args } if args.pair_id == 0 => created_pool = created_pair,
+            Step::CreatePool { args } => created_pool = Some(pair(&portfolio, args.pair_id).await?),
+            _ => {}
+        }
+        let pair = match (step, step.pool_id()) {
+            (Step::Allocate { .. }, Some(0)) => Some(created_pool.ok_or(anyhow::anyhow!(
+                "Pool id zero allocates to the pool created last, which the batch does not create"
+            ))?),
+            (_, Some(0)) => created_pool,
+            (_, Some(pool_id)) => Some(pool_pair(&portfolio, pool_id).await?),
+            (_, None) => None,
+        };
+        for (token, amount) in step.max_debits(pair)? {
+            *debits.entry(token).or_default() += amount;
+        }
+        pairs.push(pair);
+    }
+    for (token, amount) in debits {
+        approve::ensure_allowance(cfg, client, token, portfolio.address(), amount).await?;
+    }
+
+    let mut data = Vec::with_capacity(steps.len());
+    for step in steps {
+        data.push(Bytes::from(
+            step.to_call(&portfolio, client.address()).await?.encode(),
+        ));
+    }
+
+    let results: Vec<Bytes> = portfolio
+        .multicall(data.clone())
+        .call()
+        .await
+        .context("batch.rs: Batch simulation failed")?;
+
+    for (i, ((step, result), pair)) in steps.iter().zip(results.iter()).zip(pairs).enumerate() {
+        let step_msg = format!(
+            "   - {}{} {} {}",
+            "#".purple(),
+            i.to_string().bold().purple(),
+            step.function().bold().purple(),
+            step.format_result(result, pair)?.purple()
+        );
+        println!("{}", step_msg.on_black());
+    }
+    println!(
+        "{}",
+        format!("{} {}", "Batch simulation successful".bold().green(), "🤑").on_black()
+    );
+
+    if !skip_confirm && !Confirm::new("Send batch?").with_default(false).prompt()? {
+        println!("{}", "Batch not sent".yellow().on_black());
+        return Ok(None);
+    }
+
+    let call = portfolio.multicall(data);
+    let sent = tx::send_with_nonce(cfg, client, call.tx)
+        .await
+        .context("batch.rs: Failed to send batch")?;
+    Ok(Some(sent))
+}
+
+/// Fetches the tokens of the pool's pair.
+async fn pool_pair(portfolio: &IPortfolio<Client>, pool_id: u64) -> Result<Pair, anyhow::Error> {
+    pair(portfolio, PoolId(pool_id).pair_id()).await
+}
+
+/// Fetches the tokens of a pair, with their decimals.
+async fn pair(portfolio: &IPortfolio<Client>, pair_id: u32) -> Result<Pair, anyhow::Error> {
+    Ok(Pair::from(
+        portfolio
+            .pairs(pair_id)
+            .call()
+            .await
+            .context("batch.rs: Failed to get pair")?,
+    ))
+}
+
+/// Pair of tokens created by the batch, with their decimals read from the tokens.
+async fn new_pair(
+    client: &Arc<Client>,
+    asset: Address,
+    quote: Address,
+) -> Result<Pair, anyhow::Error> {
+    let decimals = |token: Address| async move {
+        IERC20Permit::new(token, client.clone())
+            .decimals()
+            .call()
+            .await
+            .context("batch.rs: Failed to get token decimals")
+    };
+    Ok(Pair {
+        asset,
+        asset_decimals: decimals(asset).await?,
+        quote,
+        quote_decimals: decimals(quote).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_plan() {
+        let plan: Plan = Figment::new()
+            .merge(Toml::string(
+                r#"
+                [[steps]]
+                action = "swap"
+                pool_id = 1099511627777
+                sell_asset = true
+                amount = 1.5
+
+                [[steps]]
+                action = "deallocate"
+                pool_id = 1099511627777
+                use_max = true
+                "#,
+            ))
+            .extract()
+            .unwrap();
+
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].function(), "swap");
+        match &plan.steps[1] {
+            Step::Deallocate { pool_id, args } => {
+                assert_eq!(*pool_id, 1099511627777);
+                assert!(args.use_max);
+            }
+            step => panic!("unexpected step {:?}", step),
+        }
+    }
+
+    #[test]
+    fn parses_json_pool_creation_step() {
+        let step: Step = serde_json::from_str(
+            r#"{"action": "create_pool", "strategy": "0x0000000000000000000000000000000000000001", "strike": 1000.0, "fee_bps": 30}"#,
+        )
+        .unwrap();
+
+        match &step {
+            Step::CreatePool { args } => {
+                assert_eq!(args.pair_id, 0);
+                assert_eq!(args.fee_bps, 30);
+            }
+            step => panic!("unexpected step {:?}", step),
+        }
+        assert_eq!(step.function(), "createPool");
+    }
+
+    #[test]
+    fn refuses_swaps_after_the_pool_moved() {
+        let swap = |pool_id| Step::Swap {
+            pool_id,
+            args: SwapArgs::default(),
+        };
+        let deallocate = |pool_id| Step::Deallocate {
+            pool_id,
+            args: DeallocateArgs::default(),
+        };
+
+        assert!(check_swaps(&[swap(1), swap(2), deallocate(1)]).is_ok());
+        assert!(check_swaps(&[swap(1), swap(2), swap(1)]).is_err());
+        assert!(check_swaps(&[deallocate(1), swap(1)]).is_err());
+    }
+}
@@ -0,0 +1,718 @@
+use super::{contract, loaded, method, shared_types::Order, PORTFOLIO};
+use ethers::{
+    abi::{Abi, AbiEncode},
+    contract::{ContractCall, EthCall, EthEvent, Event, Lazy},
+    prelude::*,
+};
+use std::sync::Arc;
+
+pub(super) const BUNDLED: &str = include_str!("../../abi/IPortfolio.json");
+
+pub static IPORTFOLIO_ABI: Lazy<Abi> = Lazy::new(|| loaded(&PORTFOLIO, BUNDLED));
+
+/// Getters the wrappers call by name, the other functions are encoded by their call structs.
+/// `simulateSwap` is left out, as versions without it are supported: commands check it through `compat` first.
+const GETTERS: &[&str] = &[
+    "pairs(uint24)",
+    "pools(uint64)",
+    "positions(address,uint64)",
+    "getAmountOut(uint64,bool,uint256,address)",
+    "getSpotPrice(uint64)",
+    "getReserves(uint64)",
+    "getLiquidityDeltas(uint64,int128)",
+    "getMaxLiquidity(uint64,uint256,uint256)",
+    "getNetBalance(address)",
+    "getPairId(address,address)",
+    "getPairNonce()",
+    "getPoolNonce(uint24)",
+    "protocolFees(address)",
+    "protocolFee()",
+    "WETH()",
+    "version()",
+];
+
+/// Signatures of the functions the loaded ABI must have.
+pub(super) fn functions() -> Vec<String> {
+    let calls = [
+        AllocateCall::abi_signature(),
+        DeallocateCall::abi_signature(),
+        SwapCall::abi_signature(),
+        ClaimFeeCall::abi_signature(),
+        CreatePairCall::abi_signature(),
+        CreatePoolCall::abi_signature(),
+        ChangeParametersCall::abi_signature(),
+        MulticallCall::abi_signature(),
+    ];
+    calls
+        .into_iter()
+        .map(|signature| signature.into_owned())
+        .chain(GETTERS.iter().map(|getter| getter.to_string()))
+        .collect()
+}
+
+/// Signatures of the events the loaded ABI must have.
+pub(super) fn events() -> Vec<String> {
+    [
+        SwapFilter::abi_signature(),
+        AllocateFilter::abi_signature(),
+        DeallocateFilter::abi_signature(),
+        CreatePairFilter::abi_signature(),
+        CreatePoolFilter::abi_signature(),
+        ChangeParametersFilter::abi_signature(),
+        ClaimFeesFilter::abi_signature(),
+    ]
+    .into_iter()
+    .map(|signature| signature.into_owned())
+    .collect()
+}
+
+contract!(IPortfolio, IPORTFOLIO_ABI);
+
+impl<M: Middleware> IPortfolio<M> {
+    pub fn allocate(
+        &self,
+        use_max: bool,
+        recipient: Address,
+        pool_id: u64,
+        delta_liquidity: u128,
+        max_delta_asset: u128,
+        max_delta_quote: u128,
+    ) -> ContractCall<M, (U256, U256)> {
+        method(
+            &self.0,
+            "allocate",
+            (
+                use_max,
+                recipient,
+                pool_id,
+                delta_liquidity,
+                max_delta_asset,
+                max_delta_quote,
+            ),
+        )
+    }
+
+    pub fn deallocate(
+        &self,
+        use_max: bool,
+        pool_id: u64,
+        delta_liquidity: u128,
+        min_delta_asset: u128,
+        min_delta_quote: u128,
+    ) -> ContractCall<M, (U256, U256)> {
+        method(
+            &self.0,
+            "deallocate",
+            (
+                use_max,
+                pool_id,
+                delta_liquidity,
+                min_delta_asset,
+                min_delta_quote,
+            ),
+        )
+    }
+
+    pub fn swap(&self, args: Order) -> ContractCall<M, (u64, U256, U256)> {
+        method(&self.0, "swap", (args,))
+    }
+
+    pub fn claim_fee(&self, token: Address, amount: U256) -> ContractCall<M, ()> {
+        method(&self.0, "claimFee", (token, amount))
+    }
+
+    pub fn create_pair(&self, asset: Address, quote: Address) -> ContractCall<M, u32> {
+        method(&self.0, "createPair", (asset, quote))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_pool(
+        &self,
+        pair_id: u32,
+        reserve_x_per_wad: U256,
+        reserve_y_per_wad: U256,
+        fee_basis_points: u16,
+        priority_fee_basis_points: u16,
+        controller: Address,
+        strategy: Address,
+        strategy_args: Bytes,
+    ) -> ContractCall<M, u64> {
+        method(
+            &self.0,
+            "createPool",
+            (
+                pair_id,
+                reserve_x_per_wad,
+                reserve_y_per_wad,
+                fee_basis_points,
+                priority_fee_basis_points,
+                controller,
+                strategy,
+                strategy_args,
+            ),
+        )
+    }
+
+    pub fn change_parameters(
+        &self,
+        pool_id: u64,
+        priority_fee_basis_points: u16,
+        fee_basis_points: u16,
+    ) -> ContractCall<M, ()> {
+        method(
+            &self.0,
+            "changeParameters",
+            (pool_id, priority_fee_basis_points, fee_basis_points),
+        )
+    }
+
+    pub fn multicall(&self, data: Vec<Bytes>) -> ContractCall<M, Vec<Bytes>> {
+        method(&self.0, "multicall", data)
+    }
+
+    pub fn pairs(&self, pair_id: u32) -> ContractCall<M, (Address, u8, Address, u8)> {
+        method(&self.0, "pairs", pair_id)
+    }
+
+    pub fn positions(&self, owner: Address, pool_id: u64) -> ContractCall<M, u128> {
+        method(&self.0, "positions", (owner, pool_id))
+    }
+
+    pub fn get_amount_out(
+        &self,
+        pool_id: u64,
+        sell_asset: bool,
+        amount_in: U256,
+        swapper: Address,
+    ) -> ContractCall<M, U256> {
+        method(
+            &self.0,
+            "getAmountOut",
+            (pool_id, sell_asset, amount_in, swapper),
+        )
+    }
+
+    pub fn get_spot_price(&self, pool_id: u64) -> ContractCall<M, U256> {
+        method(&self.0, "getSpotPrice", pool_id)
+    }
+
+    pub fn simulate_swap(
+        &self,
+        order: Order,
+        timestamp: U256,
+        swapper: Address,
+    ) -> ContractCall<M, (bool, I256, I256)> {
+        method(&self.0, "simulateSwap", (order, timestamp, swapper))
+    }
+
+    pub fn get_reserves(&self, pool_id: u64) -> ContractCall<M, (U256, U256)> {
+        method(&self.0, "getReserves", pool_id)
+    }
+
+    pub fn get_liquidity_deltas(
+        &self,
+        pool_id: u64,
+        delta_liquidity: i128,
+    ) -> ContractCall<M, (u128, u128)> {
+        method(&self.0, "getLiquidityDeltas", (pool_id, delta_liquidity))
+    }
+
+    pub fn get_max_liquidity(
+        &self,
+        pool_id: u64,
+        delta_asset: U256,
+        delta_quote: U256,
+    ) -> ContractCall<M, u128> {
+        method(
+            &self.0,
+            "getMaxLiquidity",
+            (pool_id, delta_asset, delta_quote),
+        )
+    }
+
+    pub fn get_net_balance(&self, token: Address) -> ContractCall<M, I256> {
+        method(&self.0, "getNetBalance", token)
+    }
+
+    pub fn get_pair_id(&self, asset: Address, quote: Address) -> ContractCall<M, u32> {
+        method(&self.0, "getPairId", (asset, quote))
+    }
+
+    pub fn get_pair_nonce(&self) -> ContractCall<M, u32> {
+        method(&self.0, "getPairNonce", ())
+    }
+
+    pub fn get_pool_nonce(&self, pair_nonce: u32) -> ContractCall<M, u32> {
+        method(&self.0, "getPoolNonce", pair_nonce)
+    }
+
+    pub fn protocol_fees(&self, token: Address) -> ContractCall<M, U256> {
+        method(&self.0, "protocolFees", token)
+    }
+
+    pub fn protocol_fee(&self) -> ContractCall<M, U256> {
+        method(&self.0, "protocolFee", ())
+    }
+
+    pub fn weth(&self) -> ContractCall<M, Address> {
+        method(&self.0, "WETH", ())
+    }
+
+    pub fn version(&self) -> ContractCall<M, String> {
+        method(&self.0, "version", ())
+    }
+}
+
+// =================== Calls ===================
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(
+    name = "allocate",
+    abi = "allocate(bool,address,uint64,uint128,uint128,uint128)"
+)]
+pub struct AllocateCall {
+    pub use_max: bool,
+    pub recipient: Address,
+    pub pool_id: u64,
+    pub delta_liquidity: u128,
+    pub max_delta_asset: u128,
+    pub max_delta_quote: u128,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(
+    name = "deallocate",
+    abi = "deallocate(bool,uint64,uint128,uint128,uint128)"
+)]
+pub struct DeallocateCall {
+    pub use_max: bool,
+    pub pool_id: u64,
+    pub delta_liquidity: u128,
+    pub min_delta_asset: u128,
+    pub min_delta_quote: u128,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(name = "swap", abi = "swap((uint128,uint128,bool,uint64,bool))")]
+pub struct SwapCall {
+    pub args: Order,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(name = "claimFee", abi = "claimFee(address,uint256)")]
+pub struct ClaimFeeCall {
+    pub token: Address,
+    pub amount: U256,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(name = "createPair", abi = "createPair(address,address)")]
+pub struct CreatePairCall {
+    pub asset: Address,
+    pub quote: Address,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(
+    name = "createPool",
+    abi = "createPool(uint24,uint256,uint256,uint16,uint16,address,address,bytes)"
+)]
+pub struct CreatePoolCall {
+    pub pair_id: u32,
+    pub reserve_x_per_wad: U256,
+    pub reserve_y_per_wad: U256,
+    pub fee_basis_points: u16,
+    pub priority_fee_basis_points: u16,
+    pub controller: Address,
+    pub strategy: Address,
+    pub strategy_args: Bytes,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(
+    name = "changeParameters",
+    abi = "changeParameters(uint64,uint16,uint16)"
+)]
+pub struct ChangeParametersCall {
+    pub pool_id: u64,
+    pub priority_fee_basis_points: u16,
+    pub fee_basis_points: u16,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthCall)]
+#[ethcall(name = "multicall", abi = "multicall(bytes[])")]
+pub struct MulticallCall {
+    pub data: Vec<Bytes>,
+}
+
+/// Portfolio calls the cli builds. Each call is encoded by its signature, which `init` checked the loaded ABI has.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IPortfolioCalls {
+    Allocate(AllocateCall),
+    Deallocate(DeallocateCall),
+    Swap(SwapCall),
+    ClaimFee(ClaimFeeCall),
+    CreatePair(CreatePairCall),
+    CreatePool(CreatePoolCall),
+    ChangeParameters(ChangeParametersCall),
+    Multicall(MulticallCall),
+}
+
+impl AbiEncode for IPortfolioCalls {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            IPortfolioCalls::Allocate(call) => call.encode(),
+            IPortfolioCalls::Deallocate(call) => call.encode(),
+            IPortfolioCalls::Swap(call) => call.encode(),
+            IPortfolioCalls::ClaimFee(call) => call.encode(),
+            IPortfolioCalls::CreatePair(call) => call.encode(),
+            IPortfolioCalls::CreatePool(call) => call.encode(),
+            IPortfolioCalls::ChangeParameters(call) => call.encode(),
+            IPortfolioCalls::Multicall(call) => call.encode(),
+        }
+    }
+}
+
+// =================== Events ===================
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "Swap",
+    abi = "Swap(uint64,uint256,address,uint256,address,uint256,uint256,int256)"
+)]
+pub struct SwapFilter {
+    #[ethevent(indexed)]
+    pub pool_id: u64,
+    pub price: U256,
+    #[ethevent(indexed)]
+    pub token_in: Address,
+    pub input: U256,
+    #[ethevent(indexed)]
+    pub token_out: Address,
+    pub output: U256,
+    pub fee_amount_dec: U256,
+    pub invariant_wad: I256,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "Allocate",
+    abi = "Allocate(uint64,address,address,uint256,uint256,uint256)"
+)]
+pub struct AllocateFilter {
+    #[ethevent(indexed)]
+    pub pool_id: u64,
+    #[ethevent(indexed)]
+    pub asset: Address,
+    #[ethevent(indexed)]
+    pub quote: Address,
+    pub delta_asset: U256,
+    pub delta_quote: U256,
+    pub delta_liquidity: U256,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "Deallocate",
+    abi = "Deallocate(uint64,address,address,uint256,uint256,uint256)"
+)]
+pub struct DeallocateFilter {
+    #[ethevent(indexed)]
+    pub pool_id: u64,
+    #[ethevent(indexed)]
+    pub asset: Address,
+    #[ethevent(indexed)]
+    pub quote: Address,
+    pub delta_asset: U256,
+    pub delta_quote: U256,
+    pub delta_liquidity: U256,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "CreatePair",
+    abi = "CreatePair(uint24,address,address,uint8,uint8)"
+)]
+pub struct CreatePairFilter {
+    #[ethevent(indexed)]
+    pub pair_id: u32,
+    #[ethevent(indexed)]
+    pub asset: Address,
+    #[ethevent(indexed)]
+    pub quote: Address,
+    pub decimals_asset: u8,
+    pub decimals_quote: u8,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "CreatePool",
+    abi = "CreatePool(uint64,address,address,uint256,uint256,uint16,uint16,address,address)"
+)]
+pub struct CreatePoolFilter {
+    #[ethevent(indexed)]
+    pub pool_id: u64,
+    #[ethevent(indexed)]
+    pub asset: Address,
+    #[ethevent(indexed)]
+    pub quote: Address,
+    pub reserve_x_per_wad: U256,
+    pub reserve_y_per_wad: U256,
+    pub fee_basis_points: u16,
+    pub priority_fee_basis_points: u16,
+    pub controller: Address,
+    pub strategy: Address,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(
+    name = "ChangeParameters",
+    abi = "ChangeParameters(uint64,uint16,uint16)"
+)]
+pub struct ChangeParametersFilter {
+    #[ethevent(indexed)]
+    pub pool_id: u64,
+    #[ethevent(indexed)]
+    pub priority_fee_basis_points: u16,
+    #[ethevent(indexed)]
+    pub fee_basis_points: u16,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthEvent)]
+#[ethevent(name = "ClaimFees", abi = "ClaimFees(address,uint256)")]
+pub struct ClaimFeesFilter {
+    #[ethevent(indexed)]
+    pub token: Address,
+    pub amount: U256,
+}
+
+/// Declares the event filters of `IPortfolio`.
+macro_rules! filters {
+    ($($filter:ident $method:ident)*) => {
+        impl<M: Middleware> IPortfolio<M> {
+            $(
+                pub fn $method(&self) -> Event<Arc<M>, M, $filter> {
+                    self.0.event()
+                }
+            )*
+        }
+    };
+}
+
+filters! {
+    SwapFilter swap_filter
+    AllocateFilter allocate_filter
+    DeallocateFilter deallocate_filter
+    CreatePairFilter create_pair_filter
+    CreatePoolFilter create_pool_filter
+    ChangeParametersFilter change_parameters_filter
+    ClaimFeesFilter claim_fees_filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generated::reference::{self, ReferenceCalls};
+
+    /// Reference bindings generated by abigen from the bundled ABI, as forge would.
+    mod generated {
+        ethers::contract::abigen!(Reference, "abi/IPortfolio.json");
+    }
+
+    #[test]
+    fn bundled_abi_has_every_binding() {
+        super::super::load(
+            "IPortfolio",
+            BUNDLED,
+            "missing-abi-dir",
+            &functions(),
+            &events(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn abi_without_simulate_swap_loads() {
+        let mut abi: serde_json::Value = serde_json::from_str(BUNDLED).unwrap();
+        if let Some(entries) = abi.get_mut("abi") {
+            abi = entries.take();
+        }
+        abi.as_array_mut()
+            .unwrap()
+            .retain(|entry| entry["name"] != "simulateSwap");
+
+        let abi = super::super::parse(&abi.to_string()).unwrap();
+        assert!(abi.function("simulateSwap").is_err());
+        super::super::check("IPortfolio", &abi, "the test ABI", &functions(), &events()).unwrap();
+    }
+
+    #[test]
+    fn calls_encode_like_abigen() {
+        let (token, strategy) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        let calls = [
+            (
+                IPortfolioCalls::Allocate(AllocateCall {
+                    use_max: false,
+                    recipient: token,
+                    pool_id: 1_099_511_627_777,
+                    delta_liquidity: 10,
+                    max_delta_asset: 20,
+                    max_delta_quote: 30,
+                }),
+                ReferenceCalls::Allocate(reference::AllocateCall {
+                    use_max: false,
+                    recipient: token,
+                    pool_id: 1_099_511_627_777,
+                    delta_liquidity: 10,
+                    max_delta_asset: 20,
+                    max_delta_quote: 30,
+                }),
+            ),
+            (
+                IPortfolioCalls::Deallocate(DeallocateCall {
+                    use_max: true,
+                    pool_id: 1,
+                    delta_liquidity: 2,
+                    min_delta_asset: 3,
+                    min_delta_quote: 4,
+                }),
+                ReferenceCalls::Deallocate(reference::DeallocateCall {
+                    use_max: true,
+                    pool_id: 1,
+                    delta_liquidity: 2,
+                    min_delta_asset: 3,
+                    min_delta_quote: 4,
+                }),
+            ),
+            (
+                IPortfolioCalls::Swap(SwapCall {
+                    args: Order {
+                        input: 5,
+                        output: 6,
+                        use_max: false,
+                        pool_id: 7,
+                        sell_asset: true,
+                    },
+                }),
+                ReferenceCalls::Swap(reference::SwapCall {
+                    args: reference::Order {
+                        input: 5,
+                        output: 6,
+                        use_max: false,
+                        pool_id: 7,
+                        sell_asset: true,
+                    },
+                }),
+            ),
+            (
+                IPortfolioCalls::ClaimFee(ClaimFeeCall {
+                    token,
+                    amount: U256::MAX,
+                }),
+                ReferenceCalls::ClaimFee(reference::ClaimFeeCall {
+                    token,
+                    amount: U256::MAX,
+                }),
+            ),
+            (
+                IPortfolioCalls::CreatePair(CreatePairCall {
+                    asset: token,
+                    quote: strategy,
+                }),
+                ReferenceCalls::CreatePair(reference::CreatePairCall {
+                    asset: token,
+                    quote: strategy,
+                }),
+            ),
+            (
+                IPortfolioCalls::CreatePool(CreatePoolCall {
+                    pair_id: 1,
+                    reserve_x_per_wad: U256::from(8),
+                    reserve_y_per_wad: U256::from(9),
+                    fee_basis_points: 30,
+                    priority_fee_basis_points: 10,
+                    controller: token,
+                    strategy,
+                    strategy_args: Bytes::from(vec![1, 2, 3]),
+                }),
+                ReferenceCalls::CreatePool(reference::CreatePoolCall {
+                    pair_id: 1,
+                    reserve_x_per_wad: U256::from(8),
+                    reserve_y_per_wad: U256::from(9),
+                    fee_basis_points: 30,
+                    priority_fee_basis_points: 10,
+                    controller: token,
+                    strategy,
+                    strategy_args: Bytes::from(vec![1, 2, 3]),
+                }),
+            ),
+            (
+                IPortfolioCalls::ChangeParameters(ChangeParametersCall {
+                    pool_id: 1,
+                    priority_fee_basis_points: 5,
+                    fee_basis_points: 30,
+                }),
+                ReferenceCalls::ChangeParameters(reference::ChangeParametersCall {
+                    pool_id: 1,
+                    priority_fee_basis_points: 5,
+                    fee_basis_points: 30,
+                }),
+            ),
+            (
+                IPortfolioCalls::Multicall(MulticallCall {
+                    data: vec![Bytes::from(vec![0xab]), Bytes::new()],
+                }),
+                ReferenceCalls::Multicall(reference::MulticallCall {
+                    data: vec![Bytes::from(vec![0xab]), Bytes::new()],
+                }),
+            ),
+        ];
+
+        for (call, expected) in calls {
+            assert_eq!(call.clone().encode(), expected.encode(), "{:?}", call);
+        }
+    }
+
+    #[test]
+    fn events_match_abigen() {
+        assert_eq!(SwapFilter::signature(), reference::SwapFilter::signature());
+        assert_eq!(
+            CreatePoolFilter::signature(),
+            reference::CreatePoolFilter::signature()
+        );
+        assert_eq!(
+            ChangeParametersFilter::signature(),
+            reference::ChangeParametersFilter::signature()
+        );
+
+        let log = reference::SwapFilter {
+            pool_id: 7,
+            price: U256::from(1_000),
+            token_in: Address::repeat_byte(1),
+            input: U256::from(2),
+            token_out: Address::repeat_byte(2),
+            output: U256::from(3),
+            fee_amount_dec: U256::from(4),
+            invariant_wad: I256::from(-5),
+        };
+        let raw = ethers::abi::RawLog {
+            topics: vec![
+                reference::SwapFilter::signature(),
+                H256::from_low_u64_be(7),
+                H256::from(log.token_in),
+                H256::from(log.token_out),
+            ],
+            data: ethers::abi::encode(&[
+                ethers::abi::Token::Uint(log.price),
+                ethers::abi::Token::Uint(log.input),
+                ethers::abi::Token::Uint(log.output),
+                ethers::abi::Token::Uint(log.fee_amount_dec),
+                ethers::abi::Token::Int(log.invariant_wad.into_raw()),
+            ]),
+        };
+        let decoded = <SwapFilter as ethers::contract::EthLogDecode>::decode_log(&raw).unwrap();
+        assert_eq!(decoded.pool_id, log.pool_id);
+        assert_eq!(decoded.token_out, log.token_out);
+        assert_eq!(decoded.invariant_wad, log.invariant_wad);
+    }
+}
@@ -0,0 +1,4 @@
+pub use super::i_portfolio::{
+    AllocateCall, ClaimFeeCall, CreatePairCall, CreatePoolCall, DeallocateCall, MulticallCall,
+    SwapCall,
+};
@@ -0,0 +1,26 @@
+use super::{contract, i_portfolio::IPORTFOLIO_ABI, method};
+use ethers::{
+    contract::{ContractCall, EthAbiType},
+    prelude::*,
+};
+
+contract!(IPortfolioStruct, IPORTFOLIO_ABI);
+
+/// State and configuration of a pool, as returned by `pools`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthAbiType)]
+pub struct PortfolioPool {
+    pub virtual_x: u128,
+    pub virtual_y: u128,
+    pub liquidity: u128,
+    pub last_timestamp: u32,
+    pub fee_basis_points: u16,
+    pub priority_fee_basis_points: u16,
+    pub controller: Address,
+    pub strategy: Address,
+}
+
+impl<M: Middleware> IPortfolioStruct<M> {
+    pub fn pools(&self, pool_id: u64) -> ContractCall<M, PortfolioPool> {
+        method(&self.0, "pools", pool_id)
+    }
+}
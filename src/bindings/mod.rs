@@ -0,0 +1,162 @@
+//! Contract bindings built at runtime from json ABIs, used instead of the forge generated `out/bindings`
+//! when those are missing or the `runtime-abi` feature is enabled, so the cli builds without foundry.
+//! The ABIs bundled from `abi/` are replaced by any `IPortfolio.json` or `NormalStrategy.json` in the
+//! configured `abi_dir`, e.g. to talk to another Portfolio version, once `init` checked they have every
+//! function and event the cli encodes.
+#![allow(dead_code)]
+pub mod i_portfolio;
+pub mod i_portfolio_actions;
+pub mod i_portfolio_struct;
+pub mod normal_strategy;
+pub mod shared_types;
+
+use alloy_json_abi::JsonAbi;
+use anyhow::{self, Context};
+use ethers::{
+    abi::{Abi, Detokenize, Tokenize},
+    contract::{Contract, ContractCall},
+    prelude::*,
+    utils::{id, keccak256},
+};
+use std::sync::OnceLock;
+
+/// ABIs loaded from the `abi_dir` by `init`, read once by the statics of the contracts.
+static PORTFOLIO: OnceLock<Abi> = OnceLock::new();
+static STRATEGY: OnceLock<Abi> = OnceLock::new();
+
+/// Loads the ABIs of the contracts from `abi_dir`, falling back to the bundled ones.
+/// Must run before the first contract is built, later calls keep the ABIs already in use.
+pub fn init(abi_dir: &str) -> Result<(), anyhow::Error> {
+    let portfolio = load(
+        "IPortfolio",
+        i_portfolio::BUNDLED,
+        abi_dir,
+        &i_portfolio::functions(),
+        &i_portfolio::events(),
+    )?;
+    let strategy = load(
+        "NormalStrategy",
+        normal_strategy::BUNDLED,
+        abi_dir,
+        &normal_strategy::functions(),
+        &[],
+    )?;
+    let _ = PORTFOLIO.set(portfolio);
+    let _ = STRATEGY.set(strategy);
+    Ok(())
+}
+
+/// ABI set by `init`, or the `bundled` one if it did not run, as in unit tests.
+fn loaded(cell: &OnceLock<Abi>, bundled: &str) -> Abi {
+    cell.get()
+        .cloned()
+        .unwrap_or_else(|| parse(bundled).expect("bundled ABIs are tested to parse"))
+}
+
+/// Loads the ABI of `name` from `abi_dir`, falling back to the `bundled` copy, and checks it has the
+/// `functions` and `events` the bindings encode, given by their canonical signatures.
+fn load(
+    name: &str,
+    bundled: &str,
+    abi_dir: &str,
+    functions: &[String],
+    events: &[String],
+) -> Result<Abi, anyhow::Error> {
+    let path = std::path::Path::new(abi_dir).join(format!("{}.json", name));
+    let (contents, source) = match std::fs::read_to_string(&path) {
+        Ok(contents) => (contents, format!("{:?}", path)),
+        Err(_) => (bundled.to_string(), "the bundled ABI".to_string()),
+    };
+    let abi = parse(&contents).context(format!("Invalid {} ABI in {}", name, source))?;
+    check(name, &abi, &source, functions, events)?;
+    Ok(abi)
+}
+
+/// Checks an ABI has the `functions` and `events` the bindings encode, given by their canonical signatures.
+fn check(
+    name: &str,
+    abi: &Abi,
+    source: &str,
+    functions: &[String],
+    events: &[String],
+) -> Result<(), anyhow::Error> {
+    for signature in functions {
+        if !abi
+            .functions()
+            .any(|function| function.short_signature() == id(signature))
+        {
+            return Err(anyhow::anyhow!(
+                "{} ABI in {} has no `{}`, which the cli calls",
+                name,
+                source,
+                signature
+            ));
+        }
+    }
+    for signature in events {
+        if !abi
+            .events()
+            .any(|event| event.signature() == H256::from(keccak256(signature)))
+        {
+            return Err(anyhow::anyhow!(
+                "{} ABI in {} has no `{}` event, which the cli decodes",
+                name,
+                source,
+                signature
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a plain ABI or a forge artifact with an `abi` field.
+/// Goes through alloy, which accepts the newer solc output ethers rejects, such as `internalType`s.
+fn parse(contents: &str) -> Result<Abi, anyhow::Error> {
+    let mut value: serde_json::Value = serde_json::from_str(contents)?;
+    if let Some(abi) = value.get_mut("abi") {
+        value = abi.take();
+    }
+    let abi: JsonAbi = serde_json::from_value(value)?;
+    Ok(serde_json::from_str(&serde_json::to_string(&abi)?)?)
+}
+
+/// Builds a call of the function `name`, which `init` checked the loaded ABI has with these parameters.
+fn method<M: Middleware, T: Tokenize, D: Detokenize>(
+    contract: &Contract<M>,
+    name: &str,
+    args: T,
+) -> ContractCall<M, D> {
+    contract
+        .method(name, args)
+        .expect("functions are checked when the ABI is loaded")
+}
+
+/// Declares a contract wrapper around an ethers `Contract` using a runtime loaded ABI.
+macro_rules! contract {
+    ($name:ident, $abi:ident) => {
+        #[derive(Clone, Debug)]
+        pub struct $name<M>(ethers::contract::Contract<M>);
+
+        impl<M> std::ops::Deref for $name<M> {
+            type Target = ethers::contract::Contract<M>;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl<M: ethers::providers::Middleware> $name<M> {
+            pub fn new(
+                address: impl Into<ethers::types::Address>,
+                client: std::sync::Arc<M>,
+            ) -> Self {
+                Self(ethers::contract::Contract::new(
+                    address.into(),
+                    $abi.clone(),
+                    client,
+                ))
+            }
+        }
+    };
+}
+pub(crate) use contract;
@@ -0,0 +1,77 @@
+use super::{contract, loaded, method, STRATEGY};
+use ethers::{
+    abi::Abi,
+    contract::{ContractCall, Lazy},
+    prelude::*,
+};
+
+pub(super) const BUNDLED: &str = include_str!("../../abi/NormalStrategy.json");
+
+pub static NORMALSTRATEGY_ABI: Lazy<Abi> = Lazy::new(|| loaded(&STRATEGY, BUNDLED));
+
+/// Signatures of the functions the loaded ABI must have.
+pub(super) fn functions() -> Vec<String> {
+    [
+        "configs(uint64)",
+        "getStrategyData(uint256,uint256,uint256,bool,uint256)",
+        "getSpotPrice(uint64)",
+        "portfolio()",
+    ]
+    .iter()
+    .map(|signature| signature.to_string())
+    .collect()
+}
+
+contract!(NormalStrategy, NORMALSTRATEGY_ABI);
+
+impl<M: Middleware> NormalStrategy<M> {
+    pub fn configs(&self, pool_id: u64) -> ContractCall<M, (u128, u32, u32, u32, bool)> {
+        method(&self.0, "configs", pool_id)
+    }
+
+    pub fn get_strategy_data(
+        &self,
+        strike_price_wad: U256,
+        volatility_basis_points: U256,
+        duration_seconds: U256,
+        is_perpetual: bool,
+        price_wad: U256,
+    ) -> ContractCall<M, (Bytes, U256, U256)> {
+        method(
+            &self.0,
+            "getStrategyData",
+            (
+                strike_price_wad,
+                volatility_basis_points,
+                duration_seconds,
+                is_perpetual,
+                price_wad,
+            ),
+        )
+    }
+
+    pub fn get_spot_price(&self, pool_id: u64) -> ContractCall<M, U256> {
+        method(&self.0, "getSpotPrice", pool_id)
+    }
+
+    pub fn portfolio(&self) -> ContractCall<M, Address> {
+        method(&self.0, "portfolio", ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_abi_has_every_binding() {
+        super::super::load(
+            "NormalStrategy",
+            BUNDLED,
+            "missing-abi-dir",
+            &functions(),
+            &[],
+        )
+        .unwrap();
+    }
+}
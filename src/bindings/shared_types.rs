@@ -0,0 +1,11 @@
+use ethers::{contract::EthAbiType, prelude::*};
+
+/// Swap order of Portfolio's `swap`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, EthAbiType)]
+pub struct Order {
+    pub input: u128,
+    pub output: u128,
+    pub use_max: bool,
+    pub pool_id: u64,
+    pub sell_asset: bool,
+}
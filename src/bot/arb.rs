@@ -0,0 +1,296 @@
+//! Arbitrage of a pool against a reference price.
+//! On every block, the swap moving the pool's price to the reference less the fee is sized with the strategy math,
+//! quoted by Portfolio, and sent as a one step batch when its profit at the reference price, net of gas, clears `min_profit`.
+use crate::actions::{approve, swap::SwapArgs};
+use crate::backtest::read_prices;
+use crate::batch::{self, Step};
+use crate::bindings::i_portfolio::IPortfolio;
+use crate::greeks::Risk;
+use crate::info::PoolState;
+use crate::sim::{self, Pool};
+use crate::utils::{round_wad, signer_client, Client, Rounding};
+use crate::{compat, gas, ArbArgs, Config};
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, parse_ether},
+};
+use serde_json::Value;
+use std::{collections::VecDeque, ops::ControlFlow, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+/// How long the http reference is waited for, so a hanging endpoint only skips a block.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Source of the reference price, from the `arb` config.
+pub enum Reference {
+    /// A json endpoint, with the dotted path of the price in its response.
+    Http {
+        client: reqwest::Client,
+        url: String,
+        path: String,
+    },
+    /// Prices replayed one per block.
+    Csv(VecDeque<f64>),
+    /// The spot price of another pool.
+    Pool(u64),
+}
+
+impl Reference {
+    pub fn from_config(cfg: &Config) -> Result<Self, anyhow::Error> {
+        match cfg.arb.source.as_str() {
+            "http" if cfg.arb.url.is_empty() => {
+                Err(anyhow::anyhow!("The http reference needs an arb url"))
+            }
+            "http" => Ok(Self::Http {
+                client: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+                url: cfg.arb.url.clone(),
+                path: cfg.arb.price_path.clone(),
+            }),
+            "csv" => Ok(Self::Csv(
+                read_prices(&std::fs::read_to_string(&cfg.arb.csv)?)?.into(),
+            )),
+            "pool" if cfg.arb.reference_pool_id == 0 => Err(anyhow::anyhow!(
+                "The pool reference needs an arb reference_pool_id"
+            )),
+            "pool" => Ok(Self::Pool(cfg.arb.reference_pool_id)),
+            source => Err(anyhow::anyhow!(
+                "Unknown reference source {}, expected http, csv or pool",
+                source
+            )),
+        }
+    }
+
+    /// Next reference price, none once a csv is replayed.
+    pub async fn next<M: Middleware + 'static>(
+        &mut self,
+        portfolio: &IPortfolio<M>,
+    ) -> Result<Option<f64>, anyhow::Error> {
+        match self {
+            Self::Http { client, url, path } => {
+                let body = client
+                    .get(url.as_str())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                Ok(Some(json_price(&serde_json::from_str(&body)?, path)?))
+            }
+            Self::Csv(prices) => Ok(prices.pop_front()),
+            Self::Pool(pool_id) => {
+                let price = portfolio
+                    .get_spot_price(*pool_id)
+                    .call()
+                    .await
+                    .map_err(|err| {
+                        anyhow::anyhow!("arb.rs: Failed to get reference price: {}", err)
+                    })?;
+                Ok(Some(format_ether(price).parse()?))
+            }
+        }
+    }
+}
+
+/// Reads a positive price at a dotted path of a json value, such as `data.0.price`, from a number or a numeric string.
+pub fn json_price(value: &Value, path: &str) -> Result<f64, anyhow::Error> {
+    let mut value = value;
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        value = match key.parse::<usize>() {
+            Ok(index) if value.is_array() => &value[index],
+            _ => &value[key],
+        };
+    }
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|price| *price > 0.0)
+    .ok_or(anyhow::anyhow!("No price at {} in the response", path))
+}
+
+/// Profit of a swap with its quoted `output`, valued at the reference price in quote tokens.
+pub fn profit(swap: &sim::Swap, output: f64, reference: f64) -> f64 {
+    if swap.sell_asset {
+        output - swap.input * reference
+    } else {
+        output * reference - swap.input
+    }
+}
+
+/// Handles `bot arb`, checking the pool against the reference on every block.
+pub async fn main(cfg: &Config, args: &ArbArgs) -> Result<(), anyhow::Error> {
+    if !args.dry_run {
+        approve::require_policy(cfg, "bot arb")?;
+    }
+    let client = signer_client(cfg).await?;
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    compat::detect(cfg, &portfolio).await?.require_all(&[
+        "getAmountOut",
+        "getSpotPrice",
+        "swap",
+    ])?;
+    let reference = Mutex::new(Reference::from_config(cfg)?);
+
+    let start_msg = format!(
+        "{} {} {} {} {} {}",
+        "Arbitraging pool".yellow().bold(),
+        args.pool_id.to_string().bold().magenta(),
+        "against the".yellow(),
+        cfg.arb.source.bold().yellow(),
+        "reference, min profit".yellow(),
+        cfg.arb.min_profit.to_string().bold().yellow()
+    );
+    println!("{}", start_msg.on_black());
+
+    let (client, portfolio, reference) = (&client, &portfolio, &reference);
+    super::on_blocks(client, args.once, "Arbitrage failed:", || async move {
+        let Some(price) = reference.lock().await.next(portfolio).await? else {
+            println!("{}", "Replayed every reference price".green().on_black());
+            return Ok(ControlFlow::Break(()));
+        };
+        check(cfg, client, portfolio, args, price).await?;
+        Ok(ControlFlow::Continue(()))
+    })
+    .await
+}
+
+/// Sizes, quotes and sends the arbitrage of the pool against `reference` if it is worth it.
+async fn check(
+    cfg: &Config,
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    args: &ArbArgs,
+    reference: f64,
+) -> Result<(), anyhow::Error> {
+    if !cfg.swap.in_band(reference) {
+        let band_msg = format!(
+            "   - {} {} {}",
+            "reference".purple(),
+            reference.to_string().bold().purple(),
+            "is outside the swap price band, skipping".purple()
+        );
+        println!("{}", band_msg.on_black());
+        return Ok(());
+    }
+
+    let state = PoolState::fetch(cfg, client.clone(), args.pool_id).await?;
+    let risk = Risk::fetch(client.clone(), &state).await?;
+    let wad = |amount: u128| {
+        format_ether(U256::from(amount))
+            .parse::<f64>()
+            .unwrap_or_default()
+    };
+    let pool = Pool {
+        config: risk.config,
+        is_perpetual: false,
+        fee: state.pool.fee_basis_points as f64 / 10_000.0,
+        liquidity: wad(state.pool.liquidity),
+        reserve_x: wad(state.pool.virtual_x),
+        reserve_y: wad(state.pool.virtual_y),
+        fees_asset: 0.0,
+        fees_quote: 0.0,
+    };
+
+    let Some(swap) = pool.arbitrage(reference).filter(|swap| swap.input >= 1e-12) else {
+        let inline_msg = format!(
+            "   - {} {} {} {} {}",
+            format!("#{}", state.block).purple(),
+            "pool".purple(),
+            risk.price.to_string().bold().purple(),
+            "is within the fee of".purple(),
+            reference.to_string().bold().purple()
+        );
+        println!("{}", inline_msg.on_black());
+        return Ok(());
+    };
+
+    let input = round_wad(swap.input, Rounding::Floor);
+    let amount_out = portfolio
+        .get_amount_out(
+            args.pool_id,
+            swap.sell_asset,
+            parse_ether(input)?,
+            client.address(),
+        )
+        .call()
+        .await
+        .map_err(|err| anyhow::anyhow!("arb.rs: Failed to get amount out: {}", err))?;
+    let output: f64 = format_ether(amount_out).parse()?;
+    let profit = profit(&swap, output, reference);
+    let gas_cost = gas::cost_in_quote(cfg, client, cfg.arb.swap_gas, cfg.arb.native_price).await?;
+
+    let opportunity_msg = format!(
+        "   - {} {} {} {} {} {} {} {} {} {}",
+        format!("#{}", state.block).purple(),
+        "pool".purple(),
+        risk.price.to_string().bold().purple(),
+        "reference".purple(),
+        reference.to_string().bold().purple(),
+        if swap.sell_asset {
+            format!("sell {} asset", input).red()
+        } else {
+            format!("buy with {} quote", input).green()
+        },
+        "profit".purple(),
+        profit.to_string().bold().purple(),
+        "gas".purple(),
+        gas_cost.to_string().bold().purple(),
+    );
+    println!("{}", opportunity_msg.on_black());
+
+    if profit - gas_cost < cfg.arb.min_profit {
+        println!(
+            "{}",
+            "   - below the min profit, skipping".purple().on_black()
+        );
+        return Ok(());
+    }
+    if args.dry_run {
+        println!("{}", "   - dry run, not sent".yellow().on_black());
+        return Ok(());
+    }
+
+    let step = Step::Swap {
+        pool_id: args.pool_id,
+        args: SwapArgs {
+            sell_asset: swap.sell_asset,
+            amount: input,
+            price: reference,
+            slippage: cfg.arb.slippage,
+        },
+    };
+    batch::execute(cfg, client.clone(), &[step], true, false).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::greeks::StrategyConfig;
+
+    #[test]
+    fn reads_json_prices() {
+        let response: Value =
+            serde_json::from_str(r#"{"data": [{"price": "1850.5"}], "eth": 1900}"#).unwrap();
+        assert_eq!(json_price(&response, "data.0.price").unwrap(), 1850.5);
+        assert_eq!(json_price(&response, "eth").unwrap(), 1900.0);
+        assert!(json_price(&response, "data.1.price").is_err());
+    }
+
+    #[test]
+    fn arbitrage_is_profitable_at_the_reference() {
+        let config = StrategyConfig {
+            strike: 1000.0,
+            volatility: 0.1,
+            tau: 1.0,
+        };
+        let pool = Pool::new(config, false, 0.003, 10.0, 1000.0);
+        for reference in [1100.0, 900.0] {
+            let swap = pool.arbitrage(reference).unwrap();
+            assert_eq!(swap.sell_asset, reference < 1000.0);
+            assert!(profit(&swap, swap.output, reference) > 0.0);
+        }
+    }
+}
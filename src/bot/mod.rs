@@ -0,0 +1,48 @@
+//! Long-running bots trading and providing liquidity on Portfolio pools with the signer's funds.
+mod arb;
+mod rebalance;
+
+use super::utils::Client;
+use super::{BotArgs, BotCommands, Config};
+use colored::Colorize;
+use ethers::{prelude::*, providers::StreamExt};
+use std::{future::Future, ops::ControlFlow};
+
+/// Handles the "Bot" command.
+pub async fn main(cfg: &Config, args: &BotArgs) -> Result<(), anyhow::Error> {
+    match &args.command {
+        BotCommands::Arb(args) => arb::main(cfg, args).await,
+        BotCommands::Rebalance(args) => rebalance::main(cfg, args).await,
+    }
+}
+
+/// Runs `tick` on the current block and on every new one, until it breaks, or only once with `once`.
+/// Failures are printed after `failed` and retried on the next block, unless running once.
+pub async fn on_blocks<F, Fut>(
+    client: &Client,
+    once: bool,
+    failed: &str,
+    mut tick: F,
+) -> Result<(), anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<ControlFlow<()>, anyhow::Error>>,
+{
+    let mut blocks = client.subscribe_blocks().await?;
+    loop {
+        match tick().await {
+            Ok(ControlFlow::Break(())) => return Ok(()),
+            Ok(ControlFlow::Continue(())) => {}
+            Err(err) if !once => eprintln!("{} {}", failed.red(), err),
+            Err(err) => return Err(err),
+        }
+        if once {
+            return Ok(());
+        }
+        if blocks.next().await.is_none() {
+            return Err(anyhow::anyhow!(
+                "bot/mod.rs: Subscription closed by the node"
+            ));
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Rebalancing of a liquidity position out of expiring or off-center NormalStrategy pools.
+//! When the position's pool breaks the policy, the position is deallocated and allocated into the best-centered pool
+//! of the same pair that meets the policy, or into a new pool struck at the spot price, in one guarded multicall.
+use crate::actions::{
+    allocate::AllocateArgs, approve, create_pool::CreatePoolArgs, deallocate::DeallocateArgs,
+};
+use crate::batch::{self, Step};
+use crate::bindings::{i_portfolio::IPortfolio, normal_strategy::NormalStrategy};
+use crate::events::uint;
+use crate::greeks::{Risk, StrategyConfig};
+use crate::info::PoolState;
+use crate::list::created_pools;
+use crate::tx::decode_log;
+use crate::utils::{round_wad, signer_client, Client, PoolId, Rounding};
+use crate::{compat, Config, RebalanceArgs};
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, parse_ether},
+};
+use figment::{
+    providers::{Format, Json, Toml},
+    Figment,
+};
+use serde::{Deserialize, Serialize};
+use std::{cell::Cell, ops::ControlFlow, sync::Arc};
+
+/// Rules a position's pool must meet, and the pool created when no pool of the pair does.
+///
+/// ### Example
+/// ```toml
+/// min_days_to_maturity = 7.0
+/// max_strike_distance = 0.2
+/// target_allocation = 1.0
+/// slippage = 0.01
+///
+/// [new_pool]
+/// strategy = "0x..."
+/// volatility_bps = 8000
+/// duration_days = 30
+/// fee_bps = 30
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    /// Moves the position when its pool matures in fewer days than this.
+    pub min_days_to_maturity: f64,
+    /// Moves the position when the spot price is further than this from the strike, as a fraction of the strike.
+    pub max_strike_distance: f64,
+    /// Share of the deallocated tokens allocated to the new pool.
+    pub target_allocation: f64,
+    /// Tolerated slippage of the deallocated and allocated amounts, as a fraction.
+    pub slippage: f64,
+    /// Pool created when none meets the policy, struck and priced at the spot price.
+    pub new_pool: CreatePoolArgs,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            min_days_to_maturity: 7.0,
+            max_strike_distance: 0.2,
+            target_allocation: 1.0,
+            slippage: 0.01,
+            new_pool: CreatePoolArgs::default(),
+        }
+    }
+}
+
+impl Policy {
+    /// Loads a policy from a `.json` file, or from toml otherwise.
+    pub fn load(path: &str) -> Result<Self, anyhow::Error> {
+        let figment = if path.ends_with(".json") {
+            Figment::new().merge(Json::file(path))
+        } else {
+            Figment::new().merge(Toml::file(path))
+        };
+        let policy: Self = figment.extract()?;
+        if !(0.0..=1.0).contains(&policy.target_allocation) || policy.target_allocation == 0.0 {
+            return Err(anyhow::anyhow!("Target allocation must be in (0, 1]"));
+        }
+        if !(0.0..1.0).contains(&policy.slippage) {
+            return Err(anyhow::anyhow!("Slippage must be in [0, 1)"));
+        }
+        Ok(policy)
+    }
+
+    /// Why a pool breaks the policy at `price`, if it does.
+    pub fn breach(&self, config: &StrategyConfig, price: f64) -> Option<String> {
+        let days = config.tau * 365.0;
+        let distance = (price / config.strike - 1.0).abs();
+        if days < self.min_days_to_maturity {
+            Some(format!("matures in {:.2} days", days))
+        } else if distance > self.max_strike_distance {
+            Some(format!("price is {:.2}% from the strike", distance * 100.0))
+        } else {
+            None
+        }
+    }
+
+    /// Best-centered pool meeting the policy, preferring later maturities between equally centered ones.
+    pub fn choose(&self, pools: &[(u64, StrategyConfig, f64)]) -> Option<u64> {
+        pools
+            .iter()
+            .filter(|(_, config, price)| self.breach(config, *price).is_none())
+            .min_by(|(_, a, a_price), (_, b, b_price)| {
+                let distance = |config: &StrategyConfig, price: f64| {
+                    ((price / config.strike - 1.0).abs() * 10_000.0).round()
+                };
+                distance(a, *a_price)
+                    .total_cmp(&distance(b, *b_price))
+                    .then(b.tau.total_cmp(&a.tau))
+            })
+            .map(|(pool_id, _, _)| *pool_id)
+    }
+}
+
+/// Handles `bot rebalance`, checking the position's pool on every block.
+pub async fn main(cfg: &Config, args: &RebalanceArgs) -> Result<(), anyhow::Error> {
+    let policy = Policy::load(&args.policy)?;
+    if !args.dry_run {
+        approve::require_policy(cfg, "bot rebalance")?;
+    }
+    let client = signer_client(cfg).await?;
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    compat::detect(cfg, &portfolio).await?.require_all(&[
+        "getLiquidityDeltas",
+        "positions",
+        "allocate",
+        "deallocate",
+    ])?;
+
+    let start_msg = format!(
+        "{} {} {} {}",
+        "Rebalancing the position in pool".yellow().bold(),
+        args.pool_id.to_string().bold().magenta(),
+        "with policy".yellow(),
+        args.policy.bold().yellow()
+    );
+    println!("{}", start_msg.on_black());
+
+    let pool_id = Cell::new(args.pool_id);
+    let (client, portfolio, policy, pool_id) = (&client, &portfolio, &policy, &pool_id);
+    super::on_blocks(client, args.once, "Rebalance failed:", || async move {
+        if let Some(next) =
+            check(cfg, client, portfolio, policy, pool_id.get(), args.dry_run).await?
+        {
+            pool_id.set(next);
+        }
+        Ok(ControlFlow::Continue(()))
+    })
+    .await
+}
+
+/// Moves the position out of `pool_id` if it breaks the policy, returning the pool it moved to.
+async fn check(
+    cfg: &Config,
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    policy: &Policy,
+    pool_id: u64,
+    dry_run: bool,
+) -> Result<Option<u64>, anyhow::Error> {
+    let liquidity = portfolio
+        .positions(client.address(), pool_id)
+        .call()
+        .await
+        .map_err(|err| anyhow::anyhow!("rebalance.rs: Failed to get position: {}", err))?;
+    if liquidity == 0 {
+        return Err(anyhow::anyhow!(
+            "The signer has no liquidity in pool {}",
+            pool_id
+        ));
+    }
+
+    let state = PoolState::fetch(cfg, client.clone(), pool_id).await?;
+    let risk = Risk::fetch(client.clone(), &state).await?;
+    let Some(reason) = policy.breach(&risk.config, risk.price) else {
+        let hold_msg = format!(
+            "   - {} {} {}",
+            format!("#{}", state.block).purple(),
+            "pool".purple(),
+            format!("{} meets the policy", pool_id).purple()
+        );
+        println!("{}", hold_msg.on_black());
+        return Ok(None);
+    };
+    let breach_msg = format!(
+        "   - {} {} {} {}",
+        format!("#{}", state.block).purple(),
+        "pool".purple(),
+        pool_id.to_string().bold().purple(),
+        reason.red()
+    );
+    println!("{}", breach_msg.on_black());
+
+    // Candidates are the other pools of the pair at the same block.
+    let pair_id = PoolId(pool_id).pair_id();
+    let mut pools = Vec::new();
+    for event in created_pools(cfg, client.clone()).await? {
+        if event.pool_id == pool_id || PoolId(event.pool_id).pair_id() != pair_id {
+            continue;
+        }
+        let candidate =
+            PoolState::fetch_at(cfg, client.clone(), event.pool_id, state.block).await?;
+        if let Ok(candidate_risk) = Risk::fetch(client.clone(), &candidate).await {
+            pools.push((event.pool_id, candidate_risk.config, candidate_risk.price));
+        }
+    }
+
+    let wad = |amount: U256| format_ether(amount).parse::<f64>().unwrap_or_default();
+    let (freed_asset, freed_quote) = portfolio
+        .get_liquidity_deltas(pool_id, -(liquidity as i128))
+        .call()
+        .await
+        .map_err(|err| {
+            anyhow::anyhow!("rebalance.rs: Failed to get deallocated amounts: {}", err)
+        })?;
+    let (freed_asset, freed_quote) = (wad(U256::from(freed_asset)), wad(U256::from(freed_quote)));
+
+    let mut steps = vec![Step::Deallocate {
+        pool_id,
+        args: DeallocateArgs {
+            liquidity: wad(U256::from(liquidity)),
+            min_asset: round_wad(freed_asset * (1.0 - policy.slippage), Rounding::Floor),
+            min_quote: round_wad(freed_quote * (1.0 - policy.slippage), Rounding::Floor),
+            use_max: false,
+        },
+    }];
+
+    // Reserves of one unit of liquidity in the target pool.
+    let (target, per_asset, per_quote) = match policy.choose(&pools) {
+        Some(target) => {
+            let (asset, quote) = portfolio
+                .get_liquidity_deltas(target, parse_ether(1)?.as_u128() as i128)
+                .call()
+                .await
+                .map_err(|err| {
+                    anyhow::anyhow!("rebalance.rs: Failed to get allocated amounts: {}", err)
+                })?;
+            (target, wad(U256::from(asset)), wad(U256::from(quote)))
+        }
+        None => {
+            let new_pool = CreatePoolArgs {
+                pair_id,
+                strike: (risk.price * 1e6).round() / 1e6,
+                price: (risk.price * 1e6).round() / 1e6,
+                ..policy.new_pool.clone()
+            };
+            new_pool.validate()?;
+            let strategy = new_pool.strategy.parse::<Address>()?;
+            let (_, asset, quote) = NormalStrategy::new(strategy, client.clone())
+                .get_strategy_data(
+                    parse_ether(new_pool.strike)?,
+                    U256::from(new_pool.volatility_bps),
+                    U256::from(new_pool.duration_days) * 24 * 60 * 60,
+                    new_pool.is_perpetual,
+                    parse_ether(new_pool.price)?,
+                )
+                .call()
+                .await
+                .map_err(|err| {
+                    anyhow::anyhow!("rebalance.rs: Failed to get strategy data: {}", err)
+                })?;
+            if cfg.confirmations == 0 && !dry_run {
+                return Err(anyhow::anyhow!(
+                    "Rebalancing into a new pool waits for the batch to read the id of the pool, set confirmations above 0"
+                ));
+            }
+            steps.push(Step::CreatePool { args: new_pool });
+            // Pool id zero allocates to the pool created last, the one created by the multicall.
+            (0, wad(asset), wad(quote))
+        }
+    };
+
+    let (asset, quote) = (
+        freed_asset * policy.target_allocation,
+        freed_quote * policy.target_allocation,
+    );
+    let new_liquidity = if per_asset > 0.0 && per_quote > 0.0 {
+        (asset / per_asset).min(quote / per_quote)
+    } else if per_asset > 0.0 {
+        asset / per_asset
+    } else {
+        quote / per_quote
+    };
+    let new_liquidity = round_wad(new_liquidity * (1.0 - policy.slippage), Rounding::Floor);
+    steps.push(Step::Allocate {
+        pool_id: target,
+        args: AllocateArgs {
+            liquidity: new_liquidity,
+            max_asset: round_wad(
+                new_liquidity * per_asset * (1.0 + policy.slippage),
+                Rounding::Ceil,
+            ),
+            max_quote: round_wad(
+                new_liquidity * per_quote * (1.0 + policy.slippage),
+                Rounding::Ceil,
+            ),
+            use_max: false,
+        },
+    });
+
+    let move_msg = format!(
+        "   - {} {} {} {} {}",
+        "moving".purple(),
+        format!("{} liquidity", wad(U256::from(liquidity)))
+            .bold()
+            .purple(),
+        "into".purple(),
+        if target == 0 {
+            "a new pool".to_string()
+        } else {
+            format!("pool {}", target)
+        }
+        .bold()
+        .purple(),
+        format!("as {} liquidity", new_liquidity).purple()
+    );
+    println!("{}", move_msg.on_black());
+
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&steps)?);
+        println!("{}", "   - dry run, not sent".yellow().on_black());
+        return Ok(None);
+    }
+    let receipt = batch::execute(cfg, client.clone(), &steps, true, false).await?;
+    if target != 0 {
+        return Ok(Some(target));
+    }
+    let created = receipt
+        .iter()
+        .flat_map(|receipt| &receipt.logs)
+        .filter_map(decode_log)
+        .find(|(name, _)| name == "CreatePool")
+        .and_then(|(_, params)| uint(&params, "poolId"))
+        .ok_or(anyhow::anyhow!(
+            "rebalance.rs: The batch has no CreatePool log"
+        ))?;
+    Ok(Some(created.as_u64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(strike: f64, days: f64) -> StrategyConfig {
+        StrategyConfig {
+            strike,
+            volatility: 0.8,
+            tau: days / 365.0,
+        }
+    }
+
+    #[test]
+    fn breaches_on_maturity_and_strike_distance() {
+        let policy = Policy::default();
+        assert!(policy.breach(&config(100.0, 30.0), 110.0).is_none());
+        assert!(policy
+            .breach(&config(100.0, 3.0), 100.0)
+            .unwrap()
+            .contains("matures"));
+        assert!(policy
+            .breach(&config(100.0, 30.0), 130.0)
+            .unwrap()
+            .contains("strike"));
+    }
+
+    #[test]
+    fn chooses_the_best_centered_pool_meeting_the_policy() {
+        let policy = Policy::default();
+        let pools = [
+            (1, config(100.0, 2.0), 120.0),
+            (2, config(110.0, 10.0), 120.0),
+            (3, config(120.0, 14.0), 120.0),
+            (4, config(120.0, 60.0), 120.0),
+        ];
+        assert_eq!(policy.choose(&pools), Some(4));
+        assert_eq!(policy.choose(&pools[..1]), None);
+    }
+}
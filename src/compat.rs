@@ -0,0 +1,230 @@
+//! Compatibility of the cli with the Portfolio version a network runs.
+//! Each supported `version()` maps to the ABI of that version, from which its capabilities are derived:
+//! the functions it has and the events it emits. Commands require the functions they call up front,
+//! and the events of the version's ABI are decoded with it.
+use super::utils::read_block;
+use super::Config;
+use crate::bindings::i_portfolio::{IPortfolio, IPORTFOLIO_ABI};
+use alloy_json_abi::JsonAbi;
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::prelude::*;
+use std::collections::BTreeSet;
+
+/// Versions the cli ships the ABI of. Other versions are supported by dropping their ABI at
+/// `{abi_dir}/{version}/IPortfolio.json`, keyed by version so the unversioned ABI the runtime loaded bindings read
+/// never vouches for a deployment.
+const SUPPORTED: &[(&str, &str)] = &[("v1.3.0-beta", include_str!("../abi/IPortfolio.json"))];
+
+/// Portfolio functions the cli encodes through the compiled bindings.
+const USED: &[&str] = &[
+    "allocate",
+    "deallocate",
+    "swap",
+    "claimFee",
+    "createPair",
+    "createPool",
+    "changeParameters",
+    "multicall",
+    "pairs",
+    "pools",
+    "getAmountOut",
+    "getSpotPrice",
+    "simulateSwap",
+];
+
+/// What a Portfolio version offers.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    /// Names of the functions of the contract.
+    pub functions: BTreeSet<String>,
+    /// Names of the events the contract emits.
+    pub events: BTreeSet<String>,
+}
+
+impl Capabilities {
+    pub fn from_abi(abi: &JsonAbi) -> Self {
+        Self {
+            functions: abi
+                .functions()
+                .map(|function| function.name.clone())
+                .collect(),
+            events: abi.events().map(|event| event.name.clone()).collect(),
+        }
+    }
+
+    pub fn has(&self, function: &str) -> bool {
+        self.functions.contains(function)
+    }
+
+    pub fn emits(&self, event: &str) -> bool {
+        self.events.contains(event)
+    }
+}
+
+/// A supported Portfolio version, with its ABI and capabilities.
+#[derive(Debug, Clone)]
+pub struct Compat {
+    pub version: String,
+    /// ABI of the version, which its events and calls are decoded with.
+    pub abi: JsonAbi,
+    pub capabilities: Capabilities,
+}
+
+impl Compat {
+    /// Fails with a clear message if the version lacks `function`.
+    pub fn require(&self, function: &str) -> Result<(), anyhow::Error> {
+        if !self.capabilities.has(function) {
+            return Err(anyhow::anyhow!(
+                "Portfolio {} has no `{}` function",
+                self.version,
+                function
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails with the first of `functions` the version lacks.
+    pub fn require_all(&self, functions: &[&str]) -> Result<(), anyhow::Error> {
+        functions
+            .iter()
+            .try_for_each(|function| self.require(function))
+    }
+
+    /// Fails if a function the cli calls is encoded differently by this version than by the compiled bindings.
+    fn check_bindings(&self, bindings: &JsonAbi) -> Result<(), anyhow::Error> {
+        for name in USED {
+            let Some(function) = self.abi.functions().find(|function| function.name == *name)
+            else {
+                continue;
+            };
+            let compiled = bindings
+                .functions()
+                .find(|compiled| compiled.name == *name)
+                .map(|compiled| compiled.selector());
+            if compiled != Some(function.selector()) {
+                return Err(anyhow::anyhow!(
+                    "Portfolio {} changed `{}`, which the cli was built for a different version of. Rebuild the bindings for it, or build with the `runtime-abi` feature and put its ABI in `abi_dir`",
+                    self.version,
+                    name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolves the ABI of `version`, preferring one dropped at `{abi_dir}/{version}/IPortfolio.json`.
+fn resolve(version: &str, abi_dir: &str) -> Result<Compat, anyhow::Error> {
+    let path = std::path::Path::new(abi_dir)
+        .join(version)
+        .join("IPortfolio.json");
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(_) => SUPPORTED
+            .iter()
+            .find(|(supported, _)| *supported == version)
+            .map(|(_, abi)| abi.to_string())
+            .ok_or(anyhow::anyhow!(
+                "Portfolio {} is not supported, supported versions are {}. Put its ABI at {:?} to use it anyway",
+                version,
+                SUPPORTED
+                    .iter()
+                    .map(|(supported, _)| *supported)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                path
+            ))?,
+    };
+
+    let mut value: serde_json::Value = serde_json::from_str(&contents)?;
+    if let Some(abi) = value.get_mut("abi") {
+        value = abi.take();
+    }
+    let abi: JsonAbi = serde_json::from_value(value)
+        .context(format!("compat.rs: Invalid ABI for Portfolio {}", version))?;
+
+    Ok(Compat {
+        version: version.to_string(),
+        capabilities: Capabilities::from_abi(&abi),
+        abi,
+    })
+}
+
+/// Reads the version of the configured Portfolio and checks the cli can talk to it.
+pub async fn detect<M: Middleware + 'static>(
+    cfg: &Config,
+    portfolio: &IPortfolio<M>,
+) -> Result<Compat, anyhow::Error> {
+    let block = read_block(cfg, &*portfolio.client()).await?;
+    let version = portfolio
+        .version()
+        .block(block)
+        .call()
+        .await
+        .context("compat.rs: Failed to get Portfolio version")?;
+    let compat = resolve(&version, &cfg.abi_dir)?;
+
+    let bindings: JsonAbi = serde_json::from_str(&serde_json::to_string(&*IPORTFOLIO_ABI)?)?;
+    compat.check_bindings(&bindings)?;
+
+    let version_msg = format!(
+        "{} {}",
+        "Portfolio version:".yellow(),
+        compat.version.bold().yellow()
+    );
+    // On stderr, so the json output of commands stays parseable.
+    eprintln!("{}", version_msg.on_black());
+    Ok(compat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_bundled_version() {
+        let compat = resolve("v1.3.0-beta", "missing-abi-dir").unwrap();
+
+        assert!(compat.capabilities.has("simulateSwap"));
+        assert!(compat.capabilities.emits("Swap"));
+        assert!(compat.require_all(&["swap", "getAmountOut"]).is_ok());
+        assert!(compat.require_all(&["swap", "notAFunction"]).is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_version() {
+        let err = resolve("v0.0.0", "missing-abi-dir").unwrap_err();
+
+        assert!(err.to_string().contains("not supported"), "{}", err);
+    }
+
+    #[test]
+    fn refuses_unknown_version_with_the_default_abi_dir() {
+        // The checkout ships `abi/IPortfolio.json` for the bindings, which must not vouch for any version.
+        let err = resolve("v0.0.0", &Config::default().abi_dir).unwrap_err();
+
+        assert!(err.to_string().contains("not supported"), "{}", err);
+        assert!(resolve("v1.3.0-beta", &Config::default().abi_dir).is_ok());
+    }
+
+    #[test]
+    fn prefers_abi_in_abi_dir() {
+        let dir = std::env::temp_dir().join(format!("compat-abi-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("v9.9.9")).unwrap();
+        std::fs::write(
+            dir.join("v9.9.9").join("IPortfolio.json"),
+            r#"[{"type": "function", "name": "swap", "stateMutability": "payable", "inputs": [], "outputs": []}]"#,
+        )
+        .unwrap();
+
+        let compat = resolve("v9.9.9", dir.to_str().unwrap());
+        let other = resolve("v9.9.8", dir.to_str().unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(other.is_err());
+        let compat = compat.unwrap();
+        assert!(compat.capabilities.has("swap"));
+        assert!(compat.require("getAmountOut").is_err());
+    }
+}
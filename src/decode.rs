@@ -0,0 +1,359 @@
+use super::abi::{self, Decoded};
+use super::events::Pair;
+use super::tokens::IERC20Permit;
+use super::utils::{read_block, PoolId};
+use super::{Config, DecodeArgs};
+use crate::bindings::i_portfolio::IPortfolio;
+use alloy_primitives::B256;
+use anyhow::{self, Context};
+use async_recursion::async_recursion;
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_units, hex},
+};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, sync::Arc};
+
+/// Handles the "Decode" command.
+/// Accepts raw calldata, a transaction hash, or the path to a json receipt.
+pub async fn main(cfg: &Config, args: &DecodeArgs) -> Result<(), anyhow::Error> {
+    let abis = abi::known_abis()?;
+    let provider = Provider::<Ws>::connect(&cfg.rpc_url)
+        .await
+        .ok()
+        .map(Arc::new);
+    let block = match (&provider, &cfg.block) {
+        (Some(provider), Some(_)) => Some(read_block(cfg, &**provider).await?),
+        _ => None,
+    };
+    let mut scaler = Scaler::new(cfg, provider.clone(), block)?;
+
+    let mut call = None;
+    let mut receipt = None;
+
+    match Input::parse(&args.input)? {
+        Input::Receipt(parsed) => receipt = Some(parsed),
+        Input::Hash(hash) => {
+            let provider = provider.clone().ok_or(anyhow::anyhow!(
+                "Decoding a transaction hash needs an rpc connection"
+            ))?;
+            let tx = provider
+                .get_transaction(hash)
+                .await?
+                .ok_or(anyhow::anyhow!("Transaction {:?} not found", hash))?;
+            call = Some(abi::decode_call(&abis, &tx.input)?);
+            receipt = provider.get_transaction_receipt(hash).await?;
+        }
+        Input::Calldata(bytes) => call = Some(abi::decode_call(&abis, &bytes)?),
+    }
+
+    if let Some(call) = &mut call {
+        scaler.scale_call(call).await;
+    }
+
+    let mut events = Vec::new();
+    if let Some(receipt) = &receipt {
+        for log in &receipt.logs {
+            let topics: Vec<B256> = log.topics.iter().map(|topic| B256::from(topic.0)).collect();
+            match abi::decode_log(&abis, &topics, &log.data) {
+                Some(mut event) => {
+                    scaler.scale(&mut event.fields, Some(log.address)).await;
+                    events.push((log.address, event));
+                }
+                None => events.push((
+                    log.address,
+                    Decoded {
+                        name: "Unknown".to_string(),
+                        fields: Map::from_iter([
+                            ("topics".to_string(), json!(log.topics)),
+                            ("data".to_string(), json!(log.data)),
+                        ]),
+                        calls: vec![],
+                    },
+                )),
+            }
+        }
+    }
+
+    if args.json {
+        let output = json!({
+            "call": call.as_ref().map(Decoded::to_json),
+            "events": events
+                .iter()
+                .map(|(address, event)| {
+                    let mut event = event.to_json();
+                    event["address"] = json!(format!("{:?}", address));
+                    event
+                })
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    if let Some(call) = &call {
+        println!("{}", "Call:".yellow().bold().on_black());
+        print_decoded(call, 1);
+    }
+    if !events.is_empty() {
+        println!("{}", "Events:".yellow().bold().on_black());
+        for (address, event) in &events {
+            let address_msg = format!("   {} {:?}", "from".purple(), address);
+            println!("{}", address_msg.on_black());
+            print_decoded(event, 1);
+        }
+    }
+
+    Ok(())
+}
+
+/// What the input of the "Decode" command was recognized as.
+#[derive(Debug, PartialEq)]
+enum Input {
+    /// Path to a json transaction receipt.
+    Receipt(TransactionReceipt),
+    /// 32 bytes of hex, read as a transaction hash.
+    Hash(H256),
+    /// Any other hex, read as calldata.
+    Calldata(Vec<u8>),
+}
+
+impl Input {
+    /// Files take precedence over hex, so a receipt can be named anything.
+    fn parse(input: &str) -> Result<Self, anyhow::Error> {
+        if std::path::Path::new(input).is_file() {
+            let contents = std::fs::read_to_string(input)?;
+            let receipt = serde_json::from_str::<TransactionReceipt>(&contents)
+                .context("decode.rs: Failed to parse receipt")?;
+            return Ok(Input::Receipt(receipt));
+        }
+
+        let bytes = hex::decode(input.trim_start_matches("0x"))
+            .context("decode.rs: Input is neither a file nor hex")?;
+        if bytes.len() == 32 {
+            Ok(Input::Hash(H256::from_slice(&bytes)))
+        } else {
+            Ok(Input::Calldata(bytes))
+        }
+    }
+}
+
+fn print_decoded(decoded: &Decoded, depth: usize) {
+    let indent = "   ".repeat(depth);
+    println!("{}{}", indent, decoded.name.bold().purple());
+    for (name, value) in &decoded.fields {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        println!("{}   - {}: {}", indent, name.purple(), value);
+    }
+    for call in &decoded.calls {
+        print_decoded(call, depth + 1);
+    }
+}
+
+/// Rewrites raw integer amounts as decimal amounts of the token they are denominated in.
+/// Tokens are inferred from the parameter names, with pairs and decimals fetched over rpc when connected.
+pub struct Scaler {
+    portfolio: Option<IPortfolio<Provider<Ws>>>,
+    provider: Option<Arc<Provider<Ws>>>,
+    /// Block the pairs and decimals are read at, the latest if unset.
+    block: Option<u64>,
+    pairs: HashMap<u64, (Address, Address)>,
+    decimals: HashMap<Address, u8>,
+}
+
+impl Scaler {
+    pub fn new(
+        cfg: &Config,
+        provider: Option<Arc<Provider<Ws>>>,
+        block: Option<u64>,
+    ) -> Result<Self, anyhow::Error> {
+        let portfolio = match &provider {
+            Some(provider) => Some(IPortfolio::new(
+                cfg.portfolio_address.parse::<Address>()?,
+                provider.clone(),
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            portfolio,
+            provider,
+            block,
+            pairs: HashMap::new(),
+            decimals: HashMap::new(),
+        })
+    }
+
+    #[async_recursion(?Send)]
+    async fn scale_call(&mut self, call: &mut Decoded) {
+        let token = call
+            .fields
+            .get("token")
+            .and_then(|token| token.as_str())
+            .and_then(|token| token.parse::<Address>().ok());
+        self.scale(&mut call.fields, token).await;
+        for nested in &mut call.calls {
+            self.scale_call(nested).await;
+        }
+    }
+
+    /// Scales the amounts of `fields`, falling back to `default_token` for plain `amount`s and `value`s.
+    #[async_recursion(?Send)]
+    pub async fn scale(&mut self, fields: &mut Map<String, Value>, default_token: Option<Address>) {
+        let address = |key: &str, fields: &Map<String, Value>| {
+            fields
+                .get(key)
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse::<Address>().ok())
+        };
+
+        let pair = match fields
+            .get("poolId")
+            .and_then(|id| id.as_str())
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            Some(pool_id) => self.pair(pool_id).await,
+            None => None,
+        };
+        let asset = address("asset", fields).or(pair.map(|pair| pair.0));
+        let quote = address("quote", fields).or(pair.map(|pair| pair.1));
+        let sell_asset = fields.get("sellAsset").and_then(|value| value.as_bool());
+        let token_in = address("tokenIn", fields).or(match sell_asset {
+            Some(true) => asset,
+            Some(false) => quote,
+            None => None,
+        });
+        let token_out = address("tokenOut", fields).or(match sell_asset {
+            Some(true) => quote,
+            Some(false) => asset,
+            None => None,
+        });
+
+        let keys: Vec<String> = fields.keys().cloned().collect();
+        for key in keys {
+            let lower = key.to_lowercase();
+            if lower.ends_with("id") || lower.contains("basispoints") || lower.contains("decimals")
+            {
+                continue;
+            }
+
+            if let Some(Value::Object(nested)) = fields.get_mut(&key) {
+                self.scale(nested, default_token).await;
+                continue;
+            }
+
+            let decimals = if lower.contains("liquidity") || lower.ends_with("wad") {
+                Some(18)
+            } else {
+                let token = if lower == "input" {
+                    token_in
+                } else if lower == "output" {
+                    token_out
+                } else if lower.contains("asset") {
+                    asset
+                } else if lower.contains("quote") {
+                    quote
+                } else if lower == "amount" || lower == "value" {
+                    default_token
+                } else {
+                    None
+                };
+                match token {
+                    Some(token) => self.decimals(token).await,
+                    None => None,
+                }
+            };
+
+            let Some(decimals) = decimals else {
+                continue;
+            };
+            let Some(raw) = fields
+                .get(&key)
+                .and_then(|value| value.as_str())
+                .and_then(|value| U256::from_dec_str(value).ok())
+            else {
+                continue;
+            };
+            if let Ok(scaled) = format_units(raw, decimals as u32) {
+                fields.insert(key, json!(scaled));
+            }
+        }
+    }
+
+    async fn pair(&mut self, pool_id: u64) -> Option<(Address, Address)> {
+        if let Some(pair) = self.pairs.get(&pool_id) {
+            return Some(*pair);
+        }
+
+        let mut call = self.portfolio.as_ref()?.pairs(PoolId(pool_id).pair_id());
+        if let Some(block) = self.block {
+            call = call.block(block);
+        }
+        // `pairs` has the decimals of both tokens, which saves reading them from the tokens.
+        let pair = Pair::from(call.call().await.ok()?);
+        self.decimals.insert(pair.asset, pair.asset_decimals);
+        self.decimals.insert(pair.quote, pair.quote_decimals);
+        self.pairs.insert(pool_id, (pair.asset, pair.quote));
+        Some((pair.asset, pair.quote))
+    }
+
+    async fn decimals(&mut self, token: Address) -> Option<u8> {
+        if let Some(decimals) = self.decimals.get(&token) {
+            return Some(*decimals);
+        }
+
+        let mut call = IERC20Permit::new(token, self.provider.clone()?).decimals();
+        if let Some(block) = self.block {
+            call = call.block(block);
+        }
+        let decimals = call.call().await.ok()?;
+        self.decimals.insert(token, decimals);
+        Some(decimals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_hashes_and_calldata() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(
+            Input::parse(&hash).unwrap(),
+            Input::Hash(H256::repeat_byte(0xab))
+        );
+        assert_eq!(
+            Input::parse("0xdeadbeef00").unwrap(),
+            Input::Calldata(vec![0xde, 0xad, 0xbe, 0xef, 0x00])
+        );
+        assert_eq!(
+            Input::parse("deadbeef").unwrap(),
+            Input::Calldata(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert!(Input::parse("not-hex").is_err());
+    }
+
+    #[test]
+    fn detects_receipt_files() {
+        let receipt = TransactionReceipt {
+            transaction_hash: H256::repeat_byte(1),
+            block_number: Some(7.into()),
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("decode-receipt-{}.json", std::process::id()));
+        std::fs::write(&path, serde_json::to_string(&receipt).unwrap()).unwrap();
+
+        let parsed = Input::parse(path.to_str().unwrap());
+        std::fs::write(&path, "{}").unwrap();
+        let invalid = Input::parse(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(parsed.unwrap(), Input::Receipt(receipt));
+        assert!(invalid.is_err());
+    }
+}
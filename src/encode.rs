@@ -0,0 +1,365 @@
+use super::batch::{Plan, Step};
+use super::{compat, Config, EncodeArgs, EncodeCommands};
+use crate::bindings::i_portfolio::{IPortfolio, IPortfolioCalls, MulticallCall};
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{abi::AbiEncode, prelude::*};
+use std::sync::Arc;
+
+/// Handles the "Encode" command.
+/// Prints the calldata of a Portfolio call built from cli flags or a json spec, without sending it.
+pub async fn main(cfg: &Config, args: &EncodeArgs) -> Result<(), anyhow::Error> {
+    let (steps, multicall) = match (&args.command, &args.spec) {
+        (Some(EncodeCommands::Multicall { plan }), _) => (
+            Plan::load(plan)
+                .context("encode.rs: Failed to load plan")?
+                .steps,
+            true,
+        ),
+        (Some(command), _) => (vec![command.to_step()], false),
+        (None, Some(spec)) => load_spec(spec)?,
+        (None, None) => {
+            return Err(anyhow::anyhow!(
+                "Missing call, pass a function subcommand or --spec <json>"
+            ))
+        }
+    };
+    if steps.is_empty() {
+        return Err(anyhow::anyhow!("Multicall has no steps"));
+    }
+
+    let account = match (&args.account, cfg.impersonate) {
+        (Some(account), _) => account.parse::<Address>()?,
+        (None, Some(account)) => account,
+        (None, None) => std::env::var("PRIVATE_KEY")
+            .context("encode.rs: Pass --account or set the PRIVATE_KEY env var")?
+            .parse::<LocalWallet>()?
+            .address(),
+    };
+    let address = cfg.portfolio_address.parse::<Address>()?;
+
+    // Only swaps and pool creations read the chain, the other calls are encoded offline if the rpc is unreachable.
+    let provider = Provider::<Ws>::connect(&cfg.rpc_url)
+        .await
+        .ok()
+        .map(Arc::new);
+    let mut calls = Vec::with_capacity(steps.len());
+    match provider {
+        Some(provider) => {
+            let portfolio = IPortfolio::new(address, provider);
+            let compat = compat::detect(cfg, &portfolio).await?;
+            for step in &steps {
+                compat.require(step.function())?;
+            }
+            for step in &steps {
+                let call = step
+                    .to_call(&portfolio, account)
+                    .await
+                    .context(format!("encode.rs: Failed to encode {}", step.function()))?;
+                calls.push(call);
+            }
+        }
+        None => {
+            for step in &steps {
+                let call = step
+                    .to_offline_call(account)
+                    .ok_or(anyhow::anyhow!(
+                        "Encoding {} needs an rpc connection, {} is unreachable",
+                        step.function(),
+                        cfg.rpc_url
+                    ))?
+                    .context(format!("encode.rs: Failed to encode {}", step.function()))?;
+                calls.push(call);
+            }
+        }
+    }
+
+    let (data, calldata) = calldata(calls, multicall);
+    let function = if multicall {
+        "multicall"
+    } else {
+        steps[0].function()
+    };
+
+    let header_msg = format!(
+        "{} {} {} {:?}",
+        "Encoded".yellow().bold(),
+        function.bold().yellow(),
+        "to".yellow(),
+        address
+    );
+    println!("{}", header_msg.on_black());
+    if multicall {
+        for (i, (step, entry)) in steps.iter().zip(data.iter()).enumerate() {
+            let step_msg = format!(
+                "   - {}{} {} {}",
+                "#".purple(),
+                i.to_string().bold().purple(),
+                step.function().bold().purple(),
+                entry
+            );
+            println!("{}", step_msg.on_black());
+        }
+    }
+    println!("{}", calldata);
+
+    Ok(())
+}
+
+/// Calldata of each call, and the calldata to send: the multicall of all of them if `multicall`.
+fn calldata(calls: Vec<IPortfolioCalls>, multicall: bool) -> (Vec<Bytes>, Bytes) {
+    let data: Vec<Bytes> = calls
+        .into_iter()
+        .map(|call| Bytes::from(call.encode()))
+        .collect();
+    let calldata = if multicall {
+        Bytes::from(IPortfolioCalls::Multicall(MulticallCall { data: data.clone() }).encode())
+    } else {
+        data[0].clone()
+    };
+    (data, calldata)
+}
+
+/// Parses a json step, or a json plan whose `steps` are encoded as a multicall, from a file or inline.
+fn load_spec(spec: &str) -> Result<(Vec<Step>, bool), anyhow::Error> {
+    let contents = if std::path::Path::new(spec).is_file() {
+        std::fs::read_to_string(spec)?
+    } else {
+        spec.to_string()
+    };
+    let value: serde_json::Value =
+        serde_json::from_str(&contents).context("encode.rs: Spec is not valid json")?;
+
+    if value.get("steps").is_some() {
+        let plan: Plan = serde_json::from_value(value).context("encode.rs: Invalid plan")?;
+        Ok((plan.steps, true))
+    } else {
+        let step: Step = serde_json::from_value(value).context("encode.rs: Invalid step")?;
+        Ok((vec![step], false))
+    }
+}
+
+impl EncodeCommands {
+    /// Converts the flags of a single call into a batch step.
+    fn to_step(&self) -> Step {
+        match self.clone() {
+            EncodeCommands::Swap { pool_id, args } => Step::Swap { pool_id, args },
+            EncodeCommands::Allocate { pool_id, args } => Step::Allocate { pool_id, args },
+            EncodeCommands::Deallocate { pool_id, args } => Step::Deallocate { pool_id, args },
+            EncodeCommands::Claim(args) => Step::Claim { args },
+            EncodeCommands::CreatePair(args) => Step::CreatePair { args },
+            EncodeCommands::CreatePool(args) => Step::CreatePool { args },
+            EncodeCommands::ChangeParameters { pool_id, args } => {
+                Step::ChangeParameters { pool_id, args }
+            }
+            EncodeCommands::Multicall { .. } => unreachable!("multicalls are loaded from a plan"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abi::encode_call;
+    use crate::{
+        AllocateArgs, ChangeParametersArgs, ClaimArgs, CreatePairArgs, CreatePoolArgs,
+        DeallocateArgs, SwapArgs,
+    };
+    use ethers::{abi::Token, utils::parse_ether};
+
+    const POOL_ID: u64 = 1_099_511_627_777;
+
+    fn account() -> Address {
+        Address::repeat_byte(0xaa)
+    }
+
+    fn offline(command: EncodeCommands) -> Bytes {
+        let call = command
+            .to_step()
+            .to_offline_call(account())
+            .expect("call is encoded offline")
+            .unwrap();
+        Bytes::from(call.encode())
+    }
+
+    fn ether(amount: f64) -> Token {
+        Token::Uint(parse_ether(amount).unwrap())
+    }
+
+    #[test]
+    fn encodes_allocate() {
+        let calldata = offline(EncodeCommands::Allocate {
+            pool_id: POOL_ID,
+            args: AllocateArgs {
+                liquidity: 1.5,
+                max_asset: 10.0,
+                max_quote: 20_000.0,
+                use_max: false,
+            },
+        });
+
+        assert_eq!(
+            calldata,
+            encode_call(
+                "allocate(bool,address,uint64,uint128,uint128,uint128)",
+                &[
+                    Token::Bool(false),
+                    Token::Address(account()),
+                    Token::Uint(POOL_ID.into()),
+                    ether(1.5),
+                    ether(10.0),
+                    ether(20_000.0),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_deallocate() {
+        let calldata = offline(EncodeCommands::Deallocate {
+            pool_id: POOL_ID,
+            args: DeallocateArgs {
+                liquidity: 0.0,
+                min_asset: 1.0,
+                min_quote: 900.0,
+                use_max: true,
+            },
+        });
+
+        assert_eq!(
+            calldata,
+            encode_call(
+                "deallocate(bool,uint64,uint128,uint128,uint128)",
+                &[
+                    Token::Bool(true),
+                    Token::Uint(POOL_ID.into()),
+                    ether(0.0),
+                    ether(1.0),
+                    ether(900.0),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_claim_of_the_full_balance() {
+        let token = Address::repeat_byte(0x11);
+        let calldata = offline(EncodeCommands::Claim(ClaimArgs {
+            token: format!("{:?}", token),
+            amount: 0.0,
+        }));
+
+        assert_eq!(
+            calldata,
+            encode_call(
+                "claimFee(address,uint256)",
+                &[Token::Address(token), Token::Uint(U256::MAX)],
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_create_pair() {
+        let (asset, quote) = (Address::repeat_byte(0x11), Address::repeat_byte(0x22));
+        let calldata = offline(EncodeCommands::CreatePair(CreatePairArgs {
+            asset: format!("{:?}", asset),
+            quote: format!("{:?}", quote),
+        }));
+
+        assert_eq!(
+            calldata,
+            encode_call(
+                "createPair(address,address)",
+                &[Token::Address(asset), Token::Address(quote)],
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_change_parameters() {
+        let calldata = offline(EncodeCommands::ChangeParameters {
+            pool_id: POOL_ID,
+            args: ChangeParametersArgs {
+                priority_fee_bps: 5,
+                fee_bps: 30,
+            },
+        });
+
+        assert_eq!(
+            calldata,
+            encode_call(
+                "changeParameters(uint64,uint16,uint16)",
+                &[
+                    Token::Uint(POOL_ID.into()),
+                    Token::Uint(5.into()),
+                    Token::Uint(30.into()),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_multicall_of_plan() {
+        let path = std::env::temp_dir().join(format!("encode-plan-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(
+                "[[steps]]\naction = \"deallocate\"\npool_id = {}\nuse_max = true\n\n[[steps]]\naction = \"change_parameters\"\npool_id = {}\npriority_fee_bps = 5\nfee_bps = 30\n",
+                POOL_ID, POOL_ID
+            ),
+        )
+        .unwrap();
+        let plan = Plan::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let calls = plan
+            .unwrap()
+            .steps
+            .iter()
+            .map(|step| step.to_offline_call(account()).unwrap().unwrap())
+            .collect();
+        let (data, calldata) = calldata(calls, true);
+
+        let deallocate = encode_call(
+            "deallocate(bool,uint64,uint128,uint128,uint128)",
+            &[
+                Token::Bool(true),
+                Token::Uint(POOL_ID.into()),
+                ether(0.0),
+                ether(0.0),
+                ether(0.0),
+            ],
+        );
+        let change_parameters = encode_call(
+            "changeParameters(uint64,uint16,uint16)",
+            &[
+                Token::Uint(POOL_ID.into()),
+                Token::Uint(5.into()),
+                Token::Uint(30.into()),
+            ],
+        );
+        assert_eq!(data, vec![deallocate.clone(), change_parameters.clone()]);
+        assert_eq!(
+            calldata,
+            encode_call(
+                "multicall(bytes[])",
+                &[Token::Array(vec![
+                    Token::Bytes(deallocate.to_vec()),
+                    Token::Bytes(change_parameters.to_vec()),
+                ])],
+            )
+        );
+    }
+
+    #[test]
+    fn swaps_and_pool_creations_need_the_chain() {
+        let swap = EncodeCommands::Swap {
+            pool_id: POOL_ID,
+            args: SwapArgs::default(),
+        };
+        let create_pool = EncodeCommands::CreatePool(CreatePoolArgs::default());
+
+        assert!(swap.to_step().to_offline_call(account()).is_none());
+        assert!(create_pool.to_step().to_offline_call(account()).is_none());
+    }
+}
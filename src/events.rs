@@ -0,0 +1,225 @@
+//! Portfolio events of a pool, shared by the commands that follow or index its activity.
+use super::tx::decode_log;
+use crate::bindings::i_portfolio::IPORTFOLIO_ABI;
+use ethers::{
+    abi::{LogParam, Token},
+    prelude::*,
+    utils::{format_ether, format_units},
+};
+use serde::Serialize;
+
+/// Events changing a pool's reserves.
+pub const POOL_EVENTS: &[&str] = &["Swap", "Allocate", "Deallocate"];
+/// Blocks fetched per request when reading logs over a range.
+const CHUNK: u64 = 2_000;
+
+/// Filter of the `events` emitted by Portfolio, for `pool_id` if set.
+pub fn filter(portfolio: Address, events: &[&str], pool_id: Option<u64>) -> Filter {
+    let topics: Vec<H256> = events
+        .iter()
+        .filter_map(|name| IPORTFOLIO_ABI.event(name).ok())
+        .map(|event| event.signature())
+        .collect();
+
+    let filter = Filter::new().address(portfolio).topic0(topics);
+    match pool_id {
+        Some(pool_id) => filter.topic1(H256::from_low_u64_be(pool_id)),
+        None => filter,
+    }
+}
+
+/// Logs of `filter` between two blocks included, fetched in chunks the node accepts.
+pub async fn logs<M: Middleware>(
+    client: &M,
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<Log>, anyhow::Error> {
+    let mut logs = Vec::new();
+    let mut from = from_block;
+    while from <= to_block {
+        let to = (from + CHUNK - 1).min(to_block);
+        logs.extend(
+            client
+                .get_logs(&filter.clone().from_block(from).to_block(to))
+                .await
+                .map_err(|err| anyhow::anyhow!("events.rs: Failed to get logs: {}", err))?,
+        );
+        from = to + 1;
+    }
+    Ok(logs)
+}
+
+/// Tokens of a pair with their decimals, as returned by Portfolio's `pairs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pair {
+    pub asset: Address,
+    pub asset_decimals: u8,
+    pub quote: Address,
+    pub quote_decimals: u8,
+}
+
+impl From<(Address, u8, Address, u8)> for Pair {
+    fn from((asset, asset_decimals, quote, quote_decimals): (Address, u8, Address, u8)) -> Self {
+        Self {
+            asset,
+            asset_decimals,
+            quote,
+            quote_decimals,
+        }
+    }
+}
+
+/// A swap of a pool, with amounts in units of its tokens.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trade {
+    pub block: u64,
+    pub tx_hash: H256,
+    pub pool_id: u64,
+    pub sell_asset: bool,
+    pub input: f64,
+    pub output: f64,
+    /// Price paid, in quote tokens per asset token.
+    pub price: f64,
+    /// Spot price of the pool after the swap.
+    pub spot_price: f64,
+}
+
+impl Trade {
+    /// Parses a Swap log of a pool of `pair`, scaling the amounts by the decimals of their tokens.
+    pub fn from_log(log: &Log, pair: &Pair) -> Option<Self> {
+        let (name, params) = decode_log(log)?;
+        if name != "Swap" {
+            return None;
+        }
+
+        let sell_asset = address(&params, "tokenIn")? == pair.asset;
+        let (input_decimals, output_decimals) = if sell_asset {
+            (pair.asset_decimals, pair.quote_decimals)
+        } else {
+            (pair.quote_decimals, pair.asset_decimals)
+        };
+        let amount = |name: &str, decimals: u8| -> Option<f64> {
+            format_units(uint(&params, name)?, decimals as u32)
+                .ok()?
+                .parse()
+                .ok()
+        };
+
+        let pool_id = uint(&params, "poolId")?.as_u64();
+        let input = amount("input", input_decimals)?;
+        let output = amount("output", output_decimals)?;
+        let spot_price: f64 = format_ether(uint(&params, "price")?).parse().ok()?;
+        if input == 0.0 || output == 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            block: log.block_number?.as_u64(),
+            tx_hash: log.transaction_hash?,
+            pool_id,
+            sell_asset,
+            input,
+            output,
+            price: if sell_asset {
+                output / input
+            } else {
+                input / output
+            },
+            spot_price,
+        })
+    }
+
+    /// Amount of asset tokens traded.
+    pub fn asset_amount(&self) -> f64 {
+        if self.sell_asset {
+            self.input
+        } else {
+            self.output
+        }
+    }
+
+    /// Amount of quote tokens traded.
+    pub fn quote_amount(&self) -> f64 {
+        if self.sell_asset {
+            self.output
+        } else {
+            self.input
+        }
+    }
+}
+
+pub fn uint(params: &[LogParam], name: &str) -> Option<U256> {
+    match params.iter().find(|param| param.name == name)?.value {
+        Token::Uint(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn address(params: &[LogParam], name: &str) -> Option<Address> {
+    match params.iter().find(|param| param.name == name)?.value {
+        Token::Address(value) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{abi::encode, utils::parse_units};
+
+    fn swap_log(token_in: Address, token_out: Address, input: U256, output: U256) -> Log {
+        Log {
+            topics: vec![
+                IPORTFOLIO_ABI.event("Swap").unwrap().signature(),
+                H256::from_low_u64_be(7),
+                H256::from(token_in),
+                H256::from(token_out),
+            ],
+            data: encode(&[
+                Token::Uint(parse_units(1_000, 18).unwrap().into()),
+                Token::Uint(input),
+                Token::Uint(output),
+                Token::Uint(U256::zero()),
+                Token::Int(U256::zero()),
+            ])
+            .into(),
+            block_number: Some(1.into()),
+            transaction_hash: Some(H256::repeat_byte(1)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scales_amounts_by_the_decimals_of_the_pair() {
+        let pair = Pair {
+            asset: Address::repeat_byte(1),
+            asset_decimals: 18,
+            quote: Address::repeat_byte(2),
+            quote_decimals: 6,
+        };
+
+        let sell = swap_log(
+            pair.asset,
+            pair.quote,
+            parse_units(2, 18).unwrap().into(),
+            parse_units(1_990, 6).unwrap().into(),
+        );
+        let trade = Trade::from_log(&sell, &pair).unwrap();
+        assert!(trade.sell_asset);
+        assert_eq!((trade.input, trade.output), (2.0, 1_990.0));
+        assert_eq!(trade.price, 995.0);
+        assert_eq!(trade.spot_price, 1_000.0);
+
+        let buy = swap_log(
+            pair.quote,
+            pair.asset,
+            parse_units(1_010, 6).unwrap().into(),
+            parse_units(1, 18).unwrap().into(),
+        );
+        let trade = Trade::from_log(&buy, &pair).unwrap();
+        assert!(!trade.sell_asset);
+        assert_eq!((trade.quote_amount(), trade.asset_amount()), (1_010.0, 1.0));
+        assert_eq!(trade.price, 1_010.0);
+    }
+}
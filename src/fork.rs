@@ -0,0 +1,224 @@
+use super::tokens::IERC20Permit;
+use super::{Config, ForkArgs};
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, format_units, keccak256, Anvil, AnvilInstance},
+};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+/// Local anvil node that every command talks to while `--fork` or `--fork-state` is set.
+pub struct Fork {
+    /// Kept for the lifetime of the fork, the node is killed on drop.
+    _anvil: AnvilInstance,
+    provider: Arc<Provider<Ws>>,
+    endpoint: String,
+    /// Keeps the fork's nonces away from the real network's.
+    state_dir: PathBuf,
+    /// Impersonated signer, if the command sends transactions.
+    signer: Option<Address>,
+    start_block: U64,
+    start_balance: U256,
+}
+
+/// Starts an anvil fork of the configured network, or a plain local node loading a state dump,
+/// impersonating and funding the signer if `needs_signer`, as commands only reading state run without one.
+pub async fn start(args: &ForkArgs, needs_signer: bool) -> Result<Option<Fork>, anyhow::Error> {
+    if args.fork.is_none() && args.fork_state.is_none() {
+        return Ok(None);
+    }
+
+    let cfg = Config::new()?;
+    let mut anvil = Anvil::new().timeout(60_000u64);
+    if let Some(block) = args.fork {
+        anvil = anvil.fork(&cfg.rpc_url).fork_block_number(block);
+    }
+    if let Some(state) = &args.fork_state {
+        anvil = anvil.args(["--load-state", state.as_str()]);
+    }
+    let anvil = anvil.spawn();
+
+    let fork_msg = format!(
+        "{} {} {}",
+        "Rehearsing on local fork".yellow().bold(),
+        anvil.ws_endpoint().bold().yellow(),
+        match args.fork {
+            Some(block) => format!("at block {}", block),
+            None => "from local state".to_string(),
+        }
+        .yellow()
+    );
+    println!("{}", fork_msg.on_black());
+
+    let provider = Arc::new(Provider::<Ws>::connect(anvil.ws_endpoint()).await?);
+    let signer = match needs_signer {
+        true => Some(impersonate(&provider, args).await?),
+        false => None,
+    };
+    let start_balance = match signer {
+        Some(signer) => provider.get_balance(signer, None).await?,
+        None => U256::zero(),
+    };
+
+    Ok(Some(Fork {
+        start_block: provider.get_block_number().await?,
+        start_balance,
+        endpoint: anvil.ws_endpoint(),
+        state_dir: std::env::temp_dir().join(format!("portfolio-rs-fork-{}", anvil.port())),
+        _anvil: anvil,
+        provider,
+        signer,
+    }))
+}
+
+/// Impersonates the `--fork-account`, or the `PRIVATE_KEY` account, funding it with gas if it has none.
+async fn impersonate(provider: &Provider<Ws>, args: &ForkArgs) -> Result<Address, anyhow::Error> {
+    let signer = match &args.fork_account {
+        Some(account) => account.parse::<Address>()?,
+        None => std::env::var("PRIVATE_KEY")
+            .context("fork.rs: Pass --fork-account or set the PRIVATE_KEY env var")?
+            .parse::<LocalWallet>()?
+            .address(),
+    };
+
+    provider
+        .request::<_, ()>("anvil_impersonateAccount", [signer])
+        .await
+        .context("fork.rs: Failed to impersonate signer")?;
+    if provider.get_balance(signer, None).await?.is_zero() {
+        provider
+            .request::<_, ()>(
+                "anvil_setBalance",
+                (signer, U256::exp10(18) * U256::from(100)),
+            )
+            .await?;
+    }
+
+    Ok(signer)
+}
+
+impl Fork {
+    /// Points the config at the fork, sending the impersonated signer's transactions unsigned.
+    pub fn apply(&self, cfg: &mut Config) {
+        cfg.rpc_url = self.endpoint.clone();
+        cfg.state_dir = self.state_dir.to_string_lossy().to_string();
+        cfg.impersonate = self.signer;
+    }
+
+    /// Prints the signer's native and token balance changes and the gas it used since the fork started,
+    /// if the command had a signer.
+    pub async fn report(&self) -> Result<(), anyhow::Error> {
+        let Some(signer) = self.signer else {
+            return Ok(());
+        };
+        let end_block = self.provider.get_block_number().await?;
+        let end_balance = self.provider.get_balance(signer, None).await?;
+
+        let mut gas_used = U256::zero();
+        let mut transactions = 0;
+        for number in self.start_block.as_u64() + 1..=end_block.as_u64() {
+            let Some(block) = self.provider.get_block(number).await? else {
+                continue;
+            };
+            for hash in block.transactions {
+                let Some(receipt) = self.provider.get_transaction_receipt(hash).await? else {
+                    continue;
+                };
+                if receipt.from == signer {
+                    gas_used += receipt.gas_used.unwrap_or_default();
+                    transactions += 1;
+                }
+            }
+        }
+
+        let deltas = self.token_deltas(signer, end_block).await?;
+
+        let header_msg = format!(
+            "\n{} {} {} {} {}",
+            "Fork Report:".yellow().bold(),
+            "\n   - transactions:".yellow(),
+            transactions.to_string().bold().yellow(),
+            "\n   - gas used:".yellow(),
+            gas_used.to_string().bold().yellow(),
+        );
+        println!("{}", header_msg.on_black());
+
+        let native_delta = if end_balance >= self.start_balance {
+            format!("+{}", format_ether(end_balance - self.start_balance))
+        } else {
+            format!("-{}", format_ether(self.start_balance - end_balance))
+        };
+        let native_msg = format!(
+            "   - {} {}",
+            "native:".yellow(),
+            native_delta.bold().yellow()
+        );
+        println!("{}", native_msg.on_black());
+
+        for (token, (received, sent)) in deltas {
+            let erc20 = IERC20Permit::new(token, self.provider.clone());
+            let symbol = erc20
+                .symbol()
+                .call()
+                .await
+                .unwrap_or_else(|_| format!("{:?}", token));
+            let decimals = erc20.decimals().call().await.unwrap_or(18);
+
+            let delta = if received >= sent {
+                format!("+{}", format_units(received - sent, decimals as u32)?)
+            } else {
+                format!("-{}", format_units(sent - received, decimals as u32)?)
+            };
+            let token_msg = format!(
+                "   - {} {}",
+                format!("{}:", symbol).yellow(),
+                delta.bold().yellow()
+            );
+            println!("{}", token_msg.on_black());
+        }
+
+        Ok(())
+    }
+
+    /// Sums the ERC20 transfers into and out of the signer, by token.
+    async fn token_deltas(
+        &self,
+        signer: Address,
+        end_block: U64,
+    ) -> Result<BTreeMap<Address, (U256, U256)>, anyhow::Error> {
+        let mut deltas = BTreeMap::<Address, (U256, U256)>::new();
+        if end_block <= self.start_block {
+            return Ok(deltas);
+        }
+
+        let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+        let logs = self
+            .provider
+            .get_logs(
+                &Filter::new()
+                    .from_block(self.start_block.as_u64() + 1)
+                    .to_block(end_block)
+                    .topic0(transfer),
+            )
+            .await?;
+
+        let signer = H256::from(signer);
+        for log in logs {
+            if log.topics.len() != 3 || log.data.len() != 32 {
+                continue;
+            }
+            let amount = U256::from_big_endian(&log.data);
+            let entry = deltas.entry(log.address).or_default();
+            if log.topics[2] == signer {
+                entry.0 += amount;
+            }
+            if log.topics[1] == signer {
+                entry.1 += amount;
+            }
+        }
+
+        deltas.retain(|_, (received, sent)| !received.is_zero() || !sent.is_zero());
+        Ok(deltas)
+    }
+}
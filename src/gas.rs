@@ -0,0 +1,258 @@
+use super::utils::Client;
+use super::{Config, Gas};
+use crate::bindings::i_portfolio::IPortfolio;
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    types::transaction::eip2718::TypedTransaction,
+    utils::{format_ether, format_units, parse_units},
+};
+use std::sync::Arc;
+
+impl Config {
+    /// Gas settings of a network, with the cli flags taking precedence over the configured defaults.
+    /// Refuses a gas multiplier that isn't a positive finite number.
+    fn gas_settings(&self, chain_id: u64) -> Result<Gas, anyhow::Error> {
+        let defaults = self
+            .gas
+            .get(&chain_id.to_string())
+            .cloned()
+            .unwrap_or_default();
+
+        let gas_multiplier = self.gas_args.gas_multiplier.or(defaults.gas_multiplier);
+        if let Some(multiplier) = gas_multiplier {
+            if !multiplier.is_finite() || multiplier <= 0.0 {
+                return Err(anyhow::anyhow!(
+                    "gas.rs: Gas multiplier must be a positive number, got {}",
+                    multiplier
+                ));
+            }
+        }
+
+        Ok(Gas {
+            max_fee: self.gas_args.max_fee.or(defaults.max_fee),
+            priority_fee: self.gas_args.priority_fee.or(defaults.priority_fee),
+            gas_limit: self.gas_args.gas_limit.or(defaults.gas_limit),
+            gas_multiplier,
+            ..defaults
+        })
+    }
+}
+
+/// Fills the fees and gas limit of `tx`, prints the estimated cost and refuses it if it exceeds the ceiling.
+pub async fn prepare(
+    cfg: &Config,
+    client: &Arc<Client>,
+    tx: &mut TypedTransaction,
+) -> Result<(), anyhow::Error> {
+    let settings = cfg.gas_settings(client.signer().chain_id())?;
+    tx.set_from(client.address());
+
+    let (estimated_max_fee, estimated_priority_fee) = client
+        .estimate_eip1559_fees(None)
+        .await
+        .context("gas.rs: Failed to estimate fees")?;
+    let max_fee = match settings.max_fee {
+        Some(gwei) => parse_units(gwei, "gwei")?.into(),
+        None => estimated_max_fee,
+    };
+    let priority_fee = match settings.priority_fee {
+        Some(gwei) => parse_units(gwei, "gwei")?.into(),
+        None => estimated_priority_fee,
+    }
+    .min(max_fee);
+
+    match tx {
+        TypedTransaction::Eip1559(inner) => {
+            inner.max_fee_per_gas = Some(max_fee);
+            inner.max_priority_fee_per_gas = Some(priority_fee);
+        }
+        _ => {
+            tx.set_gas_price(max_fee);
+        }
+    }
+
+    let gas_limit = match settings.gas_limit {
+        Some(gas_limit) => U256::from(gas_limit),
+        None => {
+            let estimate = client
+                .estimate_gas(tx, None)
+                .await
+                .context("gas.rs: Failed to estimate gas")?;
+            scale(estimate, settings.gas_multiplier.unwrap_or(1.0))
+        }
+    };
+    tx.set_gas(gas_limit);
+
+    let cost = gas_limit * max_fee;
+    let quote_cost = match settings.quote_pool_id {
+        Some(pool_id) => Some(quote_cost(cfg, client, pool_id, cost).await?),
+        None => None,
+    };
+
+    let gas_msg = format!(
+        "{} {} {} {} {} {} {} {}",
+        "\nGas Limit:".bold().blue(),
+        gas_limit.to_string().bold().blue(),
+        "\nMax Fee (gwei):".bold().blue(),
+        format_units(max_fee, "gwei")?.bold().blue(),
+        "\nPriority Fee (gwei):".bold().blue(),
+        format_units(priority_fee, "gwei")?.bold().blue(),
+        "\nMax Cost:".bold().blue(),
+        match quote_cost {
+            Some(quote_cost) => format!("{} (~{:.4} quote)", format_ether(cost), quote_cost),
+            None => format_ether(cost),
+        }
+        .bold()
+        .blue(),
+    );
+    println!("{}", gas_msg.on_black());
+
+    check_cost(cost, settings.max_cost)
+}
+
+/// Refuses a transaction whose estimated `cost`, in wei, exceeds the `max_cost` ceiling, in native token.
+fn check_cost(cost: U256, max_cost: Option<f64>) -> Result<(), anyhow::Error> {
+    if let Some(max_cost) = max_cost {
+        let ceiling = parse_units(max_cost, "ether")?.into();
+        if cost > ceiling {
+            return Err(anyhow::anyhow!(
+                "Estimated cost {} exceeds the configured ceiling {}",
+                format_ether(cost),
+                max_cost
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Estimates the cost of `gas` units at the current fees in quote tokens,
+/// through the network's `quote_pool_id`, or at `native_price` quote tokens per native token.
+pub async fn cost_in_quote(
+    cfg: &Config,
+    client: &Arc<Client>,
+    gas: u64,
+    native_price: f64,
+) -> Result<f64, anyhow::Error> {
+    let settings = cfg.gas_settings(client.signer().chain_id())?;
+    let max_fee = match settings.max_fee {
+        Some(gwei) => parse_units(gwei, "gwei")?.into(),
+        None => {
+            client
+                .estimate_eip1559_fees(None)
+                .await
+                .context("gas.rs: Failed to estimate fees")?
+                .0
+        }
+    };
+    let cost = max_fee * U256::from(gas);
+    match settings.quote_pool_id {
+        Some(pool_id) => quote_cost(cfg, client, pool_id, cost).await,
+        None => Ok(format_ether(cost).parse::<f64>()? * native_price),
+    }
+}
+
+/// Values `cost` in the quote token of a pool whose asset is the wrapped native token.
+async fn quote_cost(
+    cfg: &Config,
+    client: &Arc<Client>,
+    pool_id: u64,
+    cost: U256,
+) -> Result<f64, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let spot_price = portfolio
+        .get_spot_price(pool_id)
+        .call()
+        .await
+        .context("gas.rs: Failed to get spot price of the quote pool")?;
+
+    let cost: f64 = format_ether(cost).parse()?;
+    let price: f64 = format_ether(spot_price).parse()?;
+    Ok(cost * price)
+}
+
+/// Scales a gas amount by a multiplier, with 4 decimals of precision.
+fn scale(amount: U256, multiplier: f64) -> U256 {
+    amount * U256::from((multiplier * 10_000.0) as u64) / U256::from(10_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GasArgs;
+    use ethers::utils::parse_ether;
+
+    fn config(gas_args: GasArgs, defaults: Gas) -> Config {
+        Config {
+            gas: [("1".to_string(), defaults)].into_iter().collect(),
+            gas_args,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn cli_flags_take_precedence_over_the_chain_defaults() {
+        let defaults = Gas {
+            max_fee: Some(50.0),
+            priority_fee: Some(2.0),
+            gas_limit: Some(500_000),
+            gas_multiplier: Some(1.5),
+            max_cost: Some(0.1),
+            quote_pool_id: Some(1),
+        };
+        let cfg = config(
+            GasArgs {
+                max_fee: Some(30.0),
+                gas_multiplier: Some(1.2),
+                ..GasArgs::default()
+            },
+            defaults,
+        );
+
+        let settings = cfg.gas_settings(1).unwrap();
+        assert_eq!(settings.max_fee, Some(30.0));
+        assert_eq!(settings.gas_multiplier, Some(1.2));
+        assert_eq!(settings.priority_fee, Some(2.0));
+        assert_eq!(settings.gas_limit, Some(500_000));
+        assert_eq!(settings.max_cost, Some(0.1));
+        assert_eq!(settings.quote_pool_id, Some(1));
+
+        let other_chain = cfg.gas_settings(5).unwrap();
+        assert_eq!(other_chain.max_fee, Some(30.0));
+        assert_eq!(other_chain.priority_fee, None);
+    }
+
+    #[test]
+    fn refuses_invalid_gas_multipliers() {
+        for multiplier in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let cfg = config(
+                GasArgs {
+                    gas_multiplier: Some(multiplier),
+                    ..GasArgs::default()
+                },
+                Gas::default(),
+            );
+            assert!(cfg.gas_settings(1).is_err());
+        }
+
+        let cfg = config(
+            GasArgs::default(),
+            Gas {
+                gas_multiplier: Some(0.0),
+                ..Gas::default()
+            },
+        );
+        assert!(cfg.gas_settings(1).is_err());
+    }
+
+    #[test]
+    fn refuses_costs_above_the_ceiling() {
+        let cost = parse_ether(0.05).unwrap();
+        assert!(check_cost(cost, None).is_ok());
+        assert!(check_cost(cost, Some(0.05)).is_ok());
+        assert!(check_cost(cost, Some(0.1)).is_ok());
+        assert!(check_cost(cost, Some(0.01)).is_err());
+    }
+}
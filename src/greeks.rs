@@ -0,0 +1,333 @@
+//! Greeks of NormalStrategy liquidity.
+//! A unit of liquidity holds `1 - N(d1)` asset tokens and `K N(d2)` quote tokens, the replicating portfolio of a covered call
+//! struck at `K` expiring in `tau`, so its value and sensitivities follow Black-Scholes without rates:
+//! - value: `S (1 - N(d1)) + K N(d2)`
+//! - delta: `1 - N(d1)`, in asset tokens
+//! - gamma: `-n(d1) / (S sigma sqrt(tau))`
+//! - theta: `S n(d1) sigma / (2 sqrt(tau))`, per year
+//! - vega: `-S n(d1) sqrt(tau)`, per unit of volatility
+use super::info::PoolState;
+use super::utils::PoolId;
+use crate::bindings::normal_strategy::NormalStrategy;
+use colored::Colorize;
+use ethers::{prelude::*, utils::format_ether};
+use serde::Serialize;
+use statrs::distribution::{Continuous, ContinuousCDF, Normal};
+use std::{str::FromStr, sync::Arc};
+
+/// Seconds in a year of 365 days.
+pub const YEAR: f64 = 31_536_000.0;
+
+/// Parameters of a NormalStrategy pool.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StrategyConfig {
+    pub strike: f64,
+    /// Implied volatility, as a fraction.
+    pub volatility: f64,
+    /// Time to expiry, in years.
+    pub tau: f64,
+}
+
+impl StrategyConfig {
+    /// Reads the config of a pool from its strategy, with its time to expiry at `timestamp`.
+    pub async fn fetch<M: Middleware + 'static>(
+        client: Arc<M>,
+        strategy: Address,
+        pool_id: u64,
+        block: u64,
+        timestamp: u64,
+    ) -> Result<Self, anyhow::Error> {
+        let (strike, volatility_bps, duration, creation, is_perpetual) =
+            NormalStrategy::new(strategy, client)
+                .configs(pool_id)
+                .block(block)
+                .call()
+                .await
+                .map_err(|err| {
+                    anyhow::anyhow!(
+                        "Failed to read the NormalStrategy config of pool {} (pair {}): {}",
+                        pool_id,
+                        PoolId(pool_id).pair_id(),
+                        err
+                    )
+                })?;
+
+        let remaining = if is_perpetual {
+            duration as f64
+        } else {
+            (creation as f64 + duration as f64 - timestamp as f64).max(0.0)
+        };
+        Ok(Self {
+            strike: format_ether(U256::from(strike)).parse()?,
+            volatility: volatility_bps as f64 / 10_000.0,
+            tau: remaining / YEAR,
+        })
+    }
+}
+
+/// Value and sensitivities of liquidity, in quote tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Greeks {
+    pub value: f64,
+    /// Change in value per quote token of price, in asset tokens.
+    pub delta: f64,
+    /// Change in delta per quote token of price.
+    pub gamma: f64,
+    /// Change in value per day.
+    pub theta: f64,
+    /// Change in value per volatility point.
+    pub vega: f64,
+}
+
+impl Greeks {
+    /// Greeks of one unit of liquidity at `price`.
+    pub fn of(config: &StrategyConfig, price: f64) -> Self {
+        let normal = Normal::new(0.0, 1.0).expect("standard normal");
+        let StrategyConfig {
+            strike,
+            volatility,
+            tau,
+        } = *config;
+
+        // Expired pools hold only the asset below the strike, and only the quote above it.
+        let deviation = volatility * tau.sqrt();
+        if deviation <= 0.0 || price <= 0.0 {
+            let delta = if price < strike { 1.0 } else { 0.0 };
+            return Self {
+                value: delta * price + (1.0 - delta) * strike,
+                delta,
+                ..Default::default()
+            };
+        }
+
+        let d1 = ((price / strike).ln() + deviation * deviation / 2.0) / deviation;
+        let d2 = d1 - deviation;
+        let density = normal.pdf(d1);
+        Self {
+            value: price * (1.0 - normal.cdf(d1)) + strike * normal.cdf(d2),
+            delta: 1.0 - normal.cdf(d1),
+            gamma: -density / (price * deviation),
+            theta: price * density * volatility / (2.0 * tau.sqrt()) / 365.0,
+            vega: -price * density * tau.sqrt() / 100.0,
+        }
+    }
+
+    /// Greeks of `liquidity` units.
+    pub fn scale(self, liquidity: f64) -> Self {
+        Self {
+            value: self.value * liquidity,
+            delta: self.delta * liquidity,
+            gamma: self.gamma * liquidity,
+            theta: self.theta * liquidity,
+            vega: self.vega * liquidity,
+        }
+    }
+}
+
+/// Risk of a pool's liquidity at a block, per unit of liquidity.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Risk {
+    pub config: StrategyConfig,
+    pub price: f64,
+    pub unit: Greeks,
+}
+
+impl Risk {
+    /// Reads the strategy config of the pool at its block and prices its liquidity at the spot price.
+    pub async fn fetch<M: Middleware + 'static>(
+        client: Arc<M>,
+        state: &PoolState,
+    ) -> Result<Self, anyhow::Error> {
+        let timestamp = client
+            .get_block(state.block)
+            .await
+            .map_err(|err| anyhow::anyhow!("greeks.rs: Failed to get block: {}", err))?
+            .ok_or(anyhow::anyhow!(
+                "greeks.rs: Block {} not found",
+                state.block
+            ))?
+            .timestamp
+            .as_u64();
+        let config = StrategyConfig::fetch(
+            client,
+            state.pool.strategy,
+            state.pool_id,
+            state.block,
+            timestamp,
+        )
+        .await?;
+        let price: f64 = format_ether(state.spot_price).parse()?;
+        Ok(Self {
+            config,
+            price,
+            unit: Greeks::of(&config, price),
+        })
+    }
+
+    /// Greeks of `liquidity` wad units of the pool.
+    pub fn greeks(&self, liquidity: u128) -> Greeks {
+        let liquidity: f64 = format_ether(U256::from(liquidity))
+            .parse()
+            .unwrap_or_default();
+        self.unit.scale(liquidity)
+    }
+
+    /// Greeks of `liquidity` wad units after the shocks of `scenario`.
+    pub fn revalue(&self, scenario: &Scenario, liquidity: u128) -> Greeks {
+        let (config, price) = scenario.apply(&self.config, self.price);
+        let liquidity: f64 = format_ether(U256::from(liquidity))
+            .parse()
+            .unwrap_or_default();
+        Greeks::of(&config, price).scale(liquidity)
+    }
+}
+
+/// Shocks applied to a position, such as `price=+10%,vol=-20%,days=7`.
+/// Price and volatility changes are relative, days pass time towards expiry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Scenario {
+    pub price: f64,
+    pub volatility: f64,
+    pub days: f64,
+}
+
+impl FromStr for Scenario {
+    type Err = anyhow::Error;
+
+    fn from_str(scenario: &str) -> Result<Self, Self::Err> {
+        let mut parsed = Scenario::default();
+        for shock in scenario
+            .split(',')
+            .map(str::trim)
+            .filter(|shock| !shock.is_empty())
+        {
+            let (key, value) = shock.split_once('=').ok_or(anyhow::anyhow!(
+                "Invalid shock {}, expected key=value",
+                shock
+            ))?;
+            let percent = |value: &str| -> Result<f64, anyhow::Error> {
+                let value = value
+                    .trim()
+                    .strip_suffix('%')
+                    .ok_or(anyhow::anyhow!("Shock {} must be a percentage", shock))?;
+                Ok(value.trim_start_matches('+').parse::<f64>()? / 100.0)
+            };
+            match key.trim() {
+                "price" => parsed.price = percent(value)?,
+                "vol" | "volatility" => parsed.volatility = percent(value)?,
+                "days" => parsed.days = value.trim().parse()?,
+                key => {
+                    return Err(anyhow::anyhow!(
+                        "Unknown shock {}, expected price, vol or days",
+                        key
+                    ))
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+impl Scenario {
+    /// Revalues a unit of liquidity under the shocks.
+    pub fn apply(&self, config: &StrategyConfig, price: f64) -> (StrategyConfig, f64) {
+        let shocked = StrategyConfig {
+            volatility: (config.volatility * (1.0 + self.volatility)).max(0.0),
+            tau: (config.tau - self.days / 365.0).max(0.0),
+            ..*config
+        };
+        (shocked, (price * (1.0 + self.price)).max(0.0))
+    }
+}
+
+/// Prints greeks under a `title`, with their value after a scenario if any.
+pub fn print(title: &str, greeks: &Greeks, shocked: Option<(&str, &Greeks)>) {
+    let greeks_msg = format!(
+        "        {}
+        - value (quote): {}
+        - delta (asset): {}
+        - gamma: {}
+        - theta (quote/day): {}
+        - vega (quote/vol point): {}",
+        title.yellow().bold(),
+        greeks.value.to_string().yellow(),
+        greeks.delta.to_string().yellow(),
+        greeks.gamma.to_string().yellow(),
+        greeks.theta.to_string().yellow(),
+        greeks.vega.to_string().yellow(),
+    );
+    println!("{}", greeks_msg.on_black());
+
+    if let Some((scenario, shocked)) = shocked {
+        let change = shocked.value - greeks.value;
+        let scenario_msg = format!(
+            "        - {} {} {} {}",
+            format!("{}:", scenario).yellow(),
+            shocked.value.to_string().bold().yellow(),
+            "value, delta".yellow(),
+            shocked.delta.to_string().bold().yellow(),
+        );
+        println!("{}", scenario_msg.on_black());
+        let change_msg = format!(
+            "        - {} {}",
+            "change (quote):".yellow(),
+            if change < 0.0 {
+                change.to_string().red()
+            } else {
+                format!("+{}", change).green()
+            }
+        );
+        println!("{}", change_msg.on_black());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: StrategyConfig = StrategyConfig {
+        strike: 100.0,
+        volatility: 0.8,
+        tau: 0.25,
+    };
+
+    #[test]
+    fn greeks_match_finite_differences() {
+        let greeks = Greeks::of(&CONFIG, 95.0);
+        let value = |config: &StrategyConfig, price: f64| Greeks::of(config, price).value;
+        let h = 1e-3;
+
+        let delta = (value(&CONFIG, 95.0 + h) - value(&CONFIG, 95.0 - h)) / (2.0 * h);
+        let gamma =
+            (Greeks::of(&CONFIG, 95.0 + h).delta - Greeks::of(&CONFIG, 95.0 - h).delta) / (2.0 * h);
+        let later = StrategyConfig {
+            tau: CONFIG.tau - 1.0 / 365.0,
+            ..CONFIG
+        };
+        let higher_vol = StrategyConfig {
+            volatility: CONFIG.volatility + 0.01,
+            ..CONFIG
+        };
+
+        assert!((greeks.delta - delta).abs() < 1e-6);
+        assert!((greeks.gamma - gamma).abs() < 1e-6);
+        assert!((greeks.theta - (value(&later, 95.0) - greeks.value)).abs() < 1e-2);
+        assert!((greeks.vega - (value(&higher_vol, 95.0) - greeks.value)).abs() < 1e-2);
+        assert!(greeks.gamma < 0.0 && greeks.vega < 0.0 && greeks.theta > 0.0);
+    }
+
+    #[test]
+    fn parses_scenarios() {
+        let scenario: Scenario = "price=+10%, vol=-20%,days=7".parse().unwrap();
+        assert!((scenario.price - 0.1).abs() < 1e-12);
+        assert!((scenario.volatility + 0.2).abs() < 1e-12);
+        assert_eq!(scenario.days, 7.0);
+
+        assert!("price=10".parse::<Scenario>().is_err());
+        assert!("rate=+1%".parse::<Scenario>().is_err());
+
+        let (shocked, price) = scenario.apply(&CONFIG, 100.0);
+        assert!((price - 110.0).abs() < 1e-9);
+        assert!((shocked.volatility - 0.64).abs() < 1e-12);
+    }
+}
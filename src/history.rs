@@ -0,0 +1,339 @@
+//! Price history of a pool, rebuilt from its Swap events.
+//! Each swap gives an execution price and the spot price it left the pool at, and swaps are bucketed into OHLCV candles.
+use super::events::{self, Pair, Trade};
+use super::utils::{read_block, PoolId};
+use super::{Config, HistoryArgs};
+use crate::bindings::i_portfolio::IPortfolio;
+use colored::Colorize;
+use ethers::prelude::*;
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+/// A swap with the timestamp of its block.
+#[derive(Debug, Clone, Serialize)]
+pub struct Swap {
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub trade: Trade,
+}
+
+/// Execution prices of the swaps in an interval, with the traded volumes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candle {
+    /// Start of the interval, in unix seconds.
+    pub start: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Spot price of the pool after the last swap.
+    pub spot_close: f64,
+    /// Volume in asset tokens.
+    pub volume: f64,
+    /// Volume in quote tokens.
+    pub quote_volume: f64,
+    pub swaps: usize,
+}
+
+/// Handles the "History" command.
+pub async fn main(cfg: &Config, args: &HistoryArgs) -> Result<(), anyhow::Error> {
+    let interval = parse_interval(&args.interval)?;
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let swaps = fetch(
+        cfg,
+        provider,
+        args.pool_id,
+        args.from_block.unwrap_or(cfg.start_block),
+        args.to_block,
+    )
+    .await?;
+
+    let output = match (args.trades, args.csv, args.json) {
+        (true, true, _) => swaps_csv(&swaps),
+        (true, _, true) => serde_json::to_string_pretty(&swaps)?,
+        (false, true, _) => candles_csv(&candles(&swaps, interval)),
+        (false, _, true) => serde_json::to_string_pretty(&candles(&swaps, interval))?,
+        (trades, false, false) => {
+            print(args.pool_id, &swaps, interval, trades);
+            return Ok(());
+        }
+    };
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, output)?;
+            let saved_msg = format!("{} {}", "Saved history to".green(), path.bold().green());
+            println!("{}", saved_msg.on_black());
+        }
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+/// Fetches the swaps of a pool between two blocks, with amounts in the decimals of its tokens.
+pub async fn fetch<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    pool_id: u64,
+    from_block: u64,
+    to_block: Option<u64>,
+) -> Result<Vec<Swap>, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let to_block = match to_block {
+        Some(to_block) => to_block,
+        None => read_block(cfg, &*client).await?,
+    };
+    let pair = Pair::from(
+        portfolio
+            .pairs(PoolId(pool_id).pair_id())
+            .block(to_block)
+            .call()
+            .await?,
+    );
+    if pair.asset.is_zero() {
+        return Err(anyhow::anyhow!("Pool {} does not exist", pool_id));
+    }
+    let filter = events::filter(portfolio.address(), &["Swap"], Some(pool_id));
+    let logs = events::logs(&*client, &filter, from_block, to_block).await?;
+
+    let mut timestamps = HashMap::<u64, u64>::new();
+    let mut swaps = Vec::new();
+    for log in &logs {
+        let Some(trade) = Trade::from_log(log, &pair) else {
+            continue;
+        };
+        let timestamp = match timestamps.get(&trade.block) {
+            Some(timestamp) => *timestamp,
+            None => {
+                let timestamp = client
+                    .get_block(trade.block)
+                    .await
+                    .map_err(|err| anyhow::anyhow!("history.rs: Failed to get block: {}", err))?
+                    .map(|block| block.timestamp.as_u64())
+                    .unwrap_or_default();
+                timestamps.insert(trade.block, timestamp);
+                timestamp
+            }
+        };
+        swaps.push(Swap { timestamp, trade });
+    }
+
+    Ok(swaps)
+}
+
+/// Parses an interval such as `30s`, `15m`, `4h` or `1d` into seconds.
+pub fn parse_interval(interval: &str) -> Result<u64, anyhow::Error> {
+    let unit = interval
+        .chars()
+        .last()
+        .ok_or(anyhow::anyhow!("Empty interval"))?;
+    let (count, seconds) = match unit {
+        's' => (&interval[..interval.len() - 1], 1),
+        'm' => (&interval[..interval.len() - 1], 60),
+        'h' => (&interval[..interval.len() - 1], 3_600),
+        'd' => (&interval[..interval.len() - 1], 86_400),
+        _ => (interval, 1),
+    };
+    let count = count
+        .parse::<u64>()
+        .map_err(|_| anyhow::anyhow!("Invalid interval {}, try 15m, 1h or 1d", interval))?;
+    if count == 0 {
+        return Err(anyhow::anyhow!("Interval must be positive"));
+    }
+    Ok(count * seconds)
+}
+
+/// Buckets swaps, oldest first, into candles of `interval` seconds. Intervals without swaps are skipped.
+pub fn candles(swaps: &[Swap], interval: u64) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = Vec::new();
+    for swap in swaps {
+        let start = swap.timestamp - swap.timestamp % interval;
+        let trade = &swap.trade;
+        match candles.last_mut() {
+            Some(candle) if candle.start == start => {
+                candle.high = candle.high.max(trade.price);
+                candle.low = candle.low.min(trade.price);
+                candle.close = trade.price;
+                candle.spot_close = trade.spot_price;
+                candle.volume += trade.asset_amount();
+                candle.quote_volume += trade.quote_amount();
+                candle.swaps += 1;
+            }
+            _ => candles.push(Candle {
+                start,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                spot_close: trade.spot_price,
+                volume: trade.asset_amount(),
+                quote_volume: trade.quote_amount(),
+                swaps: 1,
+            }),
+        }
+    }
+    candles
+}
+
+fn swaps_csv(swaps: &[Swap]) -> String {
+    let mut csv = "timestamp,block,tx_hash,sell_asset,input,output,price,spot_price\n".to_string();
+    for swap in swaps {
+        let trade = &swap.trade;
+        let _ = writeln!(
+            csv,
+            "{},{},{:?},{},{},{},{},{}",
+            swap.timestamp,
+            trade.block,
+            trade.tx_hash,
+            trade.sell_asset,
+            trade.input,
+            trade.output,
+            trade.price,
+            trade.spot_price
+        );
+    }
+    csv
+}
+
+fn candles_csv(candles: &[Candle]) -> String {
+    let mut csv = "start,open,high,low,close,spot_close,volume,quote_volume,swaps\n".to_string();
+    for candle in candles {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{}",
+            candle.start,
+            candle.open,
+            candle.high,
+            candle.low,
+            candle.close,
+            candle.spot_close,
+            candle.volume,
+            candle.quote_volume,
+            candle.swaps
+        );
+    }
+    csv
+}
+
+fn time(timestamp: u64) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn print(pool_id: u64, swaps: &[Swap], interval: u64, trades: bool) {
+    let header_msg = format!(
+        "{} {} {} {}",
+        "History of pool".yellow().bold(),
+        pool_id.to_string().bold().magenta(),
+        swaps.len().to_string().bold().yellow(),
+        "swaps".yellow()
+    );
+    println!("{}", header_msg.on_black());
+
+    if trades {
+        for swap in swaps {
+            let trade = &swap.trade;
+            let swap_msg = format!(
+                "   - {} {} {} {} {} {} {} {} {}",
+                time(swap.timestamp).purple(),
+                if trade.sell_asset {
+                    "sell".red()
+                } else {
+                    "buy".green()
+                },
+                trade.asset_amount().to_string().bold().purple(),
+                "asset for".purple(),
+                trade.quote_amount().to_string().bold().purple(),
+                "quote @".purple(),
+                trade.price.to_string().bold().purple(),
+                "spot".purple(),
+                trade.spot_price.to_string().purple(),
+            );
+            println!("{}", swap_msg.on_black());
+        }
+        return;
+    }
+
+    for candle in candles(swaps, interval) {
+        let candle_msg = format!(
+            "   - {} {} {} {} {} {} {} {} {} {} {} {}",
+            time(candle.start).purple(),
+            "o".purple(),
+            candle.open.to_string().bold().purple(),
+            "h".purple(),
+            candle.high.to_string().bold().green(),
+            "l".purple(),
+            candle.low.to_string().bold().red(),
+            "c".purple(),
+            candle.close.to_string().bold().purple(),
+            "v".purple(),
+            candle.volume.to_string().bold().blue(),
+            format!("({} swaps)", candle.swaps).purple(),
+        );
+        println!("{}", candle_msg.on_black());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(timestamp: u64, sell_asset: bool, input: f64, output: f64) -> Swap {
+        Swap {
+            timestamp,
+            trade: Trade {
+                block: timestamp / 12,
+                tx_hash: H256::zero(),
+                pool_id: 1,
+                sell_asset,
+                input,
+                output,
+                price: if sell_asset {
+                    output / input
+                } else {
+                    input / output
+                },
+                spot_price: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("15m").unwrap(), 900);
+        assert_eq!(parse_interval("1d").unwrap(), 86_400);
+        assert_eq!(parse_interval("60").unwrap(), 60);
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("1w").is_err());
+    }
+
+    #[test]
+    fn buckets_swaps_into_candles() {
+        let swaps = [
+            swap(3_600, true, 1.0, 10.0),
+            swap(3_700, false, 30.0, 2.0),
+            swap(4_000, true, 1.0, 8.0),
+            swap(7_300, true, 2.0, 24.0),
+        ];
+
+        let candles = candles(&swaps, 3_600);
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].start, 3_600);
+        assert_eq!(
+            (
+                candles[0].open,
+                candles[0].high,
+                candles[0].low,
+                candles[0].close
+            ),
+            (10.0, 15.0, 8.0, 8.0)
+        );
+        assert_eq!(candles[0].volume, 4.0);
+        assert_eq!(candles[0].quote_volume, 48.0);
+        assert_eq!(candles[0].swaps, 3);
+        assert_eq!(candles[1].start, 7_200);
+        assert_eq!(candles[1].open, 12.0);
+    }
+}
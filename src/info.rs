@@ -1,14 +1,90 @@
-use super::Config;
-use bindings::i_portfolio_struct::{IPortfolioStruct, PortfolioPool};
+use super::events::{self, Pair, Trade, POOL_EVENTS};
+use super::greeks::{self, Risk, Scenario};
+use super::stats::{self, PoolStats};
+use super::utils::{read_block, PoolId};
+use super::{compat, Config};
+use crate::bindings::{
+    i_portfolio::IPortfolio,
+    i_portfolio_struct::{IPortfolioStruct, PortfolioPool},
+};
 use colored::Colorize;
 
-use ethers::{
-    prelude::*,
-    utils::{format_ether, parse_ether},
-};
+use ethers::{prelude::*, providers::StreamExt, utils::format_ether};
+use std::{collections::VecDeque, sync::Arc};
+
+/// Prices kept in the rolling history of `info --watch`.
+const HISTORY: usize = 40;
+/// Trades shown by `info --watch`.
+const RECENT_TRADES: usize = 8;
+/// Blocks searched for recent trades when a watch starts.
+const BACKFILL_BLOCKS: u64 = 5_000;
+
+/// State of a pool at the read block, shared by the commands reading pools.
+#[derive(Debug, Clone)]
+pub struct PoolState {
+    pub pool_id: u64,
+    pub block: u64,
+    pub pool: PortfolioPool,
+    pub spot_price: U256,
+}
+
+impl PoolState {
+    /// Reads the pool at the block of `--block`, or the latest one.
+    pub async fn fetch<M: Middleware + 'static>(
+        cfg: &Config,
+        client: Arc<M>,
+        pool_id: u64,
+    ) -> Result<Self, anyhow::Error> {
+        let block = read_block(cfg, &*client).await?;
+        Self::fetch_at(cfg, client, pool_id, block).await
+    }
+
+    /// Reads the pool at `block`, so several pools can be read at the same one.
+    pub async fn fetch_at<M: Middleware + 'static>(
+        cfg: &Config,
+        client: Arc<M>,
+        pool_id: u64,
+        block: u64,
+    ) -> Result<Self, anyhow::Error> {
+        let address = cfg.portfolio_address.parse::<Address>()?;
+        let pool = IPortfolioStruct::new(address, client.clone())
+            .pools(pool_id)
+            .block(block)
+            .call()
+            .await?;
+        let spot_price = IPortfolio::new(address, client)
+            .get_spot_price(pool_id)
+            .block(block)
+            .call()
+            .await?;
+
+        Ok(Self {
+            pool_id,
+            block,
+            pool,
+            spot_price,
+        })
+    }
+
+    /// Fields shown by `info --watch`, as wad decimals.
+    fn fields(&self) -> [(&'static str, f64); 4] {
+        let wad = |value: U256| format_ether(value).parse::<f64>().unwrap_or_default();
+        [
+            ("spot price", wad(self.spot_price)),
+            ("reserve x", wad(U256::from(self.pool.virtual_x))),
+            ("reserve y", wad(U256::from(self.pool.virtual_y))),
+            ("liquidity", wad(U256::from(self.pool.liquidity))),
+        ]
+    }
+}
 
-pub async fn main(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
-    let _ = cfg;
+pub async fn main(
+    cfg: &Config,
+    pool_id: &str,
+    json: bool,
+    with_stats: bool,
+    scenario: &Option<String>,
+) -> Result<(), anyhow::Error> {
     let start_info_msg = format!(
         "{} {} {} {}
         ",
@@ -17,27 +93,73 @@ pub async fn main(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
         "please be patient...".yellow(),
         " 🤗"
     );
-    println!("{}", start_info_msg.on_black());
+    // Kept out of the json output, so it stays parseable.
+    if !json {
+        println!("{}", start_info_msg.on_black());
+    }
 
     let ws_provider = Provider::<Ws>::connect(&cfg.rpc_url).await?;
     let client = std::sync::Arc::new(ws_provider);
 
     let pool_id = pool_id.parse::<u64>().unwrap();
 
-    let portfolio =
-        IPortfolioStruct::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let i_portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    compat::detect(cfg, &i_portfolio)
+        .await?
+        .require_all(&["pools", "getSpotPrice"])?;
 
-    let pool: PortfolioPool = portfolio.pools(pool_id).call().await?;
+    let shock = scenario
+        .as_deref()
+        .map(str::parse::<Scenario>)
+        .transpose()?;
+    let state = PoolState::fetch(cfg, client.clone(), pool_id).await?;
+    // Reading every swap of the last 30 days is slow, so the activity is only computed on request.
+    let stats = match with_stats {
+        true => Some(stats::fetch(cfg, client.clone(), &state).await?),
+        false => None,
+    };
+    // Pools whose strategy is not a NormalStrategy have no greeks, which shouldn't hide the rest of their info.
+    let risk = match Risk::fetch(client, &state).await {
+        Ok(risk) => Some(risk),
+        Err(err) => {
+            eprintln!("{} {}", "Skipping the pool's risk:".red(), err);
+            None
+        }
+    };
+    let greeks = risk.as_ref().map(|risk| risk.greeks(state.pool.liquidity));
+    let shocked = risk
+        .as_ref()
+        .zip(shock)
+        .map(|(risk, shock)| risk.revalue(&shock, state.pool.liquidity));
+    if json {
+        let output = serde_json::json!({
+            "pool_id": pool_id,
+            "block": state.block,
+            "spot_price": format_ether(state.spot_price),
+            "virtual_x": format_ether(state.pool.virtual_x),
+            "virtual_y": format_ether(state.pool.virtual_y),
+            "liquidity": format_ether(state.pool.liquidity),
+            "controller": format!("{:?}", state.pool.controller),
+            "strategy": format!("{:?}", state.pool.strategy),
+            "fee_bps": state.pool.fee_basis_points,
+            "priority_fee_bps": state.pool.priority_fee_basis_points,
+            "stats": stats,
+            "strategy_config": risk.as_ref().map(|risk| &risk.config),
+            "greeks": greeks,
+            "scenario": shocked,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    let PoolState {
+        block,
+        pool,
+        spot_price,
+        ..
+    } = state;
 
     let decoded = PoolId(pool_id).decode();
 
-    let spot_price: U256 = bindings::i_portfolio::IPortfolio::new(
-        cfg.portfolio_address.parse::<Address>()?,
-        client.clone(),
-    )
-    .get_spot_price(pool_id)
-    .call()
-    .await?;
     // Message to println at end
     // Header
     // Pool Info
@@ -47,6 +169,7 @@ pub async fn main(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
         "
         {}
         - id: {}
+        - block: {}
         {}
         - mark price: {}
         {}
@@ -63,6 +186,7 @@ pub async fn main(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
         - priority fee bps: {}",
         "Pool Info:".yellow().bold(),
         pool_id.to_string().yellow(),
+        block.to_string().yellow(),
         "Economic Info:".yellow().bold(),
         format_ether(spot_price).to_string().yellow(),
         "Pool State:".yellow().bold(),
@@ -80,25 +204,233 @@ pub async fn main(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
     );
     println!("{}", pool_info_msg.on_black());
 
+    if let Some(stats) = &stats {
+        print_stats(stats);
+    }
+
+    if let (Some(risk), Some(greeks)) = (&risk, &greeks) {
+        let config_msg = format!(
+            "        {}
+        - strike: {}
+        - volatility: {}
+        - years to expiry: {}",
+            "Strategy:".yellow().bold(),
+            risk.config.strike.to_string().yellow(),
+            format!("{}%", risk.config.volatility * 100.0).yellow(),
+            risk.config.tau.to_string().yellow(),
+        );
+        println!("{}", config_msg.on_black());
+        greeks::print("Risk:", greeks, scenario.as_deref().zip(shocked.as_ref()));
+    }
+
     Ok(())
 }
 
-/// Implements useful methods for pool-ids.
-struct PoolId(u64);
-
-impl PoolId {
-    /// Decodes the key information embedded into the poolId.
-    /// Pool ids are 64-bits, with the following information encoded:
-    /// - 0-4 bits: altered? bool
-    /// - 4-8 bits: controlled? bool
-    /// - 8-32 bits: pair nonce u24
-    /// - 32-64 bits: pool nonce u32
-    fn decode(&self) -> (bool, bool, u32, u32) {
-        let altered = self.0 & 0b0000_0000_0000_0000_0000_0000_0000_0001 != 0;
-        let controlled = self.0 & 0b0000_0000_0000_0000_0000_0000_0000_0010 != 0;
-        let pair_nonce = (self.0 & 0b0000_0000_0000_0000_0000_0011_1111_1100) >> 2;
-        let pool_nonce = (self.0 & 0b1111_1111_1111_1111_1111_1100_0000_0000) >> 10;
-
-        (altered, controlled, pair_nonce as u32, pool_nonce as u32)
+fn print_stats(stats: &PoolStats) {
+    let tvl_msg = format!(
+        "        {}
+        - tvl (quote): {}",
+        "Activity:".yellow().bold(),
+        stats.tvl.to_string().yellow()
+    );
+    println!("{}", tvl_msg.on_black());
+    for window in &stats.windows {
+        let window_msg = format!(
+            "        - {}: {} {} {} {} {} {} {} {} {} {}",
+            window.window.bold().yellow(),
+            "volume".yellow(),
+            format!(
+                "{} asset / {} quote,",
+                window.volume_asset, window.volume_quote
+            )
+            .yellow(),
+            "fees".yellow(),
+            format!("{} asset / {} quote,", window.fees_asset, window.fees_quote).yellow(),
+            window.swaps.to_string().bold().yellow(),
+            "swaps,".yellow(),
+            window.traders.to_string().bold().yellow(),
+            "traders,".yellow(),
+            "apr".yellow(),
+            format!("{:.2}%", window.apr * 100.0).bold().green(),
+        );
+        println!("{}", window_msg.on_black());
+    }
+}
+
+/// Handles `info --watch`, refreshing the pool on every new block and on its Swap, Allocate and Deallocate logs.
+pub async fn watch(cfg: &Config, pool_id: &str) -> Result<(), anyhow::Error> {
+    let pool_id = pool_id.parse::<u64>()?;
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, provider.clone());
+    compat::detect(cfg, &portfolio)
+        .await?
+        .require_all(&["pairs", "pools", "getSpotPrice"])?;
+
+    let pair = Pair::from(portfolio.pairs(PoolId(pool_id).pair_id()).call().await?);
+    let filter = events::filter(portfolio.address(), POOL_EVENTS, Some(pool_id));
+
+    let mut state = PoolState::fetch(cfg, provider.clone(), pool_id).await?;
+    let mut history = VecDeque::from([state.fields()[0].1]);
+    let from_block = state
+        .block
+        .saturating_sub(BACKFILL_BLOCKS)
+        .max(cfg.start_block);
+    let mut trades: VecDeque<Trade> = provider
+        .get_logs(&filter.clone().from_block(from_block).to_block(state.block))
+        .await?
+        .iter()
+        .filter_map(|log| Trade::from_log(log, &pair))
+        .collect();
+    while trades.len() > RECENT_TRADES {
+        trades.pop_front();
+    }
+    render(&state, None, &history, &trades);
+
+    let mut blocks = provider.subscribe_blocks().await?;
+    let mut logs = provider.subscribe_logs(&filter).await?;
+    loop {
+        tokio::select! {
+            block = blocks.next() => {
+                if block.is_none() {
+                    break;
+                }
+            }
+            log = logs.next() => {
+                let Some(log) = log else {
+                    break;
+                };
+                if log.removed == Some(true) {
+                    continue;
+                }
+                if let Some(trade) = Trade::from_log(&log, &pair) {
+                    trades.push_back(trade);
+                    if trades.len() > RECENT_TRADES {
+                        trades.pop_front();
+                    }
+                }
+            }
+        }
+
+        let next = PoolState::fetch(cfg, provider.clone(), pool_id).await?;
+        history.push_back(next.fields()[0].1);
+        if history.len() > HISTORY {
+            history.pop_front();
+        }
+        render(&next, Some(&state), &history, &trades);
+        state = next;
+    }
+
+    Err(anyhow::anyhow!("info.rs: Subscription closed by the node"))
+}
+
+/// Redraws the watch dashboard, with the deltas since the `previous` update.
+fn render(
+    state: &PoolState,
+    previous: Option<&PoolState>,
+    history: &VecDeque<f64>,
+    trades: &VecDeque<Trade>,
+) {
+    // Clears the terminal and moves the cursor home.
+    print!("\x1B[2J\x1B[H");
+
+    let header_msg = format!(
+        "{} {} {} {} {}",
+        "Watching pool".yellow().bold(),
+        state.pool_id.to_string().bold().magenta(),
+        "at block".yellow(),
+        state.block.to_string().bold().yellow(),
+        "(ctrl-c to exit)".yellow()
+    );
+    println!("{}", header_msg.on_black());
+
+    let previous = previous.map(|previous| previous.fields());
+    for (i, (name, value)) in state.fields().iter().enumerate() {
+        let delta = match &previous {
+            Some(previous) => value - previous[i].1,
+            None => 0.0,
+        };
+        let delta = if delta > 0.0 {
+            format!("(+{})", delta).green()
+        } else if delta < 0.0 {
+            format!("({})", delta).red()
+        } else {
+            "".normal()
+        };
+        let field_msg = format!(
+            "   - {} {} {}",
+            format!("{}:", name).yellow(),
+            value.to_string().bold().yellow(),
+            delta
+        );
+        println!("{}", field_msg.on_black());
+    }
+
+    let min = history.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = history.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let history_msg = format!(
+        "{} {} {}",
+        "Price history:".yellow().bold(),
+        sparkline(history).bold().blue(),
+        format!("[{} - {}]", min, max).yellow()
+    );
+    println!("\n{}", history_msg.on_black());
+
+    println!("\n{}", "Recent trades:".yellow().bold().on_black());
+    if trades.is_empty() {
+        println!("{}", "   - none".purple().on_black());
+    }
+    for trade in trades.iter().rev() {
+        let trade_msg = format!(
+            "   - {} {} {} {} {} {} {}",
+            format!("#{}", trade.block).purple(),
+            if trade.sell_asset {
+                "sell".red()
+            } else {
+                "buy".green()
+            },
+            trade.asset_amount().to_string().bold().purple(),
+            "asset for".purple(),
+            trade.quote_amount().to_string().bold().purple(),
+            "quote @".purple(),
+            trade.price.to_string().bold().purple(),
+        );
+        println!("{}", trade_msg.on_black());
+    }
+}
+
+/// Draws values as a line of block characters scaled between their min and max.
+pub fn sparkline<'a>(values: impl IntoIterator<Item = &'a f64> + Clone) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = values
+        .clone()
+        .into_iter()
+        .cloned()
+        .fold(f64::INFINITY, f64::min);
+    let max = values
+        .clone()
+        .into_iter()
+        .cloned()
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    values
+        .into_iter()
+        .map(|value| {
+            if max > min {
+                BARS[(((value - min) / (max - min)) * 7.0).round() as usize]
+            } else {
+                BARS[3]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkline_scales_between_min_and_max() {
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0]), "▁▅█");
+        assert_eq!(sparkline(&[5.0, 5.0]), "▄▄");
     }
 }
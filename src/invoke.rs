@@ -1,14 +1,91 @@
-use super::{act, actions, info, list, App, Commands, Config};
+use super::{
+    account, act, actions, backtest, batch, bot, decode, encode, history, info, list, optimize,
+    orders, pnl, snapshot, tail, tui, twap, tx, App, Commands, Config,
+};
+use crate::fork::Fork;
 use async_recursion::async_recursion;
 
-/// Handles invoking commands from the cli or other modules.
+/// Handles invoking commands from the cli, pointed at the fork if one is running.
+pub async fn main(args: &App, fork: Option<&Fork>) -> Result<(), anyhow::Error> {
+    let mut settings = Config::new()?;
+    if let Some(fork) = fork {
+        fork.apply(&mut settings);
+    }
+    #[cfg(runtime_abi)]
+    crate::bindings::init(&settings.abi_dir)?;
+    run(&settings, args).await
+}
+
+/// Whether a command acts as the signer, which `--fork` then impersonates.
+/// Commands only reading state run on the fork without a key, and the tui only trades when it has one.
+pub fn needs_signer(args: &App) -> bool {
+    match &args.command {
+        Some(Commands::Encode(encode)) => encode.account.is_none(),
+        Some(Commands::Tui {}) => {
+            args.fork.fork_account.is_some() || std::env::var("PRIVATE_KEY").is_ok()
+        }
+        command => matches!(
+            command,
+            Some(Commands::Act(_))
+                | Some(Commands::Batch(_))
+                | Some(Commands::Tx(_))
+                | Some(Commands::Bot(_))
+                | Some(Commands::Twap(_))
+                | Some(Commands::Orders(_))
+        ),
+    }
+}
+
+/// Runs a command with the given settings, also used by other modules to chain commands.
 #[async_recursion(?Send)]
-pub async fn main(args: &App) -> Result<(), anyhow::Error> {
-    let settings: Config = Config::new().unwrap();
+pub async fn run(settings: &Config, args: &App) -> Result<(), anyhow::Error> {
+    // Commands sending transactions or following new blocks always use the latest state.
+    let live = matches!(
+        &args.command,
+        Some(Commands::Act(_))
+            | Some(Commands::Batch(_))
+            | Some(Commands::Tx(_))
+            | Some(Commands::Encode(_))
+            | Some(Commands::Tail(_))
+            | Some(Commands::Bot(_))
+            | Some(Commands::Twap(_))
+            | Some(Commands::Orders(_))
+            | Some(Commands::Info { watch: true, .. })
+    );
+    if live && settings.block.is_some() {
+        return Err(anyhow::anyhow!(
+            "--block only applies to commands reading state, this command uses the latest block"
+        ));
+    }
+
     match &args.command {
-        Some(Commands::List {}) => list::list_pools(&settings).await?,
-        Some(Commands::Info { pool_id }) => info::main(&settings, pool_id).await?,
-        Some(Commands::Act(args)) => act::main(&settings, args).await?,
+        Some(Commands::List { sort, json }) => list::list_pools(settings, sort, *json).await?,
+        Some(Commands::Info {
+            pool_id,
+            watch,
+            json,
+            stats,
+            scenario,
+        }) => match watch {
+            true => info::watch(settings, pool_id).await?,
+            false => info::main(settings, pool_id, *json, *stats, scenario).await?,
+        },
+        Some(Commands::Act(args)) => act::main(settings, args).await?,
+        Some(Commands::Batch(args)) => batch::main(settings, args).await?,
+        Some(Commands::Tx(args)) => tx::main(settings, args).await?,
+        Some(Commands::Decode(args)) => decode::main(settings, args).await?,
+        Some(Commands::Encode(args)) => encode::main(settings, args).await?,
+        Some(Commands::Tui {}) => tui::main(settings).await?,
+        Some(Commands::Tail(args)) => tail::main(settings, args).await?,
+        Some(Commands::History(args)) => history::main(settings, args).await?,
+        Some(Commands::Snapshot(args)) => snapshot::main(settings, args).await?,
+        Some(Commands::Pnl(args)) => pnl::main(settings, args).await?,
+        Some(Commands::Account(args)) => account::main(settings, args).await?,
+        Some(Commands::Backtest(args)) => backtest::main(args)?,
+        Some(Commands::Optimize(args)) => optimize::main(args)?,
+        Some(Commands::Bot(args)) => bot::main(settings, args).await?,
+        Some(Commands::Twap(args)) => twap::main(settings, args).await?,
+        Some(Commands::Orders(args)) => orders::main(settings, args).await?,
         None => {
             println!("no command");
         }
@@ -1,18 +1,73 @@
 use colored::Colorize;
 
 use super::{ActArgs, App, Commands::Act, Config};
+use crate::info::PoolState;
 use crate::invoke;
+use crate::stats::{self, PoolStats};
+use crate::utils::read_block;
 use anyhow;
 use clap::{Arg, Command};
 use ethers::prelude::*;
 
 use inquire::{formatter::OptionFormatter, Select};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::bindings::i_portfolio::{CreatePoolFilter, IPortfolio};
+
+/// Fetches the CreatePool events of the configured Portfolio up to the read block, oldest first.
+pub async fn created_pools<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+) -> Result<Vec<CreatePoolFilter>, anyhow::Error> {
+    let address = cfg.portfolio_address.parse::<Address>()?;
+    let to_block = read_block(cfg, &*client).await?;
+    let events = IPortfolio::new(address, client)
+        .create_pool_filter()
+        .from_block(cfg.start_block)
+        .to_block(to_block)
+        .query()
+        .await?;
+    Ok(events)
+}
+
+/// Activity of the pools at the read block, sorted by `sort` in descending order if set.
+async fn pool_stats<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    events: &[CreatePoolFilter],
+    sort: Option<&str>,
+) -> Result<Vec<PoolStats>, anyhow::Error> {
+    if let Some(key) = sort {
+        if !stats::is_key(key) {
+            return Err(anyhow::anyhow!(
+                "Invalid sort key {}, try tvl, volume_24h, fees_7d or apr_30d",
+                key
+            ));
+        }
+    }
 
-use bindings::i_portfolio::{CreatePoolFilter, IPortfolio};
+    let block = read_block(cfg, &*client).await?;
+    let mut pools = Vec::with_capacity(events.len());
+    for event in events {
+        let state = PoolState::fetch_at(cfg, client.clone(), event.pool_id, block).await?;
+        pools.push(stats::fetch(cfg, client.clone(), &state).await?);
+    }
+    if let Some(key) = sort {
+        pools.sort_by(|a, b| {
+            b.key(key)
+                .unwrap_or_default()
+                .total_cmp(&a.key(key).unwrap_or_default())
+        });
+    }
+    Ok(pools)
+}
 
 /// Lists the pools of a Portfolio contract.
-pub async fn list_pools(cfg: &Config) -> Result<(), anyhow::Error> {
+pub async fn list_pools(
+    cfg: &Config,
+    sort: &Option<String>,
+    json: bool,
+) -> Result<(), anyhow::Error> {
     let ws_provider = Provider::<Ws>::connect(&cfg.rpc_url).await?;
     let ws_client = Arc::new(ws_provider);
     let connected_msg = format!(
@@ -23,17 +78,32 @@ pub async fn list_pools(cfg: &Config) -> Result<(), anyhow::Error> {
         "\n   - Portfolio:".yellow(),
         &cfg.portfolio_address.bold().yellow()
     );
-    println!("{}", connected_msg.on_black());
+    // Kept out of the json output, so it stays parseable.
+    if !json {
+        println!("{}", connected_msg.on_black());
+    }
 
-    let start_block = 3982259;
-    let address = (&cfg.portfolio_address).parse::<Address>()?;
-    let contract = IPortfolio::new(address, ws_client);
+    let mut events = created_pools(cfg, ws_client.clone()).await?;
 
-    let events: Vec<CreatePoolFilter> = contract
-        .create_pool_filter()
-        .from_block(start_block)
-        .query()
-        .await?;
+    let mut sorted = HashMap::<u64, f64>::new();
+    if sort.is_some() || json {
+        let pools = pool_stats(cfg, ws_client, &events, sort.as_deref()).await?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&pools)?);
+            return Ok(());
+        }
+        if let Some(key) = sort {
+            for (i, pool) in pools.iter().enumerate() {
+                sorted.insert(pool.pool_id, pool.key(key).unwrap_or_default());
+                if let Some(event) = events
+                    .iter()
+                    .position(|event| event.pool_id == pool.pool_id)
+                {
+                    events.swap(i, event);
+                }
+            }
+        }
+    }
 
     let listing_pools_msg = format!("{}{}", "Listing pools... please be patient".yellow(), " 🤗");
     println!("{}", listing_pools_msg.on_black());
@@ -53,11 +123,16 @@ pub async fn list_pools(cfg: &Config) -> Result<(), anyhow::Error> {
         pool_ids.push(event.pool_id);
 
         let pool_list_msg = format!(
-            "   - {}{} {} {}",
+            "   - {}{} {} {} {}",
             "#".purple(),
             i.to_string().bold().purple(),
             "- id:".purple(),
-            event.pool_id.to_string().bold().purple()
+            event.pool_id.to_string().bold().purple(),
+            match (sort, sorted.get(&event.pool_id)) {
+                (Some(key), Some(value)) => format!("- {}: {}", key, value),
+                _ => String::new(),
+            }
+            .purple()
         );
         println!("{}", pool_list_msg.on_black());
     }
@@ -96,9 +171,17 @@ pub async fn list_pools(cfg: &Config) -> Result<(), anyhow::Error> {
                             let args = App {
                                 command: Some(super::Commands::Info {
                                     pool_id: selected_pool_id.to_string(),
+                                    watch: false,
+                                    json: false,
+                                    stats: false,
+                                    scenario: None,
                                 }),
+                                gas: Default::default(),
+                                fork: Default::default(),
+                                block: Default::default(),
+                                approval: Default::default(),
                             };
-                            invoke::main(&args).await?;
+                            invoke::run(cfg, &args).await?;
                         }
                         "swap" => {
                             let sell_asset =
@@ -132,8 +215,12 @@ pub async fn list_pools(cfg: &Config) -> Result<(), anyhow::Error> {
                                     function: "swap".to_string(),
                                     verbose: None,
                                 })),
+                                gas: Default::default(),
+                                fork: Default::default(),
+                                block: Default::default(),
+                                approval: Default::default(),
                             };
-                            invoke::main(&args).await?;
+                            invoke::run(cfg, &args).await?;
                         }
                         _ => {
                             println!("Error: Invalid command");
@@ -1,6 +1,11 @@
 use alloy_primitives::Address;
 use clap::{Args, Parser, Subcommand};
 
+use actions::{
+    allocate::AllocateArgs, change_parameters::ChangeParametersArgs, claim::ClaimArgs,
+    create_pair::CreatePairArgs, create_pool::CreatePoolArgs, deallocate::DeallocateArgs,
+    swap::SwapArgs,
+};
 use colored::Colorize;
 use dotenv::dotenv;
 use ethers::abi::Token;
@@ -9,12 +14,42 @@ use figment::{
     Figment,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
+mod abi;
+mod account;
 mod act;
 mod actions;
+mod backtest;
+mod batch;
+mod bot;
+// Forge generated bindings when built, or ABIs loaded at runtime without them or with the `runtime-abi` feature.
+#[cfg_attr(not(runtime_abi), path = "../out/bindings/src/lib.rs")]
+#[allow(dead_code)]
+mod bindings;
+mod compat;
+mod decode;
+mod encode;
+mod events;
+mod fork;
+mod gas;
+mod greeks;
+mod history;
 mod info;
 mod invoke;
 mod list;
+mod nonce;
+mod optimize;
+mod orders;
+mod pnl;
+mod sim;
+mod snapshot;
+mod stats;
+mod tail;
+mod tokens;
+mod tui;
+mod twap;
+mod tx;
 mod utils;
 
 /// # Portfolio rs
@@ -24,13 +59,32 @@ mod utils;
 /// - `list` - Lists all the pools, including pool id, tokens, and estimated TVL if available.
 /// - `info` - Prints a pool's state and configuration, if any.
 /// - `action` - Performs an action on a pool, such as swap, add liquidity, remove liquidity, etc. [Required] Settings in portfolio.toml.
+/// - `batch` - Executes a plan of several actions atomically in one `multicall`.
+/// - `tx` - Speeds up or cancels a pending transaction.
+/// - `decode` - Decodes calldata, a transaction or a receipt into readable calls and events.
+/// - `encode` - Builds the calldata of a Portfolio call without sending it.
+/// - `history` - Rebuilds the price history of a pool from its swaps, as OHLCV candles.
+/// - `pnl` - Splits the result of an account's liquidity positions into fees, divergence loss and net PnL.
+/// - `account` - Prints an account's liquidity positions with their greeks.
+/// - `backtest` - Replays historical or generated prices through a pool configuration against holding.
+/// - `optimize` - Ranks a grid of pool configurations over simulated prices.
+/// - `bot` - Runs a bot arbitraging a pool against a reference price, or rebalancing a liquidity position.
+/// - `twap` - Splits a large swap into slices sent over a duration, within the swap price band.
+/// - `orders` - Places, lists and cancels limit orders, filled by a daemon watching the pools.
+/// - `snapshot` - Dumps the state of every pool at a block to json.
+/// - `tail` - Streams the events of Portfolio as they are emitted.
+/// - `tui` - Opens a terminal ui of the pools, positions and an order ticket.
 #[tokio::main]
 async fn main() -> anyhow::Result<(), anyhow::Error> {
     dotenv().ok();
 
     println!("{}", WELCOME.yellow().on_black());
     let args = App::parse();
-    invoke::main(&args).await?;
+    let fork = fork::start(&args.fork, invoke::needs_signer(&args)).await?;
+    invoke::main(&args, fork.as_ref()).await?;
+    if let Some(fork) = fork {
+        fork.report().await?;
+    }
     println!("{}", "Exiting...".red());
 
     Ok(())
@@ -52,6 +106,8 @@ struct Swap {
     pool_id: String,
     min_price: f64,
     max_price: f64,
+    /// Tolerated slippage from the quoted output of scheduled swaps, as a fraction.
+    slippage: f64,
 }
 
 impl Default for Swap {
@@ -60,6 +116,74 @@ impl Default for Swap {
             pool_id: "".to_string(),
             min_price: 0.0,
             max_price: 0.0,
+            slippage: 0.005,
+        }
+    }
+}
+
+impl Swap {
+    /// Whether a price is within the `min_price` and `max_price` band, always if no `max_price` is set.
+    fn in_band(&self, price: f64) -> bool {
+        self.max_price <= 0.0 || (self.min_price..=self.max_price).contains(&price)
+    }
+}
+
+/// Gas defaults of a network, keyed by chain id in the `gas` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+struct Gas {
+    /// Max fee per gas, in gwei.
+    max_fee: Option<f64>,
+    /// Max priority fee per gas, in gwei.
+    priority_fee: Option<f64>,
+    /// Gas limit used instead of the estimate.
+    gas_limit: Option<u64>,
+    /// Multiplier applied to the estimated gas limit.
+    gas_multiplier: Option<f64>,
+    /// Refuses to send transactions whose estimated cost exceeds this, in native token.
+    max_cost: Option<f64>,
+    /// Pool of wrapped native token and the quote token, used to show costs in the quote token.
+    quote_pool_id: Option<u64>,
+}
+
+/// Configuration of `bot arb`, in the `arb` table.
+#[derive(Debug, Clone, Deserialize)]
+#[allow(unused)]
+#[serde(default)]
+struct Arb {
+    /// Source of the reference price: `http`, `csv` or `pool`.
+    source: String,
+    /// Json endpoint of the `http` source.
+    url: String,
+    /// Dotted path of the price in the endpoint's response, such as `data.price`.
+    price_path: String,
+    /// Csv of the `csv` source, replayed one price per block.
+    csv: String,
+    /// Pool whose spot price is the `pool` source.
+    reference_pool_id: u64,
+    /// Profit a trade must make after gas, in quote tokens.
+    min_profit: f64,
+    /// Gas used by a swap, to cost a trade before sending it.
+    swap_gas: u64,
+    /// Price of the native token in quote tokens, to cost gas on networks without a `quote_pool_id`.
+    native_price: f64,
+    /// Tolerated slippage from the quoted output, as a fraction.
+    slippage: f64,
+}
+
+impl Default for Arb {
+    fn default() -> Self {
+        Self {
+            source: "http".to_string(),
+            url: "".to_string(),
+            price_path: "price".to_string(),
+            csv: "".to_string(),
+            reference_pool_id: 0,
+            min_profit: 0.0,
+            swap_gas: 200_000,
+            native_price: 0.0,
+            slippage: 0.005,
         }
     }
 }
@@ -73,7 +197,30 @@ pub struct Config {
     rpc_url: String,
     factory_address: String,
     portfolio_address: String,
+    /// Block to start indexing Portfolio events from, usually its deployment block.
+    start_block: u64,
     swap: Swap,
+    arb: Arb,
+    gas: HashMap<String, Gas>,
+    /// Confirmations to wait for after sending a transaction.
+    confirmations: usize,
+    /// Percent by which replacement transactions raise the fees of a stuck one.
+    fee_bump: f64,
+    /// Directory of the local state files, such as the nonces of each signer.
+    state_dir: String,
+    /// Directory of json ABIs replacing the bundled ones when built without the forge bindings.
+    abi_dir: String,
+    /// How short allowances are approved without prompting: `exact`, `unlimited` or `permit`.
+    /// Required by the commands running unattended, such as the bots.
+    approval: String,
+    #[serde(skip)]
+    gas_args: GasArgs,
+    /// Block read by the commands, from the `--block` flag.
+    #[serde(skip)]
+    block: Option<String>,
+    /// Account impersonated on a `--fork`, whose transactions are sent unsigned.
+    #[serde(skip)]
+    impersonate: Option<ethers::types::Address>,
 }
 
 impl Default for Config {
@@ -83,18 +230,38 @@ impl Default for Config {
             rpc_url: "https://mainnet.infura.io/v3/".to_string(),
             factory_address: Address::ZERO.to_string(),
             portfolio_address: Address::ZERO.to_string(),
+            start_block: 3982259,
             swap: Swap::default(),
+            arb: Arb::default(),
+            gas: HashMap::new(),
+            confirmations: 1,
+            fee_bump: 12.5,
+            state_dir: ".portfolio".to_string(),
+            abi_dir: "abi".to_string(),
+            approval: "".to_string(),
+            gas_args: GasArgs::default(),
+            block: None,
+            impersonate: None,
         }
     }
 }
 
 impl Config {
     pub fn new() -> Result<Self, figment::Error> {
-        Figment::new()
+        let app = App::parse();
+        let mut config: Config = Figment::new()
             .merge(Toml::file("portfolio.toml").nested())
             .merge(Env::prefixed("PORTFOLIO_"))
-            .join(Serialized::defaults(App::parse()))
-            .extract()
+            .join(Serialized::defaults(&app))
+            .extract()?;
+
+        // Cli gas flags take precedence over the per-network defaults.
+        config.gas_args = app.gas;
+        config.block = app.block.block;
+        if let Some(approval) = app.approval.approval {
+            config.approval = approval;
+        }
+        Ok(config)
     }
 }
 
@@ -110,26 +277,168 @@ impl Config {
 pub struct App {
     #[command(subcommand)]
     command: Option<Commands>,
+    #[command(flatten)]
+    #[serde(skip)]
+    gas: GasArgs,
+    #[command(flatten)]
+    #[serde(skip)]
+    fork: ForkArgs,
+    #[command(flatten)]
+    #[serde(skip)]
+    block: BlockArgs,
+    #[command(flatten)]
+    #[serde(skip)]
+    approval: ApprovalArgs,
+}
+
+/// # Fork
+/// Rehearses a command against a local anvil node instead of the configured network.
+///
+/// ### Usage
+/// $ port --fork <block> <command>
+/// $ port --fork-state <state.json> <command>
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+pub struct ForkArgs {
+    /// Forks the configured network at this block, reusing anvil's local rpc cache.
+    #[arg(long, global = true)]
+    fork: Option<u64>,
+    /// Loads an anvil state dump into the local node, with or without `--fork`.
+    #[arg(long, global = true)]
+    fork_state: Option<String>,
+    /// Account impersonated on the fork, the `PRIVATE_KEY` account by default. No key is needed when it is set.
+    #[arg(long, global = true)]
+    fork_account: Option<String>,
+}
+
+/// # Block
+/// Reads the state of Portfolio at a past block instead of the latest one.
+///
+/// ### Usage
+/// $ port --block <number|hash|tag> <command>
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+pub struct BlockArgs {
+    /// Block to read pools, prices, positions and tokens at, as a number, a hash or a tag such as `finalized`.
+    #[arg(long, global = true)]
+    block: Option<String>,
+}
+
+/// # Approval
+/// Approves short allowances without prompting, as needed by the bots, `twap` and `orders watch`.
+///
+/// ### Usage
+/// $ port --approval <exact|unlimited|permit> <command>
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+pub struct ApprovalArgs {
+    /// Approves the exact amount, an unlimited amount or signs a permit, instead of the `approval` of the config.
+    #[arg(long, global = true)]
+    approval: Option<String>,
+}
+
+/// # Gas
+/// Fee controls applied to every transaction sent by the cli.
+///
+/// ### Usage
+/// $ port --max-fee <gwei> --priority-fee <gwei> --gas-limit <units> --gas-multiplier <x> <command>
+#[derive(Debug, Clone, Default, Args, Serialize, Deserialize)]
+pub struct GasArgs {
+    /// Max fee per gas, in gwei.
+    #[arg(long, global = true)]
+    max_fee: Option<f64>,
+    /// Max priority fee per gas, in gwei.
+    #[arg(long, global = true)]
+    priority_fee: Option<f64>,
+    /// Gas limit used instead of the estimate.
+    #[arg(long, global = true)]
+    gas_limit: Option<u64>,
+    /// Multiplier applied to the estimated gas limit.
+    #[arg(long, global = true)]
+    gas_multiplier: Option<f64>,
 }
 
 /// ## Subcommands.
 /// Subcommands for the main program.
 ///
 /// ### Usage
-/// $ port list
-/// $ port info -p <pool_id>
+/// $ port list [--sort <key>] [--json]
+/// $ port info -p <pool_id> [--watch] [--json] [--scenario price=+10%]
 /// $ port act -p <pool_id> -a <action>
+/// $ port batch <plan>
+/// $ port tx speedup <hash>
+/// $ port decode <calldata|hash|receipt>
+/// $ port encode <function> <flags>
+/// $ port history <pool_id> [--interval 1h] [--csv|--json]
+/// $ port pnl <account> [--pool <pool_id>] [--csv]
+/// $ port account <account> [--pool <pool_id>] [--scenario price=+10%]
+/// $ port backtest --strike <strike> --volatility-bps <bps> --duration-days <days> --fee-bps <bps> [--prices <csv>]
+/// $ port optimize --strikes <strikes> --volatilities-bps <bps> --price <price> --realized-volatility <vol> [--report <path>] [--plan <path>]
+/// $ port bot arb -p <pool_id> [--once] [--dry-run]
+/// $ port bot rebalance -p <pool_id> --policy <path> [--once] [--dry-run]
+/// $ port twap -p <pool_id> --amount <amount> [--sell-asset] [--slices <n>] [--duration <1h>]
+/// $ port orders place -p <pool_id> --amount <amount> --price <limit> [--sell-asset]
+/// $ port orders watch [--once] [--dry-run]
+/// $ port snapshot [--output <path>]
+/// $ port tail [--pool <pool_id>] [--json]
+/// $ port tui
 #[derive(Subcommand, Debug, Serialize, Deserialize)]
 pub enum Commands {
     /// Lists all the pools.
-    List {},
+    List {
+        /// Sorts pools by `tvl`, or by `volume`, `fees`, `apr`, `swaps` or `traders` over `24h`, `7d` or `30d`, such as `apr_7d`.
+        #[arg(short, long)]
+        sort: Option<String>,
+        /// Prints the pools with their activity as json.
+        #[arg(short, long)]
+        json: bool,
+    },
     /// Prints a pool's state and configuration.
     Info {
         #[arg(short, long)]
         pool_id: String,
+        /// Keeps refreshing the pool on every block and trade.
+        #[arg(short, long)]
+        watch: bool,
+        /// Prints the pool as json.
+        #[arg(short, long)]
+        json: bool,
+        /// Computes the pool's activity over the last 24h, 7d and 30d, which reads every swap in them.
+        #[arg(long)]
+        stats: bool,
+        /// Revalues the pool's liquidity after shocks, such as `price=+10%,vol=-20%,days=7`.
+        #[arg(long)]
+        scenario: Option<String>,
     },
     /// Performs an action on a pool, such as swap, add liquidity, remove liquidity, etc.
     Act(ActArgs),
+    /// Executes several actions atomically in one multicall.
+    Batch(BatchArgs),
+    /// Replaces a pending transaction.
+    Tx(TxArgs),
+    /// Decodes calldata, a transaction or a receipt.
+    Decode(DecodeArgs),
+    /// Builds the calldata of a Portfolio call.
+    Encode(EncodeArgs),
+    /// Opens a terminal ui of the pools, positions and an order ticket.
+    Tui {},
+    /// Streams the events of Portfolio.
+    Tail(TailArgs),
+    /// Rebuilds the price history of a pool from its swaps.
+    History(HistoryArgs),
+    /// Dumps the state of every pool to json.
+    Snapshot(SnapshotArgs),
+    /// Rebuilds the performance of an account's liquidity positions.
+    Pnl(PnlArgs),
+    /// Prints the liquidity positions of an account with their greeks.
+    Account(AccountArgs),
+    /// Simulates a pool configuration against historical or generated prices.
+    Backtest(BacktestArgs),
+    /// Ranks pool configurations over simulated prices.
+    Optimize(OptimizeArgs),
+    /// Runs a bot trading with the signer's funds.
+    Bot(BotArgs),
+    /// Splits a swap into slices sent over a duration.
+    Twap(TwapArgs),
+    /// Manages limit orders and fills them when executable.
+    Orders(OrdersArgs),
 }
 
 /// # Act
@@ -156,6 +465,441 @@ pub struct ActArgs {
     args: Option<Vec<String>>,
 }
 
+/// # Batch
+/// Executes a plan of actions atomically through Portfolio's `multicall`.
+///
+/// ## BatchArgs
+/// Pass the path to a toml or json plan listing the steps to execute.
+///
+/// ### Usage
+/// $ port batch <plan> [--yes]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct BatchArgs {
+    /// Path to the toml or json plan.
+    plan: String,
+    /// Sends the batch without asking for confirmation.
+    #[arg(short, long)]
+    yes: bool,
+    /// Print all available logs while the batch is pending.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// # Tx
+/// Replaces a stuck transaction of the signer, reusing its nonce with higher fees.
+///
+/// ### Usage
+/// $ port tx speedup <hash>
+/// $ port tx cancel <hash>
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct TxArgs {
+    #[command(subcommand)]
+    command: TxCommands,
+}
+
+/// ## Tx subcommands.
+#[derive(Subcommand, Debug, Serialize, Deserialize)]
+pub enum TxCommands {
+    /// Resends the transaction with higher fees.
+    Speedup { hash: String },
+    /// Replaces the transaction with an empty transfer to the signer.
+    Cancel { hash: String },
+}
+
+/// # Decode
+/// Decodes Portfolio, strategy and ERC20 calldata and events, including the calls nested in a `multicall`.
+/// Amounts are scaled by the decimals of their tokens when the rpc is reachable.
+///
+/// ### Usage
+/// $ port decode <calldata>
+/// $ port decode <tx_hash> [--json]
+/// $ port decode <receipt.json>
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct DecodeArgs {
+    /// Hex calldata, a transaction hash, or the path to a json receipt.
+    input: String,
+    /// Prints the decoded call and events as json.
+    #[arg(short, long)]
+    json: bool,
+}
+
+/// # Encode
+/// Builds the calldata of a Portfolio call with the same validation as the actions, without sending it.
+/// Swaps are quoted and pools are priced over rpc.
+///
+/// ### Usage
+/// $ port encode allocate --pool-id <pool_id> --liquidity <wad> --max-asset <amount> --max-quote <amount>
+/// $ port encode multicall <plan>
+/// $ port encode --spec <json>
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct EncodeArgs {
+    #[command(subcommand)]
+    command: Option<EncodeCommands>,
+    /// Json step, or plan with `steps` encoded as a multicall, inline or as a file path.
+    #[arg(long)]
+    spec: Option<String>,
+    /// Account the calls are built for, such as the recipient of allocates. Defaults to the signer.
+    #[arg(long)]
+    account: Option<String>,
+}
+
+/// # Tail
+/// Streams the events of Portfolio as they are emitted, with amounts scaled by the decimals of their tokens.
+/// A dropped connection is resumed from the last seen block.
+///
+/// ### Usage
+/// $ port tail [--pool <pool_id>] [--pair <pair_id>] [--account <address>] [--event <name>]... [--json]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct TailArgs {
+    /// Only follows the events of this pool.
+    #[arg(long)]
+    pool: Option<u64>,
+    /// Only follows the events of the pools of this pair.
+    #[arg(long)]
+    pair: Option<u32>,
+    /// Only follows the events naming this account or sent by it.
+    #[arg(long)]
+    account: Option<String>,
+    /// Only follows these events, such as `Swap`. Repeat it for several.
+    #[arg(long = "event")]
+    events: Vec<String>,
+    /// Replays the events since this block before following new ones.
+    #[arg(long)]
+    from_block: Option<u64>,
+    /// Prints one json object per event.
+    #[arg(short, long)]
+    json: bool,
+}
+
+/// # History
+/// Rebuilds the execution and spot prices of a pool's swaps from its Swap events, aggregated into OHLCV candles.
+///
+/// ### Usage
+/// $ port history <pool_id> [--interval <15m|1h|1d>] [--from-block <block>] [--to-block <block>] [--trades] [--csv|--json] [--output <path>]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct HistoryArgs {
+    /// Pool to rebuild the history of.
+    pool_id: u64,
+    /// Length of the candles, such as `15m`, `1h` or `1d`.
+    #[arg(short, long, default_value = "1h")]
+    interval: String,
+    /// First block to read swaps from. Defaults to `start_block`.
+    #[arg(long)]
+    from_block: Option<u64>,
+    /// Last block to read swaps from. Defaults to the latest block.
+    #[arg(long)]
+    to_block: Option<u64>,
+    /// Exports every swap instead of candles.
+    #[arg(long)]
+    trades: bool,
+    /// Exports as csv.
+    #[arg(long)]
+    csv: bool,
+    /// Exports as json.
+    #[arg(long, conflicts_with = "csv")]
+    json: bool,
+    /// Writes the export to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// # Snapshot
+/// Dumps the state of every pool at the `--block`, or the latest one, to json for diffing against another snapshot.
+///
+/// ### Usage
+/// $ port --block <number|hash|tag> snapshot [--output <path>]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct SnapshotArgs {
+    /// Writes the snapshot to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// # Pnl
+/// Rebuilds the liquidity positions of an account from the Allocate, Deallocate and Swap events of their pools,
+/// and splits their result into fees earned, divergence loss against holding, and the market move of the held tokens.
+///
+/// ### Usage
+/// $ port pnl <account> [--pool <pool_id>] [--in-asset] [--csv] [--output <path>]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct PnlArgs {
+    /// Account whose positions are valued, matched against the senders of allocates and deallocates.
+    account: String,
+    /// Only values the position in this pool.
+    #[arg(short, long)]
+    pool: Option<u64>,
+    /// Values in asset tokens instead of quote tokens.
+    #[arg(long)]
+    in_asset: bool,
+    /// Exports a row after each allocate and deallocate of the account, and a current one, as csv.
+    #[arg(long)]
+    csv: bool,
+    /// Writes the csv to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// # Account
+/// Prints the liquidity positions of an account in NormalStrategy pools,
+/// with the delta, gamma, theta and vega of each from the pool's strike, volatility and time to expiry.
+///
+/// ### Usage
+/// $ port account <account> [--pool <pool_id>] [--scenario price=+10%]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct AccountArgs {
+    /// Account whose positions are read.
+    account: String,
+    /// Only reads the position in this pool.
+    #[arg(short, long)]
+    pool: Option<u64>,
+    /// Revalues the positions after shocks, such as `price=+10%,vol=-20%,days=7`.
+    #[arg(long)]
+    scenario: Option<String>,
+}
+
+/// # Backtest
+/// Replays a price series, or price paths following a geometric brownian motion, through an offline NormalStrategy pool.
+/// Arbitrageurs trade the pool to each price, and the liquidity's value, fees and divergence are compared to holding the deposit.
+///
+/// ### Usage
+/// $ port backtest --strike <strike> --volatility-bps <bps> --duration-days <days> --fee-bps <bps> [--price <price>] [--prices <csv>] [--paths <n>] [--json]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct BacktestArgs {
+    #[command(flatten)]
+    pool: sim::PoolArgs,
+    /// Csv of prices read from its `price` or `close` column, such as `history --csv` candles.
+    /// The step between prices is read from its `start` column if it has one, instead of `--step`.
+    #[arg(long)]
+    prices: Option<String>,
+    #[command(flatten)]
+    path: sim::PathArgs,
+    /// Liquidity deposited in the pool.
+    #[arg(long, default_value_t = 1.0)]
+    liquidity: f64,
+    /// Prints the result as json.
+    #[arg(long)]
+    json: bool,
+}
+
+/// # Optimize
+/// Simulates every combination of the strikes, volatilities, durations and fees over the same price paths,
+/// and ranks them by mean return, tail return, return against holding or fee capture.
+/// The best configuration can be saved as a batch plan creating its pool.
+///
+/// ### Usage
+/// $ port optimize --strikes 1600,1800,2000 --volatilities-bps 5000,8000 --durations-days 7,30 --fees-bps 5,30,100 --price 1800 --realized-volatility 0.7 [--sort <key>] [--report <path>] [--plan <path>] [--json]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct OptimizeArgs {
+    /// Strike prices swept, in quote tokens per asset token.
+    #[arg(long, value_delimiter = ',', required = true)]
+    strikes: Vec<f64>,
+    /// Implied volatilities swept, in basis points.
+    #[arg(long, value_delimiter = ',', required = true)]
+    volatilities_bps: Vec<u32>,
+    /// Durations swept, in days.
+    #[arg(long, value_delimiter = ',', default_value = "30")]
+    durations_days: Vec<u32>,
+    /// Swap fees swept, in basis points.
+    #[arg(long, value_delimiter = ',', default_value = "30")]
+    fees_bps: Vec<u16>,
+    /// Sweeps perpetual pools.
+    #[arg(long)]
+    is_perpetual: bool,
+    /// Price the pools are created at and the paths start from.
+    #[arg(long)]
+    price: f64,
+    #[command(flatten)]
+    path: sim::PathArgs,
+    /// Ranks by `mean_return`, `tail_return`, `mean_excess` or `fee_capture`.
+    #[arg(long, default_value = "mean_return")]
+    sort: String,
+    /// Configurations shown and reported.
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    /// Writes a markdown report of the sweep to this file.
+    #[arg(long)]
+    report: Option<String>,
+    /// Writes a batch plan creating a pool with the best configuration to this json file.
+    #[arg(long, requires_all = ["pair_id", "strategy"])]
+    plan: Option<String>,
+    /// Pair of the pool created by the plan.
+    #[arg(long)]
+    pair_id: Option<u32>,
+    /// NormalStrategy of the pool created by the plan.
+    #[arg(long)]
+    strategy: Option<String>,
+    /// Prints the ranked configurations as json.
+    #[arg(long)]
+    json: bool,
+}
+
+/// # Bot
+/// Long-running bots trading with the signer's funds, configured in `portfolio.toml`.
+///
+/// ### Usage
+/// $ port bot arb -p <pool_id> [--once] [--dry-run]
+/// $ port bot rebalance -p <pool_id> --policy <path> [--once] [--dry-run]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct BotArgs {
+    #[command(subcommand)]
+    command: BotCommands,
+}
+
+/// ## Bot subcommands.
+#[derive(Subcommand, Debug, Serialize, Deserialize)]
+pub enum BotCommands {
+    /// Keeps a pool in line with a reference price from the `arb` config.
+    Arb(ArbArgs),
+    /// Moves a liquidity position out of expiring or off-center pools, following a policy file.
+    Rebalance(RebalanceArgs),
+}
+
+/// ## ArbArgs
+/// Checks the pool against the reference price on every block, and swaps it back in line when the profit net of gas clears `min_profit`.
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct ArbArgs {
+    #[arg(short, long)]
+    pool_id: u64,
+    /// Checks the pool once and exits.
+    #[arg(long)]
+    once: bool,
+    /// Prints the trades without sending them.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// ## RebalanceArgs
+/// Checks the signer's position on every block, and moves it when its pool breaks the policy,
+/// deallocating and allocating into another pool of the pair, created if needed, in one multicall.
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct RebalanceArgs {
+    /// Pool of the position.
+    #[arg(short, long)]
+    pool_id: u64,
+    /// Toml or json policy file.
+    #[arg(long)]
+    policy: String,
+    /// Checks the position once and exits.
+    #[arg(long)]
+    once: bool,
+    /// Prints the steps without sending them.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// # Twap
+/// Splits a swap into equal slices sent over a duration, the last slice taking the remainder.
+/// Each slice is re-quoted before it is sent with the `[swap]` slippage, and waits while the spot or quoted price is outside
+/// the `[swap]` price band. Progress is saved to a state file, so running the same command again resumes a crashed run.
+///
+/// ### Usage
+/// $ port twap -p <pool_id> --amount <amount> [--sell-asset] [--slices <n>] [--duration <15m|1h|1d>] [--state <path>]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct TwapArgs {
+    #[arg(short, long)]
+    pool_id: u64,
+    /// Sells the asset token for the quote token if true.
+    #[arg(long)]
+    sell_asset: bool,
+    /// Total amount of input tokens to sell.
+    #[arg(long)]
+    amount: f64,
+    /// Number of swaps the amount is split into.
+    #[arg(long, default_value_t = 10)]
+    slices: u32,
+    /// Time over which the slices are spread.
+    #[arg(long, default_value = "1h")]
+    duration: String,
+    /// State file of the run, `<state_dir>/twap/<pool_id>.json` by default.
+    #[arg(long)]
+    state: Option<String>,
+}
+
+/// # Orders
+/// Limit orders kept in `<state_dir>/orders.json`. `orders watch` checks the open orders on every block,
+/// and swaps an order once Portfolio quotes its whole amount at its limit price or better, with the limit price's output as the minimum.
+///
+/// ### Usage
+/// $ port orders place -p <pool_id> --amount <amount> --price <limit> [--sell-asset] [--slippage <fraction>]
+/// $ port orders list [--json]
+/// $ port orders cancel <id>
+/// $ port orders watch [--once] [--dry-run]
+#[derive(Debug, Args, Serialize, Deserialize)]
+pub struct OrdersArgs {
+    #[command(subcommand)]
+    command: OrdersCommands,
+}
+
+/// ## Orders subcommands.
+#[derive(Subcommand, Debug, Serialize, Deserialize)]
+pub enum OrdersCommands {
+    /// Adds a limit order to the book, its `price` being the limit in quote tokens per asset token.
+    Place {
+        #[arg(short, long)]
+        pool_id: u64,
+        #[command(flatten)]
+        args: SwapArgs,
+    },
+    /// Prints the open and filled orders.
+    List {
+        /// Prints the orders as json.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Removes an open order from the book.
+    Cancel { id: u64 },
+    /// Fills the open orders as they become executable.
+    Watch {
+        /// Checks the orders once and exits.
+        #[arg(long)]
+        once: bool,
+        /// Prints the executable orders without sending them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// ## Encode subcommands.
+#[derive(Subcommand, Debug, Clone, Serialize, Deserialize)]
+pub enum EncodeCommands {
+    /// Swaps in a pool, quoting the output against its current state.
+    Swap {
+        #[arg(long)]
+        pool_id: u64,
+        #[command(flatten)]
+        args: SwapArgs,
+    },
+    /// Adds liquidity to a pool.
+    Allocate {
+        #[arg(long)]
+        pool_id: u64,
+        #[command(flatten)]
+        args: AllocateArgs,
+    },
+    /// Removes liquidity from a pool.
+    Deallocate {
+        #[arg(long)]
+        pool_id: u64,
+        #[command(flatten)]
+        args: DeallocateArgs,
+    },
+    /// Claims protocol fees.
+    Claim(ClaimArgs),
+    /// Creates a pair of tokens.
+    CreatePair(CreatePairArgs),
+    /// Creates a NormalStrategy pool.
+    CreatePool(CreatePoolArgs),
+    /// Changes the fees of a controlled pool.
+    ChangeParameters {
+        #[arg(long)]
+        pool_id: u64,
+        #[command(flatten)]
+        args: ChangeParametersArgs,
+    },
+    /// Combines the steps of a toml or json plan into one multicall.
+    Multicall { plan: String },
+}
+
 // =================== Tests ===================
 
 // `cargo test -- --nocapture` to see the output.
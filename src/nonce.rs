@@ -0,0 +1,125 @@
+use super::utils::{Client, FileLock};
+use super::Config;
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+/// How long to wait for another process to release the state file.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Nonces handed out to a signer on a chain, persisted so concurrent and scripted runs don't reuse them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct NonceState {
+    /// Next nonce to hand out.
+    next: u64,
+    /// Sent transactions that were not seen mined yet, by nonce.
+    pending: BTreeMap<u64, H256>,
+}
+
+/// Exclusive handle on the nonce state file of a signer, released on drop.
+pub struct NonceManager {
+    path: PathBuf,
+    _lock: FileLock,
+    state: NonceState,
+}
+
+impl NonceManager {
+    /// Locks the state file of the client's signer and reconciles it with the node.
+    pub async fn open(cfg: &Config, client: &Client) -> Result<Self, anyhow::Error> {
+        let dir = PathBuf::from(&cfg.state_dir)
+            .join("nonces")
+            .join(client.signer().chain_id().to_string());
+        fs::create_dir_all(&dir).context("nonce.rs: Failed to create state dir")?;
+
+        let path = dir.join(format!("{:?}.json", client.address()));
+        let lock = FileLock::acquire(&path.with_extension("lock"), LOCK_TIMEOUT)
+            .await
+            .context("nonce.rs: Nonce state is in use, wait for the other run to finish")?;
+
+        let state = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => NonceState::default(),
+        };
+
+        let mut manager = Self {
+            path,
+            _lock: lock,
+            state,
+        };
+        manager.reconcile(client).await?;
+        Ok(manager)
+    }
+
+    /// Drops mined nonces, rewinds to the first nonce the node never saw and catches up with the node's count.
+    async fn reconcile(&mut self, client: &Client) -> Result<(), anyhow::Error> {
+        let address = client.address();
+        let mined = client
+            .get_transaction_count(address, Some(BlockNumber::Latest.into()))
+            .await?
+            .as_u64();
+        let known = client
+            .get_transaction_count(address, Some(BlockNumber::Pending.into()))
+            .await?
+            .as_u64();
+
+        self.state.pending.retain(|nonce, _| *nonce >= mined);
+
+        for nonce in mined..self.state.next.max(known) {
+            let seen = match self.state.pending.get(&nonce) {
+                Some(hash) => client.get_transaction(*hash).await?.is_some(),
+                None => nonce < known,
+            };
+            if !seen {
+                let gap_msg = format!(
+                    "{} {} {}",
+                    "Nonce gap detected at".yellow(),
+                    nonce.to_string().bold().yellow(),
+                    "rewinding local nonce".yellow()
+                );
+                println!("{}", gap_msg.on_black());
+
+                self.state.pending.retain(|pending, _| *pending < nonce);
+                self.state.next = nonce;
+                break;
+            }
+        }
+
+        self.state.next = self.state.next.max(known);
+        self.save()
+    }
+
+    /// Hands out the next nonce.
+    pub fn reserve(&mut self) -> Result<U256, anyhow::Error> {
+        let nonce = self.state.next;
+        self.state.next += 1;
+        self.save()?;
+        Ok(nonce.into())
+    }
+
+    /// Gives a reserved nonce back when its transaction could not be sent.
+    pub fn release(&mut self, nonce: U256) -> Result<(), anyhow::Error> {
+        if self.state.next == nonce.as_u64() + 1 {
+            self.state.next = nonce.as_u64();
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Records the hash of the transaction sent with `nonce`.
+    pub fn record(&mut self, nonce: U256, hash: H256) -> Result<(), anyhow::Error> {
+        self.state.pending.insert(nonce.as_u64(), hash);
+        self.save()
+    }
+
+    /// Writes the state to a temporary file renamed over the state file, so a crash never leaves it half written.
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.state)?)
+            .context("nonce.rs: Failed to write nonce state")?;
+        fs::rename(&tmp, &self.path).context("nonce.rs: Failed to replace nonce state")?;
+        Ok(())
+    }
+}
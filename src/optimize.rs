@@ -0,0 +1,295 @@
+//! Sweeps of NormalStrategy pool configurations over simulated price paths.
+//! Every configuration of a duration faces the same paths, so their rankings only differ by their parameters.
+use super::actions::create_pool::CreatePoolArgs;
+use super::batch::{Plan, Step};
+use super::sim::{self, Outcome, Pool, Summary};
+use super::OptimizeArgs;
+use colored::Colorize;
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write};
+
+/// A configuration of the sweep with the distribution of its outcomes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub pool: CreatePoolArgs,
+    pub summary: Summary,
+}
+
+/// Handles the "Optimize" command.
+pub fn main(args: &OptimizeArgs) -> Result<(), anyhow::Error> {
+    if args.plan.is_some() {
+        let (Some(_), Some(strategy)) = (args.pair_id, &args.strategy) else {
+            return Err(anyhow::anyhow!(
+                "--plan needs the --pair-id and --strategy of the pool it creates"
+            ));
+        };
+        strategy
+            .parse::<ethers::types::Address>()
+            .map_err(|_| anyhow::anyhow!("Invalid strategy address {}", strategy))?;
+    }
+
+    let candidates = sweep(args)?;
+    let best = candidates
+        .first()
+        .ok_or(anyhow::anyhow!("The sweep has no configurations"))?;
+
+    if let Some(path) = &args.report {
+        std::fs::write(path, report(args, &candidates))?;
+        let saved_msg = format!("{} {}", "Saved report to".green(), path.bold().green());
+        println!("{}", saved_msg.on_black());
+    }
+    if let Some(path) = &args.plan {
+        let plan = Plan {
+            steps: vec![Step::CreatePool {
+                args: best.pool.clone(),
+            }],
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&plan)?)?;
+        let saved_msg = format!(
+            "{} {} {}",
+            "Saved the best configuration to".green(),
+            path.bold().green(),
+            "- create it with `port batch` or `port encode multicall`".green()
+        );
+        println!("{}", saved_msg.on_black());
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&candidates)?);
+    } else {
+        print(args, &candidates);
+    }
+    Ok(())
+}
+
+/// Simulates every configuration of the grid and ranks them by the sort key.
+pub fn sweep(args: &OptimizeArgs) -> Result<Vec<Candidate>, anyhow::Error> {
+    if !Summary::KEYS.contains(&args.sort.as_str()) {
+        return Err(anyhow::anyhow!(
+            "Invalid sort key {}, try {}",
+            args.sort,
+            Summary::KEYS.join(", ")
+        ));
+    }
+    if args.path.realized_volatility.is_none() {
+        return Err(anyhow::anyhow!(
+            "Set --realized-volatility, so every configuration faces the same prices"
+        ));
+    }
+    if args.price <= 0.0 {
+        return Err(anyhow::anyhow!("Price must be positive"));
+    }
+
+    let mut paths = HashMap::<u32, (f64, Vec<Vec<f64>>)>::new();
+    let mut candidates = Vec::new();
+    for &duration_days in &args.durations_days {
+        for &strike in &args.strikes {
+            for &volatility_bps in &args.volatilities_bps {
+                for &fee_bps in &args.fees_bps {
+                    let pool = CreatePoolArgs {
+                        pair_id: args.pair_id.unwrap_or_default(),
+                        strategy: args.strategy.clone().unwrap_or_default(),
+                        strike,
+                        volatility_bps,
+                        duration_days,
+                        is_perpetual: args.is_perpetual,
+                        price: args.price,
+                        fee_bps,
+                        ..Default::default()
+                    };
+                    let simulated = Pool::from_args(&pool, 1.0, args.price)?;
+                    if !paths.contains_key(&duration_days) {
+                        paths.insert(duration_days, args.path.generate(&pool, args.price)?);
+                    }
+                    let (dt, prices) = &paths[&duration_days];
+                    let outcomes: Vec<Outcome> = prices
+                        .iter()
+                        .map(|prices| sim::run(simulated.clone(), prices, *dt))
+                        .collect();
+                    candidates.push(Candidate {
+                        pool,
+                        summary: Summary::of(&outcomes),
+                    });
+                }
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| {
+        b.summary
+            .key(&args.sort)
+            .unwrap_or_default()
+            .total_cmp(&a.summary.key(&args.sort).unwrap_or_default())
+    });
+    Ok(candidates)
+}
+
+/// Markdown report of the sweep, to attach to a pool launch proposal.
+fn report(args: &OptimizeArgs, candidates: &[Candidate]) -> String {
+    let mut report = "# NormalStrategy pool configuration sweep\n\n".to_string();
+    let _ = writeln!(report, "- initial price: {}", args.price);
+    let _ = writeln!(
+        report,
+        "- price paths: {} geometric brownian motions with {} annual volatility and {} annual drift, seed {}",
+        args.path.paths,
+        args.path.realized_volatility.unwrap_or_default(),
+        args.path.drift,
+        args.path.seed
+    );
+    let _ = writeln!(
+        report,
+        "- steps: one arbitrage every `{}`, over each pool's duration{}",
+        args.path.step,
+        if args.is_perpetual {
+            " (30 days for perpetual pools)"
+        } else {
+            ""
+        }
+    );
+    let _ = writeln!(report, "- configurations: {}", candidates.len());
+    let _ = writeln!(report, "- ranked by: `{}`\n", args.sort);
+    let _ = writeln!(
+        report,
+        "Returns are on the deposit, in quote tokens. The tail return is the 5th percentile. Fee capture and divergence are means, relative to the deposit, and net vs hold is their sum.\n"
+    );
+
+    let _ = writeln!(
+        report,
+        "| # | strike | volatility bps | duration days | fee bps | mean return | median return | tail return | fee capture | divergence | net vs hold |"
+    );
+    let _ = writeln!(report, "|---|---|---|---|---|---|---|---|---|---|---|");
+    for (i, candidate) in candidates.iter().take(args.top).enumerate() {
+        let (pool, summary) = (&candidate.pool, &candidate.summary);
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | {} | {} | {:.2}% | {:.2}% | {:.2}% | {:.2}% | {:.2}% | {:.2}% |",
+            i + 1,
+            pool.strike,
+            pool.volatility_bps,
+            pool.duration_days,
+            pool.fee_bps,
+            summary.mean_return * 100.0,
+            summary.median_return * 100.0,
+            summary.tail_return * 100.0,
+            summary.fee_capture * 100.0,
+            summary.mean_divergence * 100.0,
+            summary.mean_excess * 100.0,
+        );
+    }
+
+    if let Some(best) = candidates.first() {
+        let step = Step::CreatePool {
+            args: best.pool.clone(),
+        };
+        let _ = writeln!(
+            report,
+            "\n## Pool creation\n\n```bash\nport encode --spec '{}'\n```",
+            serde_json::to_string(&step).unwrap_or_default()
+        );
+    }
+    report
+}
+
+fn print(args: &OptimizeArgs, candidates: &[Candidate]) {
+    let header_msg = format!(
+        "{} {} {} {}",
+        "Swept".yellow().bold(),
+        candidates.len().to_string().bold().magenta(),
+        "configurations, ranked by".yellow().bold(),
+        args.sort.bold().magenta()
+    );
+    println!("{}", header_msg.on_black());
+
+    for (i, candidate) in candidates.iter().take(args.top).enumerate() {
+        let (pool, summary) = (&candidate.pool, &candidate.summary);
+        let candidate_msg = format!(
+            "   - {} {} {} {} {} {} {} {}",
+            format!("#{}", i + 1).bold().purple(),
+            format!(
+                "strike {} vol {}bps {}d fee {}bps:",
+                pool.strike, pool.volatility_bps, pool.duration_days, pool.fee_bps
+            )
+            .purple(),
+            "return".purple(),
+            format!("{:.2}%", summary.mean_return * 100.0)
+                .bold()
+                .purple(),
+            "tail".purple(),
+            format!("{:.2}%", summary.tail_return * 100.0).red(),
+            "fees".purple(),
+            format!("{:.2}%", summary.fee_capture * 100.0).green(),
+        );
+        println!("{}", candidate_msg.on_black());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn ranks_the_grid_by_the_sort_key() {
+        let args = OptimizeArgs {
+            strikes: vec![100.0],
+            volatilities_bps: vec![5_000],
+            durations_days: vec![7],
+            fees_bps: vec![1, 100],
+            is_perpetual: false,
+            price: 100.0,
+            path: sim::PathArgs {
+                paths: 20,
+                step: "1h".to_string(),
+                steps: None,
+                drift: 0.0,
+                realized_volatility: Some(0.5),
+                seed: 1,
+            },
+            sort: "fee_capture".to_string(),
+            top: 10,
+            report: None,
+            plan: None,
+            pair_id: None,
+            strategy: None,
+            json: false,
+        };
+
+        let candidates = sweep(&args).unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].pool.fee_bps, 100);
+        assert!(candidates[0].summary.fee_capture > candidates[1].summary.fee_capture);
+        assert!(report(&args, &candidates).contains("\"action\":\"create_pool\""));
+    }
+
+    #[test]
+    fn plans_need_a_pair_and_a_strategy() {
+        let parse = |args: &[&str]| {
+            crate::App::try_parse_from(
+                [
+                    "port",
+                    "optimize",
+                    "--strikes",
+                    "100",
+                    "--volatilities-bps",
+                    "5000",
+                    "--price",
+                    "100",
+                ]
+                .iter()
+                .chain(args),
+            )
+        };
+
+        assert!(parse(&["--plan", "pool.json"]).is_err());
+        assert!(parse(&["--plan", "pool.json", "--pair-id", "1"]).is_err());
+        assert!(parse(&[
+            "--plan",
+            "pool.json",
+            "--pair-id",
+            "1",
+            "--strategy",
+            "0x0000000000000000000000000000000000000001"
+        ])
+        .is_ok());
+    }
+}
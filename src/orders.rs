@@ -0,0 +1,718 @@
+//! Limit orders kept in a local book and filled by a daemon watching the pools.
+//! An order is executable when Portfolio quotes its whole amount at or better than its limit price,
+//! and it is then sent as a swap whose minimum output is the limit price's output.
+use super::actions::{approve, swap::SwapArgs};
+use super::bindings::i_portfolio::IPortfolio;
+use super::events::{self, Pair, Trade};
+use super::utils::{round_wad, signer_client, wad_to_units, Client, FileLock, PoolId, Rounding};
+use super::{bot, compat, tx, Config, OrdersArgs, OrdersCommands};
+use anyhow::Context;
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, parse_ether},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// How long commands wait for another one to release the order book.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Open,
+    /// The swap was sent from `from_block` and is settled from its receipt by the next check,
+    /// or from the mined transaction of its `nonce` if it was replaced.
+    Sending {
+        tx_hash: H256,
+        nonce: u64,
+        from_block: u64,
+    },
+    Filled,
+}
+
+/// Transaction that filled an order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub tx_hash: H256,
+    pub block: Option<u64>,
+    /// Output received, or the minimum output if the swap was not seen mined.
+    pub output: f64,
+}
+
+/// A limit order, selling `amount` input tokens at `price` quote tokens per asset token or better.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub pool_id: u64,
+    #[serde(flatten)]
+    pub args: SwapArgs,
+    pub status: Status,
+    pub fill: Option<Fill>,
+}
+
+impl LimitOrder {
+    /// Output of the whole amount at the limit price, rounded down to wad.
+    pub fn limit_output(&self) -> f64 {
+        let output = if self.args.sell_asset {
+            self.args.amount * self.args.price
+        } else {
+            self.args.amount / self.args.price
+        };
+        round_wad(output, Rounding::Floor)
+    }
+
+    /// Whether the spot price is on the right side of the limit, a quote being needed otherwise.
+    pub fn in_reach(&self, spot: f64) -> bool {
+        match self.args.sell_asset {
+            true => spot >= self.args.price,
+            false => spot <= self.args.price,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "#{} pool {} {} at {}",
+            self.id,
+            self.pool_id,
+            if self.args.sell_asset {
+                format!("sell {} asset", self.args.amount)
+            } else {
+                format!("buy with {} quote", self.args.amount)
+            },
+            self.args.price
+        )
+    }
+}
+
+/// Limit orders filled by the signer of `orders watch`, persisted to `<state_dir>/orders.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Book {
+    next_id: u64,
+    pub orders: Vec<LimitOrder>,
+}
+
+impl Book {
+    pub fn path(cfg: &Config) -> PathBuf {
+        PathBuf::from(&cfg.state_dir).join("orders.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("orders.rs: Invalid order book {}", path.display())),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Writes the book to a temporary file renamed over it, so a crash never leaves it half written.
+    fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .context("orders.rs: Failed to write order book")?;
+        fs::rename(&tmp, path).context("orders.rs: Failed to replace order book")
+    }
+
+    /// Loads, changes and saves the book at `path` under its lock, so concurrent commands never lose each other's changes.
+    pub async fn edit<T>(
+        path: &Path,
+        f: impl FnOnce(&mut Book) -> Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("orders.rs: Failed to create state dir")?;
+        }
+        let _lock = FileLock::acquire(&path.with_extension("lock"), LOCK_TIMEOUT)
+            .await
+            .context("orders.rs: Order book is in use")?;
+        let mut book = Book::load(path)?;
+        let value = f(&mut book)?;
+        book.save(path)?;
+        Ok(value)
+    }
+
+    /// Adds an open order.
+    pub fn place(&mut self, pool_id: u64, args: SwapArgs) -> Result<&LimitOrder, anyhow::Error> {
+        if pool_id == 0 || args.amount <= 0.0 || args.price <= 0.0 {
+            return Err(anyhow::anyhow!(
+                "A limit order needs a pool id, an amount and a price"
+            ));
+        }
+        if !(0.0..1.0).contains(&args.slippage) {
+            return Err(anyhow::anyhow!("Slippage must be a fraction below 1"));
+        }
+        self.next_id += 1;
+        self.orders.push(LimitOrder {
+            id: self.next_id,
+            pool_id,
+            args,
+            status: Status::Open,
+            fill: None,
+        });
+        Ok(&self.orders[self.orders.len() - 1])
+    }
+
+    /// Removes an open order.
+    pub fn cancel(&mut self, id: u64) -> Result<LimitOrder, anyhow::Error> {
+        let index = self
+            .orders
+            .iter()
+            .position(|order| order.id == id)
+            .ok_or(anyhow::anyhow!("No order #{}", id))?;
+        match self.orders[index].status {
+            Status::Open => {}
+            Status::Sending { tx_hash, .. } => {
+                return Err(anyhow::anyhow!(
+                    "Order #{} is being filled in {:?}",
+                    id,
+                    tx_hash
+                ))
+            }
+            Status::Filled => return Err(anyhow::anyhow!("Order #{} is already filled", id)),
+        }
+        Ok(self.orders.remove(index))
+    }
+
+    pub fn open(&self) -> impl Iterator<Item = &LimitOrder> {
+        self.orders
+            .iter()
+            .filter(|order| order.status == Status::Open)
+    }
+
+    /// Updates an order of the book at `path`, reloaded so the orders placed or cancelled meanwhile are kept.
+    async fn update(
+        path: &Path,
+        id: u64,
+        f: impl FnOnce(&mut LimitOrder),
+    ) -> Result<(), anyhow::Error> {
+        Book::edit(path, |book| {
+            if let Some(order) = book.orders.iter_mut().find(|order| order.id == id) {
+                f(order);
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Handles the "Orders" command.
+pub async fn main(cfg: &Config, args: &OrdersArgs) -> Result<(), anyhow::Error> {
+    let path = Book::path(cfg);
+    match &args.command {
+        OrdersCommands::Place { pool_id, args } => {
+            let placed = Book::edit(&path, |book| {
+                Ok(book.place(*pool_id, args.clone())?.describe())
+            })
+            .await?;
+            let placed_msg = format!(
+                "{} {} {}",
+                "Placed order".green(),
+                placed.bold().green(),
+                "- fill it with `port orders watch`".green()
+            );
+            println!("{}", placed_msg.on_black());
+            Ok(())
+        }
+        OrdersCommands::List { json } => {
+            let book = Book::load(&path)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&book.orders)?);
+            } else {
+                print(&book);
+            }
+            Ok(())
+        }
+        OrdersCommands::Cancel { id } => {
+            let order = Book::edit(&path, |book| book.cancel(*id)).await?;
+            let cancelled_msg = format!(
+                "{} {}",
+                "Cancelled order".yellow(),
+                order.describe().bold().yellow()
+            );
+            println!("{}", cancelled_msg.on_black());
+            Ok(())
+        }
+        OrdersCommands::Watch { once, dry_run } => watch(cfg, &path, *once, *dry_run).await,
+    }
+}
+
+/// Checks the open orders on every block, reloading the book so orders placed or cancelled meanwhile are seen.
+async fn watch(cfg: &Config, path: &Path, once: bool, dry_run: bool) -> Result<(), anyhow::Error> {
+    if !dry_run {
+        approve::require_policy(cfg, "orders watch")?;
+    }
+    let client = signer_client(cfg).await?;
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    compat::detect(cfg, &portfolio).await?.require_all(&[
+        "getAmountOut",
+        "getSpotPrice",
+        "swap",
+    ])?;
+
+    let start_msg = format!(
+        "{} {} {} {}",
+        "Watching".yellow().bold(),
+        Book::load(path)?
+            .open()
+            .count()
+            .to_string()
+            .bold()
+            .magenta(),
+        "open orders of".yellow().bold(),
+        path.display().to_string().bold().yellow()
+    );
+    println!("{}", start_msg.on_black());
+
+    let (client, portfolio) = (&client, &portfolio);
+    bot::on_blocks(client, once, "Order check failed:", || async move {
+        check(cfg, client, portfolio, path, dry_run).await?;
+        Ok(ControlFlow::Continue(()))
+    })
+    .await
+}
+
+/// Settles the orders sent by a previous check, then fills the open orders executable at the current state,
+/// recording each fill in the book.
+async fn check(
+    cfg: &Config,
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    path: &Path,
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    let sending: Vec<LimitOrder> = Book::load(path)?
+        .orders
+        .into_iter()
+        .filter(|order| matches!(order.status, Status::Sending { .. }))
+        .collect();
+    for order in sending {
+        settle(client, portfolio, path, &order).await?;
+    }
+
+    let orders: Vec<LimitOrder> = Book::load(path)?.open().cloned().collect();
+    let mut spots = HashMap::<u64, f64>::new();
+    for order in orders {
+        let spot = match spots.get(&order.pool_id) {
+            Some(spot) => *spot,
+            None => {
+                let spot: f64 = format_ether(
+                    portfolio
+                        .get_spot_price(order.pool_id)
+                        .call()
+                        .await
+                        .context("orders.rs: Failed to get spot price")?,
+                )
+                .parse()?;
+                spots.insert(order.pool_id, spot);
+                spot
+            }
+        };
+
+        match fill(cfg, client, portfolio, path, &order, spot, dry_run).await {
+            Ok(Some(fill)) => {
+                Book::update(path, order.id, |filled| {
+                    filled.status = Status::Filled;
+                    filled.fill = Some(fill);
+                })
+                .await?;
+                // The fill moved the pool.
+                spots.remove(&order.pool_id);
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!(
+                "{} {} {}",
+                "Order".red(),
+                order.describe().red(),
+                format!("failed: {}", err).red()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Sends the order if Portfolio quotes its whole amount at its limit price or better.
+/// The order is saved as sending once the swap is broadcast, and stays so if the swap can't be followed to its receipt.
+async fn fill(
+    cfg: &Config,
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    path: &Path,
+    order: &LimitOrder,
+    spot: f64,
+    dry_run: bool,
+) -> Result<Option<Fill>, anyhow::Error> {
+    let waiting = |reason: String| {
+        let waiting_msg = format!("   - {} {}", order.describe().purple(), reason.purple());
+        println!("{}", waiting_msg.on_black());
+    };
+    if !order.in_reach(spot) {
+        waiting(format!("waits, spot is {}", spot));
+        return Ok(None);
+    }
+
+    let quoted: f64 = format_ether(
+        portfolio
+            .get_amount_out(
+                order.pool_id,
+                order.args.sell_asset,
+                parse_ether(order.args.amount)?,
+                client.address(),
+            )
+            .call()
+            .await
+            .context("orders.rs: Failed to get amount out")?,
+    )
+    .parse()?;
+    let limit_output = order.limit_output();
+    if quoted < limit_output {
+        waiting(format!(
+            "waits, quoted {} for a limit of {}",
+            quoted, limit_output
+        ));
+        return Ok(None);
+    }
+
+    let executable_msg = format!(
+        "   - {} {} {}",
+        order.describe().bold().green(),
+        "is executable, quoted".green(),
+        quoted.to_string().bold().green()
+    );
+    println!("{}", executable_msg.on_black());
+    if dry_run {
+        println!("{}", "   - dry run, not sent".yellow().on_black());
+        return Ok(None);
+    }
+
+    let pair = pair(portfolio, order.pool_id).await?;
+    let (token_in, decimals) = if order.args.sell_asset {
+        (pair.asset, pair.asset_decimals)
+    } else {
+        (pair.quote, pair.quote_decimals)
+    };
+    approve::ensure_allowance(
+        cfg,
+        client,
+        token_in,
+        portfolio.address(),
+        wad_to_units(parse_ether(order.args.amount)?, decimals),
+    )
+    .await?;
+
+    // The output never goes below the limit price's, whatever the slippage tolerance.
+    let mut swap = order
+        .args
+        .quote_order(portfolio, order.pool_id, client.address())
+        .await?;
+    swap.output = swap.output.max(parse_ether(limit_output)?.as_u128());
+
+    let call = portfolio.swap(swap);
+    call.call()
+        .await
+        .map_err(|err| anyhow::anyhow!("orders.rs: Swap simulation failed: {}", err))?;
+    let from_block = client.get_block_number().await?.as_u64();
+    let (pending, nonce) = tx::send_with_nonce(cfg, client, call.tx)
+        .await
+        .context("orders.rs: Failed to send swap")?;
+    let tx_hash = pending.tx_hash();
+    Book::update(path, order.id, |sending| {
+        sending.status = Status::Sending {
+            tx_hash,
+            nonce: nonce.as_u64(),
+            from_block,
+        }
+    })
+    .await?;
+    let Some(receipt) = tx::track(cfg, client, pending, false).await? else {
+        let sent_msg = format!(
+            "   - {} {}",
+            order.describe().yellow(),
+            format!("sent in {:?}, settled by the next check", tx_hash).yellow()
+        );
+        println!("{}", sent_msg.on_black());
+        return Ok(None);
+    };
+
+    let fill = Fill {
+        tx_hash,
+        block: receipt.block_number.map(|block| block.as_u64()),
+        output: traded(portfolio, &receipt, &pair).map_or(limit_output, |trade| trade.output),
+    };
+    print_fill(order, &fill);
+    Ok(Some(fill))
+}
+
+/// Settles an order sent by a previous check: filled from the receipt of its swap, still sending while
+/// the swap is in the mempool, or open again if it reverted or was dropped without a replacement being mined.
+async fn settle(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    path: &Path,
+    order: &LimitOrder,
+) -> Result<(), anyhow::Error> {
+    let Status::Sending {
+        tx_hash,
+        nonce,
+        from_block,
+    } = order.status
+    else {
+        return Ok(());
+    };
+    let pair = pair(portfolio, order.pool_id).await?;
+
+    let trade = match client
+        .get_transaction_receipt(tx_hash)
+        .await
+        .context("orders.rs: Failed to get receipt")?
+    {
+        Some(receipt) => traded(portfolio, &receipt, &pair),
+        None if client
+            .get_transaction(tx_hash)
+            .await
+            .context("orders.rs: Failed to get transaction")?
+            .is_some() =>
+        {
+            let sending_msg = format!(
+                "   - {} {}",
+                order.describe().purple(),
+                format!("is being filled in {:?}", tx_hash).purple()
+            );
+            println!("{}", sending_msg.on_black());
+            return Ok(());
+        }
+        // Dropped or replaced, a replacement is looked up in the pool's swaps.
+        None => replacement(client, portfolio, path, &pair, order, nonce, from_block).await?,
+    };
+
+    match trade {
+        Some(trade) => {
+            let fill = Fill {
+                tx_hash: trade.tx_hash,
+                block: Some(trade.block),
+                output: trade.output,
+            };
+            print_fill(order, &fill);
+            Book::update(path, order.id, |filled| {
+                filled.status = Status::Filled;
+                filled.fill = Some(fill);
+            })
+            .await
+        }
+        None => {
+            let reopened_msg = format!(
+                "{} {} {}",
+                "Order".yellow(),
+                order.describe().bold().yellow(),
+                format!("is open again, {:?} was not mined", tx_hash).yellow()
+            );
+            eprintln!("{}", reopened_msg.on_black());
+            Book::update(path, order.id, |reopened| reopened.status = Status::Open).await
+        }
+    }
+}
+
+/// Swap of the order's pool mined since `from_block` by the signer's transaction of `nonce`, such as a sped up
+/// replacement. Swaps already recorded as the fill of another order are never taken.
+async fn replacement(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    path: &Path,
+    pair: &Pair,
+    order: &LimitOrder,
+    nonce: u64,
+    from_block: u64,
+) -> Result<Option<Trade>, anyhow::Error> {
+    let recorded: HashSet<H256> = Book::load(path)?
+        .orders
+        .iter()
+        .filter(|other| other.id != order.id)
+        .filter_map(|other| other.fill.as_ref().map(|fill| fill.tx_hash))
+        .collect();
+    let latest = client.get_block_number().await?.as_u64();
+    let filter = events::filter(portfolio.address(), &["Swap"], Some(order.pool_id));
+    for log in events::logs(&**client, &filter, from_block, latest).await? {
+        let Some(trade) = Trade::from_log(&log, pair) else {
+            continue;
+        };
+        if trade.sell_asset != order.args.sell_asset || recorded.contains(&trade.tx_hash) {
+            continue;
+        }
+        let sent = client
+            .get_transaction(trade.tx_hash)
+            .await?
+            .map(|tx| (tx.from, tx.nonce));
+        if sent == Some((client.address(), U256::from(nonce))) {
+            return Ok(Some(trade));
+        }
+    }
+    Ok(None)
+}
+
+/// Swap of a receipt, none if it reverted.
+fn traded(
+    portfolio: &IPortfolio<Client>,
+    receipt: &TransactionReceipt,
+    pair: &Pair,
+) -> Option<Trade> {
+    receipt
+        .logs
+        .iter()
+        .filter(|log| log.address == portfolio.address())
+        .find_map(|log| Trade::from_log(log, pair))
+}
+
+async fn pair(portfolio: &IPortfolio<Client>, pool_id: u64) -> Result<Pair, anyhow::Error> {
+    Ok(Pair::from(
+        portfolio
+            .pairs(PoolId(pool_id).pair_id())
+            .call()
+            .await
+            .context("orders.rs: Failed to get pair")?,
+    ))
+}
+
+fn print_fill(order: &LimitOrder, fill: &Fill) {
+    let filled_msg = format!(
+        "{} {} {} {}",
+        "Filled order".bold().green(),
+        order.describe().bold().green(),
+        "for".green(),
+        fill.output.to_string().bold().green()
+    );
+    println!("{}", filled_msg.on_black());
+}
+
+fn print(book: &Book) {
+    let header_msg = format!(
+        "{} {} {}",
+        "Found".yellow().bold(),
+        book.orders.len().to_string().bold().magenta(),
+        "orders".yellow().bold()
+    );
+    println!("{}", header_msg.on_black());
+
+    for order in &book.orders {
+        let order_msg = match (&order.fill, order.status) {
+            (Some(fill), _) => format!(
+                "   - {} {}",
+                order.describe().green(),
+                format!("filled for {} in {:?}", fill.output, fill.tx_hash).green()
+            ),
+            (None, Status::Sending { tx_hash, .. }) => format!(
+                "   - {} {}",
+                order.describe().yellow(),
+                format!("sending in {:?}", tx_hash).yellow()
+            ),
+            (None, _) => format!(
+                "   - {} {}",
+                order.describe().purple(),
+                format!("open, min output {}", order.limit_output()).purple()
+            ),
+        };
+        println!("{}", order_msg.on_black());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sell(amount: f64, price: f64) -> SwapArgs {
+        SwapArgs {
+            sell_asset: true,
+            amount,
+            price,
+            slippage: 0.0,
+        }
+    }
+
+    #[test]
+    fn places_and_cancels_open_orders() {
+        let mut book = Book::default();
+        assert_eq!(book.place(1, sell(1.0, 2000.0)).unwrap().id, 1);
+        assert_eq!(book.place(1, sell(2.0, 2100.0)).unwrap().id, 2);
+        assert!(book.place(1, sell(1.0, 0.0)).is_err());
+
+        book.orders[0].status = Status::Filled;
+        assert!(book.cancel(1).is_err());
+        assert_eq!(book.cancel(2).unwrap().args.amount, 2.0);
+        assert!(book.cancel(2).is_err());
+        assert_eq!(book.open().count(), 0);
+        assert_eq!(book.place(1, sell(1.0, 2000.0)).unwrap().id, 3);
+    }
+
+    #[test]
+    fn sending_orders_are_kept_out_of_the_open_ones() {
+        let mut book = Book::default();
+        book.place(1, sell(1.0, 2000.0)).unwrap();
+        book.orders[0].status = Status::Sending {
+            tx_hash: H256::repeat_byte(1),
+            nonce: 3,
+            from_block: 10,
+        };
+
+        let mut book: Book = serde_json::from_str(&serde_json::to_string(&book).unwrap()).unwrap();
+        assert_eq!(
+            book.orders[0].status,
+            Status::Sending {
+                tx_hash: H256::repeat_byte(1),
+                nonce: 3,
+                from_block: 10
+            }
+        );
+        assert_eq!(book.open().count(), 0);
+        assert!(book.cancel(1).is_err());
+    }
+
+    #[tokio::test]
+    async fn edits_keep_the_book_valid() {
+        let dir = std::env::temp_dir().join(format!("portfolio-rs-orders-{}", std::process::id()));
+        let path = dir.join("orders.json");
+
+        let placed = Book::edit(&path, |book| Ok(book.place(1, sell(1.0, 2000.0))?.id))
+            .await
+            .unwrap();
+        assert!(
+            Book::edit(&path, |book| book.place(1, sell(1.0, 0.0)).map(|_| ()))
+                .await
+                .is_err()
+        );
+        Book::update(&path, placed, |order| order.status = Status::Filled)
+            .await
+            .unwrap();
+
+        let book = Book::load(&path).unwrap();
+        assert_eq!(book.orders.len(), 1);
+        assert_eq!(book.orders[0].status, Status::Filled);
+        assert!(!path.with_extension("json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn limit_output_sides() {
+        let mut book = Book::default();
+        book.place(1, sell(2.0, 1500.0)).unwrap();
+        book.place(
+            1,
+            SwapArgs {
+                sell_asset: false,
+                ..sell(3000.0, 1500.0)
+            },
+        )
+        .unwrap();
+
+        let (ask, bid) = (&book.orders[0], &book.orders[1]);
+        assert_eq!(ask.limit_output(), 3000.0);
+        assert_eq!(bid.limit_output(), 2.0);
+        assert!(ask.in_reach(1600.0) && !ask.in_reach(1400.0));
+        assert!(bid.in_reach(1400.0) && !bid.in_reach(1600.0));
+    }
+}
@@ -0,0 +1,554 @@
+//! Performance of liquidity positions, rebuilt from the Allocate, Deallocate and Swap events of their pools.
+//! Allocates and deallocates are attributed to the sender of their transaction. A position's result is split into:
+//! - fees: its share of the swap fees, by its share of the pool's liquidity at each swap,
+//! - divergence: what providing liquidity earned or lost against holding the deposited tokens, net of fees,
+//! - market: what holding the deposited tokens earned or lost,
+//! so that the net PnL is their sum.
+use super::compat;
+use super::events::{self, address, uint, POOL_EVENTS};
+use super::tx::decode_log;
+use super::utils::{read_block, PoolId};
+use super::{Config, PnlArgs};
+use crate::bindings::{i_portfolio::IPortfolio, i_portfolio_struct::IPortfolioStruct};
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, format_units},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::Arc,
+};
+
+/// Result of a position at a block, valued in the numeraire.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pnl {
+    pub pool_id: u64,
+    pub block: u64,
+    /// `allocate` or `deallocate` for rows following the account's events, `now` for the current one.
+    pub event: String,
+    pub liquidity: f64,
+    /// Value of the deposits when they were made.
+    pub deposited: f64,
+    /// Value of the withdrawals when they were made.
+    pub withdrawn: f64,
+    /// Value of the amounts the position redeems.
+    pub value: f64,
+    /// Value of the deposited tokens if they had been held, including the withdrawn part.
+    pub hold: f64,
+    pub fees: f64,
+    pub divergence: f64,
+    pub market: f64,
+    pub net: f64,
+}
+
+/// Running accounts of a position in one pool, fed with the pool's events in order.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    /// Values in asset tokens instead of quote tokens.
+    in_asset: bool,
+    pool_liquidity: f64,
+    liquidity: f64,
+    hold_asset: f64,
+    hold_quote: f64,
+    deposited: f64,
+    withdrawn: f64,
+    hold_withdrawn: f64,
+    fees: f64,
+}
+
+impl Ledger {
+    pub fn new(in_asset: bool) -> Self {
+        Self {
+            in_asset,
+            ..Default::default()
+        }
+    }
+
+    /// Starts from the liquidity the pool already had before the replayed events.
+    pub fn with_pool_liquidity(mut self, pool_liquidity: f64) -> Self {
+        self.pool_liquidity = pool_liquidity;
+        self
+    }
+
+    /// Values amounts at a price in quote tokens per asset token.
+    fn value(&self, asset: f64, quote: f64, price: f64) -> f64 {
+        if self.in_asset {
+            if price > 0.0 {
+                asset + quote / price
+            } else {
+                asset
+            }
+        } else {
+            asset * price + quote
+        }
+    }
+
+    pub fn allocate(&mut self, own: bool, liquidity: f64, asset: f64, quote: f64, price: f64) {
+        self.pool_liquidity += liquidity;
+        if !own {
+            return;
+        }
+        self.liquidity += liquidity;
+        self.hold_asset += asset;
+        self.hold_quote += quote;
+        self.deposited += self.value(asset, quote, price);
+    }
+
+    /// Removing part of the position also sells the same part of the held tokens, at the same price.
+    pub fn deallocate(&mut self, own: bool, liquidity: f64, asset: f64, quote: f64, price: f64) {
+        self.pool_liquidity = (self.pool_liquidity - liquidity).max(0.0);
+        if !own {
+            return;
+        }
+        let part = if self.liquidity > 0.0 {
+            (liquidity / self.liquidity).min(1.0)
+        } else {
+            0.0
+        };
+        self.withdrawn += self.value(asset, quote, price);
+        self.hold_withdrawn += self.value(self.hold_asset, self.hold_quote, price) * part;
+        self.hold_asset *= 1.0 - part;
+        self.hold_quote *= 1.0 - part;
+        self.liquidity = (self.liquidity - liquidity).max(0.0);
+    }
+
+    /// Credits the position's share of a swap fee paid in the input token.
+    pub fn swap(&mut self, fee: f64, fee_in_asset: bool, price: f64) {
+        if self.liquidity <= 0.0 || self.pool_liquidity <= 0.0 {
+            return;
+        }
+        let share = fee * (self.liquidity / self.pool_liquidity).min(1.0);
+        self.fees += if fee_in_asset {
+            self.value(share, 0.0, price)
+        } else {
+            self.value(0.0, share, price)
+        };
+    }
+
+    /// Splits the result of the position, given the amounts it redeems and the current price.
+    pub fn pnl(&self, asset: f64, quote: f64, price: f64) -> (f64, f64, f64, f64, f64, f64) {
+        let value = self.value(asset, quote, price);
+        let hold = self.value(self.hold_asset, self.hold_quote, price) + self.hold_withdrawn;
+        let lp = value + self.withdrawn;
+        let divergence = lp - hold - self.fees;
+        let market = hold - self.deposited;
+        (
+            value,
+            hold,
+            self.fees,
+            divergence,
+            market,
+            lp - self.deposited,
+        )
+    }
+}
+
+/// Handles the "Pnl" command.
+pub async fn main(cfg: &Config, args: &PnlArgs) -> Result<(), anyhow::Error> {
+    let account = args.account.parse::<Address>()?;
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, provider.clone());
+    compat::detect(cfg, &portfolio).await?.require_all(&[
+        "getLiquidityDeltas",
+        "pairs",
+        "pools",
+        "positions",
+    ])?;
+
+    let block = read_block(cfg, &*provider).await?;
+    let filter = events::filter(portfolio.address(), POOL_EVENTS, args.pool);
+    let mut pools = BTreeMap::<u64, Vec<Log>>::new();
+    for log in events::logs(&*provider, &filter, cfg.start_block, block).await? {
+        if let Some(pool_id) = log.topics.get(1) {
+            pools.entry(pool_id.to_low_u64_be()).or_default().push(log);
+        }
+    }
+
+    let mut positions = Positions {
+        pools: IPortfolioStruct::new(portfolio.address(), provider.clone()),
+        portfolio,
+        account,
+        start_block: cfg.start_block,
+        senders: HashMap::new(),
+    };
+    let mut rows = Vec::new();
+    for (pool_id, logs) in &pools {
+        rows.extend(
+            positions
+                .replay(*pool_id, logs, block, args.in_asset)
+                .await?,
+        );
+    }
+
+    if args.csv {
+        let csv = to_csv(&rows);
+        match &args.output {
+            Some(path) => {
+                std::fs::write(path, csv)?;
+                let saved_msg = format!("{} {}", "Saved pnl to".green(), path.bold().green());
+                println!("{}", saved_msg.on_black());
+            }
+            None => println!("{}", csv),
+        }
+        return Ok(());
+    }
+
+    print(account, &rows, args.in_asset);
+    Ok(())
+}
+
+struct Positions {
+    portfolio: IPortfolio<Provider<Ws>>,
+    pools: IPortfolioStruct<Provider<Ws>>,
+    account: Address,
+    /// First block of the replayed events.
+    start_block: u64,
+    senders: HashMap<H256, Address>,
+}
+
+impl Positions {
+    /// Replays the events of a pool, with a row after each of the account's events and one at `block`.
+    async fn replay(
+        &mut self,
+        pool_id: u64,
+        logs: &[Log],
+        block: u64,
+        in_asset: bool,
+    ) -> Result<Vec<Pnl>, anyhow::Error> {
+        let (asset, asset_decimals, _, quote_decimals) = self
+            .portfolio
+            .pairs(PoolId(pool_id).pair_id())
+            .block(block)
+            .call()
+            .await?;
+        let scale = |amount: U256, decimals: u8| -> f64 {
+            format_units(amount, decimals as u32)
+                .ok()
+                .and_then(|amount| amount.parse().ok())
+                .unwrap_or_default()
+        };
+        let wad = |amount: U256| -> f64 { format_ether(amount).parse().unwrap_or_default() };
+
+        // The pool's liquidity right before the events of `start_block`, which are replayed.
+        let initial = self
+            .pools
+            .pools(pool_id)
+            .block(self.start_block.saturating_sub(1))
+            .call()
+            .await?;
+        let mut ledger = Ledger::new(in_asset).with_pool_liquidity(wad(initial.liquidity.into()));
+        let mut rows = Vec::new();
+        for log in logs {
+            let Some((name, params)) = decode_log(log) else {
+                continue;
+            };
+            let (Some(log_block), Some(hash)) = (log.block_number, log.transaction_hash) else {
+                continue;
+            };
+
+            if name == "Swap" {
+                let (Some(fee), Some(token_in), Some(price)) = (
+                    uint(&params, "feeAmountDec"),
+                    address(&params, "tokenIn"),
+                    uint(&params, "price"),
+                ) else {
+                    continue;
+                };
+                let fee_in_asset = token_in == asset;
+                let decimals = if fee_in_asset {
+                    asset_decimals
+                } else {
+                    quote_decimals
+                };
+                ledger.swap(scale(fee, decimals), fee_in_asset, wad(price));
+                continue;
+            }
+
+            let (Some(delta_asset), Some(delta_quote), Some(delta_liquidity)) = (
+                uint(&params, "deltaAsset"),
+                uint(&params, "deltaQuote"),
+                uint(&params, "deltaLiquidity"),
+            ) else {
+                continue;
+            };
+            let own = self.sender(hash).await? == self.account;
+            let price = if own {
+                wad(self
+                    .portfolio
+                    .get_spot_price(pool_id)
+                    .block(log_block.as_u64())
+                    .call()
+                    .await?)
+            } else {
+                0.0
+            };
+            let amounts = (
+                wad(delta_liquidity),
+                scale(delta_asset, asset_decimals),
+                scale(delta_quote, quote_decimals),
+            );
+            match name.as_str() {
+                "Allocate" => ledger.allocate(own, amounts.0, amounts.1, amounts.2, price),
+                "Deallocate" => ledger.deallocate(own, amounts.0, amounts.1, amounts.2, price),
+                _ => continue,
+            }
+            if own {
+                let row = self
+                    .row(&ledger, pool_id, log_block.as_u64(), &name.to_lowercase())
+                    .await?;
+                rows.push(row);
+            }
+        }
+
+        let liquidity = self
+            .portfolio
+            .positions(self.account, pool_id)
+            .block(block)
+            .call()
+            .await?;
+        let liquidity = wad(liquidity.into());
+        if (liquidity - ledger.liquidity).abs() > 1e-9 * liquidity.max(1.0) {
+            let mismatch_msg = format!(
+                "{} {} {} {} {} {}{}",
+                "Warning: the liquidity rebuilt in pool".yellow(),
+                pool_id.to_string().bold().yellow(),
+                "is".yellow(),
+                ledger.liquidity.to_string().bold().yellow(),
+                "but Portfolio holds".yellow(),
+                liquidity.to_string().bold().yellow(),
+                ", events before start_block are missing and the pnl is off".yellow()
+            );
+            eprintln!("{}", mismatch_msg.on_black());
+        }
+
+        if rows.is_empty() {
+            return Ok(rows);
+        }
+        rows.push(self.row(&ledger, pool_id, block, "now").await?);
+        Ok(rows)
+    }
+
+    /// Values the position at `block`, from the liquidity Portfolio holds for the account then.
+    async fn row(
+        &self,
+        ledger: &Ledger,
+        pool_id: u64,
+        block: u64,
+        event: &str,
+    ) -> Result<Pnl, anyhow::Error> {
+        let (_, asset_decimals, _, quote_decimals) = self
+            .portfolio
+            .pairs(PoolId(pool_id).pair_id())
+            .block(block)
+            .call()
+            .await?;
+        let liquidity = self
+            .portfolio
+            .positions(self.account, pool_id)
+            .block(block)
+            .call()
+            .await?;
+        let (asset, quote) = if liquidity == 0 {
+            (0, 0)
+        } else {
+            self.portfolio
+                .get_liquidity_deltas(pool_id, -(liquidity as i128))
+                .block(block)
+                .call()
+                .await?
+        };
+        let price = self
+            .portfolio
+            .get_spot_price(pool_id)
+            .block(block)
+            .call()
+            .await?;
+
+        let scale = |amount: u128, decimals: u8| -> f64 {
+            format_units(U256::from(amount), decimals as u32)
+                .ok()
+                .and_then(|amount| amount.parse().ok())
+                .unwrap_or_default()
+        };
+        let (value, hold, fees, divergence, market, net) = ledger.pnl(
+            scale(asset, asset_decimals),
+            scale(quote, quote_decimals),
+            format_ether(price).parse().unwrap_or_default(),
+        );
+
+        Ok(Pnl {
+            pool_id,
+            block,
+            event: event.to_string(),
+            liquidity: scale(liquidity, 18),
+            deposited: ledger.deposited,
+            withdrawn: ledger.withdrawn,
+            value,
+            hold,
+            fees,
+            divergence,
+            market,
+            net,
+        })
+    }
+
+    async fn sender(&mut self, hash: H256) -> Result<Address, anyhow::Error> {
+        if let Some(sender) = self.senders.get(&hash) {
+            return Ok(*sender);
+        }
+        let sender = self
+            .portfolio
+            .client()
+            .get_transaction(hash)
+            .await?
+            .map(|tx| tx.from)
+            .unwrap_or_default();
+        self.senders.insert(hash, sender);
+        Ok(sender)
+    }
+}
+
+fn to_csv(rows: &[Pnl]) -> String {
+    let mut csv =
+        "pool_id,block,event,liquidity,deposited,withdrawn,value,hold,fees,divergence,market,net\n"
+            .to_string();
+    for row in rows {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            row.pool_id,
+            row.block,
+            row.event,
+            row.liquidity,
+            row.deposited,
+            row.withdrawn,
+            row.value,
+            row.hold,
+            row.fees,
+            row.divergence,
+            row.market,
+            row.net
+        );
+    }
+    csv
+}
+
+fn print(account: Address, rows: &[Pnl], in_asset: bool) {
+    let header_msg = format!(
+        "{} {} {} {}",
+        "Positions of".yellow().bold(),
+        format!("{:?}", account).bold().magenta(),
+        "valued in".yellow(),
+        if in_asset { "asset" } else { "quote" }.bold().yellow()
+    );
+    println!("{}", header_msg.on_black());
+    if rows.is_empty() {
+        println!("{}", "   - none".purple().on_black());
+    }
+
+    let signed = |value: f64| {
+        if value >= 0.0 {
+            format!("+{}", value).green()
+        } else {
+            value.to_string().red()
+        }
+    };
+    let mut pool_id = None;
+    for row in rows {
+        if pool_id != Some(row.pool_id) {
+            pool_id = Some(row.pool_id);
+            let pool_msg = format!(
+                "   - {} {}",
+                "pool".yellow().bold(),
+                row.pool_id.to_string().bold().magenta()
+            );
+            println!("{}", pool_msg.on_black());
+        }
+
+        if row.event != "now" {
+            let event_msg = format!(
+                "     {} {} {} {} {}",
+                format!("#{}", row.block).purple(),
+                row.event.purple(),
+                "liquidity".purple(),
+                row.liquidity.to_string().purple(),
+                format!("net {}", row.net).purple(),
+            );
+            println!("{}", event_msg.on_black());
+            continue;
+        }
+
+        let pnl_msg = format!(
+            "     {} {} {}
+       {} {}  {} {}  {} {}
+       {} {}  {} {}  {} {}  {} {}",
+            format!("#{}", row.block).yellow(),
+            "liquidity".yellow(),
+            row.liquidity.to_string().bold().yellow(),
+            "deposited:".purple(),
+            row.deposited.to_string().bold().purple(),
+            "withdrawn:".purple(),
+            row.withdrawn.to_string().bold().purple(),
+            "value:".purple(),
+            row.value.to_string().bold().purple(),
+            "fees:".purple(),
+            signed(row.fees),
+            "divergence:".purple(),
+            signed(row.divergence),
+            "market:".purple(),
+            signed(row.market),
+            "net pnl:".purple().bold(),
+            signed(row.net).bold(),
+        );
+        println!("{}", pnl_msg.on_black());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_pnl_into_fees_divergence_and_market() {
+        let mut ledger = Ledger::new(false);
+        // Another provider owns half of the pool.
+        ledger.allocate(false, 10.0, 1.0, 100.0, 100.0);
+        ledger.allocate(true, 10.0, 1.0, 100.0, 100.0);
+        ledger.swap(2.0, false, 100.0);
+        // The price doubles, the position now redeems 0.75 asset and 300 quote.
+        let (value, hold, fees, divergence, market, net) = ledger.pnl(0.75, 300.0, 200.0);
+
+        assert_eq!(ledger.deposited, 200.0);
+        assert_eq!(fees, 1.0);
+        assert_eq!(value, 450.0);
+        assert_eq!(hold, 300.0);
+        assert_eq!(market, 100.0);
+        assert_eq!(divergence, 149.0);
+        assert_eq!(net, fees + divergence + market);
+    }
+
+    #[test]
+    fn deallocating_sells_the_same_part_of_the_held_tokens() {
+        let mut ledger = Ledger::new(true);
+        ledger.allocate(true, 10.0, 1.0, 100.0, 100.0);
+        ledger.deallocate(true, 5.0, 0.5, 50.0, 100.0);
+        let (value, hold, fees, divergence, market, net) = ledger.pnl(0.5, 50.0, 100.0);
+
+        assert_eq!(ledger.deposited, 2.0);
+        assert_eq!(ledger.withdrawn, 1.0);
+        assert_eq!((value, hold, fees), (1.0, 2.0, 0.0));
+        assert_eq!((divergence, market, net), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn fee_share_counts_the_liquidity_before_the_replayed_events() {
+        let mut ledger = Ledger::new(false).with_pool_liquidity(30.0);
+        ledger.allocate(true, 10.0, 1.0, 100.0, 100.0);
+        ledger.swap(4.0, false, 100.0);
+
+        assert_eq!(ledger.fees, 1.0);
+    }
+}
@@ -0,0 +1,485 @@
+//! Offline NormalStrategy pools, to simulate trades against reference prices without a node.
+//! A unit of liquidity holds `x = 1 - N(d1)` asset tokens and `y = K N(d1 - sigma sqrt(tau))` quote tokens at a price,
+//! and arbitrageurs trade a pool until its price is within the fee of the reference price:
+//! buying asset pays the fee on the quote input, so they buy until `price = reference (1 - fee)`,
+//! and selling asset pays it on the asset input, so they sell until `price = reference / (1 - fee)`.
+use super::actions::create_pool::CreatePoolArgs;
+use super::greeks::{StrategyConfig, YEAR};
+use super::history::parse_interval;
+use clap::Args;
+use rand::{distributions::Distribution, rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal};
+
+/// Asset reserves per unit of liquidity are kept this far from 0 and 1, where the price is undefined.
+const EPSILON: f64 = 1e-12;
+/// Shortest time to expiry simulated, as expired pools no longer trade.
+const MIN_TAU: f64 = 60.0 / YEAR;
+
+/// Arguments of the price paths generated for simulations.
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct PathArgs {
+    /// Price paths generated.
+    #[arg(long, default_value_t = 100)]
+    pub paths: usize,
+    /// Time between prices, such as `1h` or `1d`.
+    #[arg(long, default_value = "1h")]
+    pub step: String,
+    /// Steps of each path, defaults to the pool's duration, or 30 days for perpetual pools.
+    #[arg(long)]
+    pub steps: Option<usize>,
+    /// Annual drift of the prices, as a fraction.
+    #[arg(long, default_value_t = 0.0)]
+    pub drift: f64,
+    /// Annual volatility of the prices, as a fraction, defaults to the pool's implied volatility.
+    #[arg(long)]
+    pub realized_volatility: Option<f64>,
+    /// Seed of the paths, so runs can be reproduced.
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+}
+
+/// Strategy and fee of a simulated pool, the `createPool` arguments that matter offline.
+#[derive(Clone, Debug, Default, Args, Serialize, Deserialize)]
+pub struct PoolArgs {
+    /// Strike price, in quote tokens per asset token.
+    #[arg(long, default_value_t)]
+    pub strike: f64,
+    /// Implied volatility, in basis points.
+    #[arg(long, default_value_t)]
+    pub volatility_bps: u32,
+    /// Time until the pool expires, in days.
+    #[arg(long, default_value_t)]
+    pub duration_days: u32,
+    /// Keeps the pool's time to expiry constant.
+    #[arg(long)]
+    pub is_perpetual: bool,
+    /// Initial price, defaults to the strike.
+    #[arg(long, default_value_t)]
+    pub price: f64,
+    /// Swap fee, in basis points.
+    #[arg(long, default_value_t)]
+    pub fee_bps: u16,
+}
+
+impl From<&PoolArgs> for CreatePoolArgs {
+    fn from(args: &PoolArgs) -> Self {
+        Self {
+            strike: args.strike,
+            volatility_bps: args.volatility_bps,
+            duration_days: args.duration_days,
+            is_perpetual: args.is_perpetual,
+            price: args.price,
+            fee_bps: args.fee_bps,
+            ..Default::default()
+        }
+    }
+}
+
+impl PathArgs {
+    /// Length of a step in years, and the number of steps covering the pool's duration.
+    pub fn steps(&self, pool: &CreatePoolArgs) -> Result<(f64, usize), anyhow::Error> {
+        let step = parse_interval(&self.step)?;
+        let duration_days = if pool.is_perpetual {
+            30
+        } else {
+            pool.duration_days
+        };
+        let steps = self
+            .steps
+            .unwrap_or((duration_days as u64 * 86_400 / step) as usize);
+        if steps == 0 {
+            return Err(anyhow::anyhow!("Paths need at least one step"));
+        }
+        Ok((step as f64 / YEAR, steps))
+    }
+
+    /// Generates the paths from `price`, using the pool's implied volatility unless a realized one is set.
+    pub fn generate(
+        &self,
+        pool: &CreatePoolArgs,
+        price: f64,
+    ) -> Result<(f64, Vec<Vec<f64>>), anyhow::Error> {
+        let (dt, steps) = self.steps(pool)?;
+        let volatility = self
+            .realized_volatility
+            .unwrap_or(pool.volatility_bps as f64 / 10_000.0);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let paths = (0..self.paths)
+            .map(|_| gbm(&mut rng, price, self.drift, volatility, steps, dt))
+            .collect();
+        Ok((dt, paths))
+    }
+}
+
+fn normal() -> Normal {
+    Normal::new(0.0, 1.0).expect("standard normal")
+}
+
+/// Reserves of a unit of liquidity at `price`.
+pub fn reserves(config: &StrategyConfig, price: f64) -> (f64, f64) {
+    let deviation = config.volatility * config.tau.max(MIN_TAU).sqrt();
+    let d1 = ((price / config.strike).ln() + deviation * deviation / 2.0) / deviation;
+    (
+        1.0 - normal().cdf(d1),
+        config.strike * normal().cdf(d1 - deviation),
+    )
+}
+
+/// Price of a unit of liquidity holding `x` asset tokens.
+pub fn price(config: &StrategyConfig, x: f64) -> f64 {
+    let deviation = config.volatility * config.tau.max(MIN_TAU).sqrt();
+    let d1 = normal().inverse_cdf(1.0 - x.clamp(EPSILON, 1.0 - EPSILON));
+    config.strike * (d1 * deviation - deviation * deviation / 2.0).exp()
+}
+
+/// Prices following a geometric brownian motion from `price`, with `steps` of `dt` years.
+pub fn gbm(
+    rng: &mut StdRng,
+    price: f64,
+    drift: f64,
+    volatility: f64,
+    steps: usize,
+    dt: f64,
+) -> Vec<f64> {
+    let shocks = normal();
+    let mut prices = Vec::with_capacity(steps + 1);
+    prices.push(price);
+    for _ in 0..steps {
+        let shock: f64 = shocks.sample(rng);
+        let last = prices[prices.len() - 1];
+        prices.push(
+            last * ((drift - volatility * volatility / 2.0) * dt + volatility * dt.sqrt() * shock)
+                .exp(),
+        );
+    }
+    prices
+}
+
+/// A swap against a simulated pool, with the input including the fee.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Swap {
+    pub sell_asset: bool,
+    pub input: f64,
+    pub output: f64,
+    pub fee: f64,
+    /// Price of the pool after the swap.
+    pub price: f64,
+}
+
+/// A NormalStrategy pool simulated offline.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pool {
+    pub config: StrategyConfig,
+    pub is_perpetual: bool,
+    /// Swap fee, as a fraction.
+    pub fee: f64,
+    pub liquidity: f64,
+    pub reserve_x: f64,
+    pub reserve_y: f64,
+    /// Fees paid to the pool, in the token they were paid in.
+    pub fees_asset: f64,
+    pub fees_quote: f64,
+}
+
+impl Pool {
+    pub fn new(
+        config: StrategyConfig,
+        is_perpetual: bool,
+        fee: f64,
+        liquidity: f64,
+        price: f64,
+    ) -> Self {
+        let (x, y) = reserves(&config, price);
+        Self {
+            config,
+            is_perpetual,
+            fee,
+            liquidity,
+            reserve_x: x * liquidity,
+            reserve_y: y * liquidity,
+            fees_asset: 0.0,
+            fees_quote: 0.0,
+        }
+    }
+
+    /// Pool of the `createPool` arguments holding `liquidity` units at `price`.
+    pub fn from_args(
+        args: &CreatePoolArgs,
+        liquidity: f64,
+        price: f64,
+    ) -> Result<Self, anyhow::Error> {
+        args.validate()?;
+        if liquidity <= 0.0 || price <= 0.0 {
+            return Err(anyhow::anyhow!("Liquidity and price must be positive"));
+        }
+        let config = StrategyConfig {
+            strike: args.strike,
+            volatility: args.volatility_bps as f64 / 10_000.0,
+            tau: args.duration_days as f64 * 86_400.0 / YEAR,
+        };
+        Ok(Self::new(
+            config,
+            args.is_perpetual,
+            args.fee_bps as f64 / 10_000.0,
+            liquidity,
+            price,
+        ))
+    }
+
+    /// Price implied by the reserves at the current time to expiry.
+    pub fn price(&self) -> f64 {
+        price(&self.config, self.reserve_x / self.liquidity)
+    }
+
+    /// Swap moving the pool's price to `target`.
+    pub fn quote(&self, target: f64) -> Swap {
+        let (x, y) = reserves(&self.config, target);
+        let (x, y) = (x * self.liquidity, y * self.liquidity);
+        let sell_asset = x > self.reserve_x;
+        let (input, output) = if sell_asset {
+            ((x - self.reserve_x) / (1.0 - self.fee), self.reserve_y - y)
+        } else {
+            ((y - self.reserve_y) / (1.0 - self.fee), self.reserve_x - x)
+        };
+        Swap {
+            sell_asset,
+            input,
+            output,
+            fee: input * self.fee,
+            price: target,
+        }
+    }
+
+    /// Swap of an arbitrageur against `reference`, none if the pool's price is within the fee of it.
+    pub fn arbitrage(&self, reference: f64) -> Option<Swap> {
+        let price = self.price();
+        let target = if reference * (1.0 - self.fee) > price {
+            reference * (1.0 - self.fee)
+        } else if reference / (1.0 - self.fee) < price {
+            reference / (1.0 - self.fee)
+        } else {
+            return None;
+        };
+        Some(self.quote(target))
+    }
+
+    /// Applies a swap, keeping its fee aside.
+    pub fn swap(&mut self, swap: &Swap) {
+        let (x, y) = reserves(&self.config, swap.price);
+        self.reserve_x = x * self.liquidity;
+        self.reserve_y = y * self.liquidity;
+        if swap.sell_asset {
+            self.fees_asset += swap.fee;
+        } else {
+            self.fees_quote += swap.fee;
+        }
+    }
+
+    /// Moves time forward, which moves the price of pools with a maturity.
+    pub fn elapse(&mut self, years: f64) {
+        if !self.is_perpetual {
+            self.config.tau = (self.config.tau - years).max(MIN_TAU);
+        }
+    }
+}
+
+/// Result of a liquidity position over a price path, in quote tokens at the last price.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Outcome {
+    pub steps: usize,
+    pub swaps: usize,
+    pub volume_asset: f64,
+    pub volume_quote: f64,
+    pub reserve_x: f64,
+    pub reserve_y: f64,
+    pub last_price: f64,
+    /// Value of the deposit at the first price.
+    pub deposit: f64,
+    /// Value of the reserves, without fees.
+    pub value: f64,
+    /// Value of the deposited reserves if they had been held.
+    pub hold: f64,
+    pub fees: f64,
+    /// Value of the reserves against holding, without fees.
+    pub divergence: f64,
+    /// Value of the reserves and fees against holding.
+    pub net: f64,
+    /// Return of the reserves and fees on the deposit.
+    pub lp_return: f64,
+}
+
+/// Replays `prices` through a pool starting at the first one, with an arbitrage after every step of `dt` years.
+pub fn run(mut pool: Pool, prices: &[f64], dt: f64) -> Outcome {
+    let first = prices.first().copied().unwrap_or_default();
+    let (hold_x, hold_y) = (pool.reserve_x, pool.reserve_y);
+    let mut outcome = Outcome {
+        steps: prices.len().saturating_sub(1),
+        deposit: hold_x * first + hold_y,
+        ..Default::default()
+    };
+
+    for reference in prices.iter().skip(1) {
+        pool.elapse(dt);
+        if let Some(swap) = pool.arbitrage(*reference) {
+            pool.swap(&swap);
+            outcome.swaps += 1;
+            if swap.sell_asset {
+                outcome.volume_asset += swap.input;
+                outcome.volume_quote += swap.output;
+            } else {
+                outcome.volume_asset += swap.output;
+                outcome.volume_quote += swap.input;
+            }
+        }
+    }
+
+    let last = prices.last().copied().unwrap_or_default();
+    outcome.last_price = last;
+    outcome.reserve_x = pool.reserve_x;
+    outcome.reserve_y = pool.reserve_y;
+    outcome.value = pool.reserve_x * last + pool.reserve_y;
+    outcome.hold = hold_x * last + hold_y;
+    outcome.fees = pool.fees_asset * last + pool.fees_quote;
+    outcome.divergence = outcome.value - outcome.hold;
+    outcome.net = outcome.divergence + outcome.fees;
+    outcome.lp_return = if outcome.deposit > 0.0 {
+        (outcome.value + outcome.fees) / outcome.deposit - 1.0
+    } else {
+        0.0
+    };
+    outcome
+}
+
+/// Distribution of the outcomes of many paths, relative to the deposit.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub paths: usize,
+    pub mean_return: f64,
+    pub median_return: f64,
+    /// 5th percentile of the returns.
+    pub tail_return: f64,
+    /// Mean return of the reserves and fees against holding.
+    pub mean_excess: f64,
+    /// Mean fees on the deposit.
+    pub fee_capture: f64,
+    /// Mean divergence on the deposit.
+    pub mean_divergence: f64,
+    pub mean_swaps: f64,
+}
+
+impl Summary {
+    /// Keys configurations can be ranked by, higher being better.
+    pub const KEYS: [&'static str; 4] =
+        ["mean_return", "tail_return", "mean_excess", "fee_capture"];
+
+    /// Value of a ranking key.
+    pub fn key(&self, key: &str) -> Option<f64> {
+        match key {
+            "mean_return" => Some(self.mean_return),
+            "tail_return" => Some(self.tail_return),
+            "mean_excess" => Some(self.mean_excess),
+            "fee_capture" => Some(self.fee_capture),
+            _ => None,
+        }
+    }
+
+    pub fn of(outcomes: &[Outcome]) -> Self {
+        if outcomes.is_empty() {
+            return Self::default();
+        }
+        let count = outcomes.len() as f64;
+        let mean =
+            |value: &dyn Fn(&Outcome) -> f64| outcomes.iter().map(value).sum::<f64>() / count;
+        let relative = |value: f64, outcome: &Outcome| {
+            if outcome.deposit > 0.0 {
+                value / outcome.deposit
+            } else {
+                0.0
+            }
+        };
+
+        let mut returns: Vec<f64> = outcomes.iter().map(|outcome| outcome.lp_return).collect();
+        returns.sort_by(f64::total_cmp);
+        let percentile = |p: f64| returns[((returns.len() - 1) as f64 * p).round() as usize];
+
+        Self {
+            paths: outcomes.len(),
+            mean_return: mean(&|outcome| outcome.lp_return),
+            median_return: percentile(0.5),
+            tail_return: percentile(0.05),
+            mean_excess: mean(&|outcome| relative(outcome.net, outcome)),
+            fee_capture: mean(&|outcome| relative(outcome.fees, outcome)),
+            mean_divergence: mean(&|outcome| relative(outcome.divergence, outcome)),
+            mean_swaps: mean(&|outcome| outcome.swaps as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(fee: f64) -> Pool {
+        let config = StrategyConfig {
+            strike: 100.0,
+            volatility: 0.5,
+            tau: 30.0 / 365.0,
+        };
+        Pool::new(config, false, fee, 10.0, 100.0)
+    }
+
+    #[test]
+    fn prices_match_reserves() {
+        let pool = pool(0.003);
+        for target in [80.0, 100.0, 125.0] {
+            let (x, _) = reserves(&pool.config, target);
+            assert!((price(&pool.config, x) - target).abs() / target < 1e-9);
+        }
+        assert!((pool.price() - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn arbitrageurs_trade_to_the_fee_band() {
+        let mut pool = pool(0.01);
+        assert!(pool.arbitrage(100.5).is_none());
+
+        let swap = pool.arbitrage(110.0).unwrap();
+        assert!(!swap.sell_asset);
+        pool.swap(&swap);
+        assert!((pool.price() - 110.0 * 0.99).abs() < 1e-6);
+        assert!((pool.fees_quote - swap.input * 0.01).abs() < 1e-12);
+
+        let swap = pool.arbitrage(90.0).unwrap();
+        assert!(swap.sell_asset);
+        pool.swap(&swap);
+        assert!((pool.price() - 90.0 / 0.99).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fees_offset_divergence_on_round_trips() {
+        let prices = [100.0, 110.0, 100.0, 90.0, 100.0];
+        let without_fees = run(pool(0.0), &prices, 0.0);
+        let with_fees = run(pool(0.003), &prices, 0.0);
+
+        assert_eq!(without_fees.swaps, 4);
+        assert!(without_fees.divergence.abs() < 1e-9);
+        assert!(with_fees.fees > 0.0);
+        assert!((with_fees.net - with_fees.divergence - with_fees.fees).abs() < 1e-12);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let path = gbm(&mut rng, 100.0, 0.0, 0.5, 24, 1.0 / 8760.0);
+        assert_eq!(path.len(), 25);
+        assert_eq!(
+            path,
+            gbm(
+                &mut StdRng::seed_from_u64(7),
+                100.0,
+                0.0,
+                0.5,
+                24,
+                1.0 / 8760.0
+            )
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Dumps the state of every pool at a block to json, for diffing against another snapshot.
+use super::info::PoolState;
+use super::list;
+use super::tokens::IERC20Permit;
+use super::utils::{read_block, PoolId};
+use super::{Config, SnapshotArgs};
+use crate::bindings::i_portfolio::IPortfolio;
+use colored::Colorize;
+use ethers::prelude::*;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+
+/// Handles the "Snapshot" command.
+pub async fn main(cfg: &Config, args: &SnapshotArgs) -> Result<(), anyhow::Error> {
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let snapshot = take(cfg, provider).await?;
+    let output = serde_json::to_string_pretty(&snapshot)?;
+
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, output)?;
+            let saved_msg = format!(
+                "{} {} {} {}",
+                "Saved snapshot of block".green(),
+                snapshot["block"].to_string().bold().green(),
+                "to".green(),
+                path.bold().green()
+            );
+            println!("{}", saved_msg.on_black());
+        }
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+/// Reads every pool with its pair at the read block. Amounts are raw integers, so snapshots diff exactly.
+pub async fn take<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+) -> Result<Value, anyhow::Error> {
+    let block_number = read_block(cfg, &*client).await?;
+    let block = client
+        .get_block(block_number)
+        .await
+        .map_err(|err| anyhow::anyhow!("snapshot.rs: Failed to get block: {}", err))?
+        .ok_or(anyhow::anyhow!("Block {} not found", block_number))?;
+    let chain_id = client
+        .get_chainid()
+        .await
+        .map_err(|err| anyhow::anyhow!("snapshot.rs: Failed to get chain id: {}", err))?;
+
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let version = portfolio.version().block(block_number).call().await?;
+
+    let mut tokens = HashMap::<Address, Value>::new();
+    let mut pools = Vec::new();
+    for event in list::created_pools(cfg, client.clone()).await? {
+        let pair_id = PoolId(event.pool_id).pair_id();
+        let (asset, _, quote, _) = portfolio.pairs(pair_id).block(block_number).call().await?;
+        for address in [asset, quote] {
+            if !tokens.contains_key(&address) {
+                let token = token(client.clone(), address, block_number).await;
+                tokens.insert(address, token);
+            }
+        }
+
+        let PoolState {
+            pool, spot_price, ..
+        } = PoolState::fetch_at(cfg, client.clone(), event.pool_id, block_number).await?;
+        pools.push(json!({
+            "pool_id": event.pool_id,
+            "pair_id": pair_id,
+            "asset": tokens[&asset],
+            "quote": tokens[&quote],
+            "virtual_x": pool.virtual_x.to_string(),
+            "virtual_y": pool.virtual_y.to_string(),
+            "liquidity": pool.liquidity.to_string(),
+            "last_timestamp": pool.last_timestamp,
+            "fee_bps": pool.fee_basis_points,
+            "priority_fee_bps": pool.priority_fee_basis_points,
+            "controller": format!("{:?}", pool.controller),
+            "strategy": format!("{:?}", pool.strategy),
+            "spot_price": spot_price.to_string(),
+        }));
+    }
+
+    Ok(json!({
+        "chain_id": chain_id.as_u64(),
+        "portfolio": format!("{:?}", portfolio.address()),
+        "version": version,
+        "block": block_number,
+        "block_hash": block.hash,
+        "timestamp": block.timestamp.as_u64(),
+        "pools": pools,
+    }))
+}
+
+/// Address, symbol and decimals of a token, leaving out what it does not implement.
+async fn token<M: Middleware + 'static>(client: Arc<M>, address: Address, block: u64) -> Value {
+    let erc20 = IERC20Permit::new(address, client);
+    json!({
+        "address": format!("{:?}", address),
+        "symbol": erc20.symbol().block(block).call().await.ok(),
+        "decimals": erc20.decimals().block(block).call().await.ok(),
+    })
+}
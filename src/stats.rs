@@ -0,0 +1,294 @@
+//! Activity of a pool over rolling windows, from its Swap events: volume, fees, swaps, traders and fee APR.
+use super::events::{self, address, uint, Pair, Trade};
+use super::info::PoolState;
+use super::tx::decode_log;
+use super::utils::PoolId;
+use super::Config;
+use crate::bindings::i_portfolio::IPortfolio;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, format_units},
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+/// Rolling windows the statistics are computed over, with their length in seconds.
+pub const WINDOWS: [(&str, u64); 3] = [("24h", 86_400), ("7d", 604_800), ("30d", 2_592_000)];
+/// Metrics `list` sorts pools by, over one of the windows.
+const METRICS: [&str; 5] = ["volume", "fees", "apr", "swaps", "traders"];
+const YEAR: f64 = 31_536_000.0;
+
+/// Whether `key` is a valid `list` sort key, see [`PoolStats::key`].
+pub fn is_key(key: &str) -> bool {
+    key == "tvl"
+        || key.rsplit_once('_').is_some_and(|(metric, window)| {
+            METRICS.contains(&metric) && WINDOWS.iter().any(|(name, _)| *name == window)
+        })
+}
+
+/// Activity of a pool over a window. Fees are in the token they were paid in.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub window: String,
+    pub volume_asset: f64,
+    pub volume_quote: f64,
+    pub fees_asset: f64,
+    pub fees_quote: f64,
+    pub swaps: usize,
+    pub traders: usize,
+    /// Fees valued in quote tokens at the current price, annualized and relative to the TVL.
+    pub apr: f64,
+}
+
+/// Activity of a pool over every window, with the TVL in quote tokens it is relative to.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub pool_id: u64,
+    pub block: u64,
+    pub spot_price: f64,
+    pub tvl: f64,
+    pub windows: Vec<Stats>,
+}
+
+/// A swap with its fee, in the input token, and the sender of its transaction.
+#[derive(Debug, Clone)]
+pub struct Swap {
+    pub trade: Trade,
+    pub fee: f64,
+    pub trader: Address,
+}
+
+impl Stats {
+    pub fn aggregate<'a>(
+        window: &str,
+        seconds: u64,
+        swaps: impl IntoIterator<Item = &'a Swap>,
+        price: f64,
+        tvl: f64,
+    ) -> Self {
+        let mut stats = Stats {
+            window: window.to_string(),
+            ..Default::default()
+        };
+        let mut traders = HashSet::new();
+        for swap in swaps {
+            stats.volume_asset += swap.trade.asset_amount();
+            stats.volume_quote += swap.trade.quote_amount();
+            if swap.trade.sell_asset {
+                stats.fees_asset += swap.fee;
+            } else {
+                stats.fees_quote += swap.fee;
+            }
+            stats.swaps += 1;
+            traders.insert(swap.trader);
+        }
+        stats.traders = traders.len();
+
+        let fees = stats.fees_asset * price + stats.fees_quote;
+        if tvl > 0.0 {
+            stats.apr = fees / tvl * YEAR / seconds as f64;
+        }
+        stats
+    }
+}
+
+impl PoolStats {
+    /// Keys `list` sorts pools by: `tvl`, or `volume`, `fees`, `apr`, `swaps` or `traders` with a window, such as `apr_7d`.
+    pub fn key(&self, key: &str) -> Option<f64> {
+        if key == "tvl" {
+            return Some(self.tvl);
+        }
+        let (metric, window) = key.rsplit_once('_')?;
+        let stats = self.windows.iter().find(|stats| stats.window == window)?;
+        match metric {
+            "volume" => Some(stats.volume_quote),
+            "fees" => Some(stats.fees_asset * self.spot_price + stats.fees_quote),
+            "apr" => Some(stats.apr),
+            "swaps" => Some(stats.swaps as f64),
+            "traders" => Some(stats.traders as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Computes the activity of a pool over the windows ending at the block of its state.
+pub async fn fetch<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    state: &PoolState,
+) -> Result<PoolStats, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let pair = Pair::from(
+        portfolio
+            .pairs(PoolId(state.pool_id).pair_id())
+            .block(state.block)
+            .call()
+            .await?,
+    );
+
+    let now = timestamp(&*client, state.block).await?;
+    let mut starts = Vec::with_capacity(WINDOWS.len());
+    for (_, seconds) in WINDOWS {
+        starts.push(
+            block_at(
+                &*client,
+                now.saturating_sub(seconds),
+                cfg.start_block,
+                state.block,
+            )
+            .await?,
+        );
+    }
+
+    let filter = events::filter(portfolio.address(), &["Swap"], Some(state.pool_id));
+    let from_block = starts.iter().copied().min().unwrap_or(state.block);
+    let mut traders = HashMap::<H256, Address>::new();
+    let mut swaps = Vec::new();
+    for log in events::logs(&*client, &filter, from_block, state.block).await? {
+        let Some(trade) = Trade::from_log(&log, &pair) else {
+            continue;
+        };
+        let Some((_, params)) = decode_log(&log) else {
+            continue;
+        };
+        let (Some(fee), Some(token_in)) =
+            (uint(&params, "feeAmountDec"), address(&params, "tokenIn"))
+        else {
+            continue;
+        };
+        let decimals = if token_in == pair.asset {
+            pair.asset_decimals
+        } else {
+            pair.quote_decimals
+        };
+
+        let trader = match traders.get(&trade.tx_hash) {
+            Some(trader) => *trader,
+            None => {
+                let trader = client
+                    .get_transaction(trade.tx_hash)
+                    .await
+                    .map_err(|err| anyhow::anyhow!("stats.rs: Failed to get transaction: {}", err))?
+                    .map(|tx| tx.from)
+                    .unwrap_or_default();
+                traders.insert(trade.tx_hash, trader);
+                trader
+            }
+        };
+        swaps.push(Swap {
+            trade,
+            fee: format_units(fee, decimals as u32)?.parse()?,
+            trader,
+        });
+    }
+
+    let wad = |value: U256| format_ether(value).parse::<f64>().unwrap_or_default();
+    let spot_price = wad(state.spot_price);
+    let tvl =
+        wad(U256::from(state.pool.virtual_x)) * spot_price + wad(U256::from(state.pool.virtual_y));
+    let windows = WINDOWS
+        .iter()
+        .zip(starts)
+        .map(|((window, seconds), start)| {
+            Stats::aggregate(
+                window,
+                *seconds,
+                swaps.iter().filter(|swap| swap.trade.block >= start),
+                spot_price,
+                tvl,
+            )
+        })
+        .collect();
+
+    Ok(PoolStats {
+        pool_id: state.pool_id,
+        block: state.block,
+        spot_price,
+        tvl,
+        windows,
+    })
+}
+
+async fn timestamp<M: Middleware>(client: &M, block: u64) -> Result<u64, anyhow::Error> {
+    Ok(client
+        .get_block(block)
+        .await
+        .map_err(|err| anyhow::anyhow!("stats.rs: Failed to get block: {}", err))?
+        .ok_or(anyhow::anyhow!("Block {} not found", block))?
+        .timestamp
+        .as_u64())
+}
+
+/// First block between `low` and `high` mined at or after `target`, by binary search.
+async fn block_at<M: Middleware>(
+    client: &M,
+    target: u64,
+    mut low: u64,
+    mut high: u64,
+) -> Result<u64, anyhow::Error> {
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if timestamp(client, middle).await? < target {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(sell_asset: bool, input: f64, output: f64, fee: f64, trader: u64) -> Swap {
+        Swap {
+            trade: Trade {
+                block: 1,
+                tx_hash: H256::zero(),
+                pool_id: 1,
+                sell_asset,
+                input,
+                output,
+                price: 0.0,
+                spot_price: 0.0,
+            },
+            fee,
+            trader: Address::from_low_u64_be(trader),
+        }
+    }
+
+    #[test]
+    fn aggregates_volume_fees_and_apr() {
+        let swaps = [
+            swap(true, 1.0, 100.0, 0.01, 1),
+            swap(false, 200.0, 2.0, 2.0, 2),
+            swap(true, 2.0, 200.0, 0.02, 1),
+        ];
+
+        let stats = Stats::aggregate("24h", 86_400, &swaps, 100.0, 36_500.0);
+
+        assert_eq!(stats.volume_asset, 5.0);
+        assert_eq!(stats.volume_quote, 500.0);
+        assert_eq!(stats.fees_quote, 2.0);
+        assert_eq!((stats.swaps, stats.traders), (3, 2));
+        // 5 quote of fees a day on 36500 of tvl.
+        assert!((stats.apr - 0.05).abs() < 1e-9, "{}", stats.apr);
+
+        let pool = PoolStats {
+            pool_id: 1,
+            block: 1,
+            spot_price: 100.0,
+            tvl: 36_500.0,
+            windows: vec![stats],
+        };
+        assert_eq!(pool.key("swaps_24h"), Some(3.0));
+        assert_eq!(pool.key("tvl"), Some(36_500.0));
+        assert_eq!(pool.key("swaps_7d"), None);
+        assert!(is_key("apr_30d") && is_key("tvl"));
+        assert!(!is_key("apr_1y") && !is_key("price_24h"));
+    }
+}
@@ -0,0 +1,364 @@
+//! Follows the events of Portfolio as they are emitted.
+//! Logs are streamed over the websocket subscription, and a dropped connection is resumed from the last seen block,
+//! replaying the logs missed in between without repeating the ones already printed.
+use super::abi;
+use super::compat;
+use super::decode::Scaler;
+use super::events;
+use super::utils::PoolId;
+use super::{Config, TailArgs};
+use crate::bindings::i_portfolio::{IPortfolio, IPORTFOLIO_ABI};
+use alloy_json_abi::JsonAbi;
+use alloy_primitives::B256;
+use colored::Colorize;
+use ethers::{prelude::*, providers::StreamExt};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Longest wait between two reconnection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Transaction senders kept to match the `--account` filter.
+const SENDERS: usize = 1_024;
+
+/// Handles the "Tail" command.
+pub async fn main(cfg: &Config, args: &TailArgs) -> Result<(), anyhow::Error> {
+    let mut provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, provider.clone());
+    let compat = compat::detect(cfg, &portfolio).await?;
+    let version = compat.version.clone();
+
+    let names = event_names(&args.events, args.pool.is_some())?;
+    if let Some(missing) = names.iter().find(|name| !compat.capabilities.emits(name)) {
+        return Err(anyhow::anyhow!(
+            "Portfolio {} does not emit {} events",
+            version,
+            missing
+        ));
+    }
+
+    let mut tail = Tail {
+        filter: events::filter(
+            portfolio.address(),
+            &names.iter().map(String::as_str).collect::<Vec<_>>(),
+            args.pool,
+        ),
+        pair: args.pair,
+        account: args
+            .account
+            .as_ref()
+            .map(|account| account.parse::<Address>())
+            .transpose()?,
+        json: args.json,
+        // The deployed version's events first, falling back to the bindings for the other contracts.
+        abis: std::iter::once(compat.abi.clone())
+            .chain(abi::known_abis()?)
+            .collect(),
+        cursor: Cursor {
+            block: args.from_block,
+            seen: HashSet::new(),
+        },
+        senders: HashMap::new(),
+    };
+
+    let tail_msg = format!(
+        "{} {} {} {}",
+        "Tailing".yellow(),
+        names.join(", ").bold().yellow(),
+        "events of Portfolio".yellow(),
+        version.bold().yellow()
+    );
+    eprintln!("{}", tail_msg.on_black());
+
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        let closed = match tail.follow(cfg, provider.clone()).await {
+            Ok(()) => "Subscription closed by the node".to_string(),
+            Err(err) => format!("Connection lost: {}", err),
+        };
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = Duration::from_secs(1);
+        }
+
+        loop {
+            let reconnect_msg = format!(
+                "{}{} {} {}",
+                closed.red(),
+                ", reconnecting in".yellow(),
+                format!("{}s", backoff.as_secs()).bold().yellow(),
+                match tail.cursor.block {
+                    Some(block) => format!("from block {}", block),
+                    None => String::new(),
+                }
+                .yellow()
+            );
+            eprintln!("{}", reconnect_msg.on_black());
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+
+            match Provider::<Ws>::connect(&cfg.rpc_url).await {
+                Ok(reconnected) => {
+                    provider = Arc::new(reconnected);
+                    break;
+                }
+                Err(err) => eprintln!("{}", format!("Failed to reconnect: {}", err).red()),
+            }
+        }
+    }
+}
+
+/// Resolves the requested event names against the ABI, defaulting to every event.
+/// Following a pool only keeps the events indexed by pool id.
+fn event_names(requested: &[String], by_pool: bool) -> Result<Vec<String>, anyhow::Error> {
+    let has_pool = |name: &str| {
+        IPORTFOLIO_ABI
+            .event(name)
+            .map(|event| event.inputs.first().map(|input| input.name.as_str()) == Some("poolId"))
+            .unwrap_or(false)
+    };
+    let all: Vec<String> = IPORTFOLIO_ABI
+        .events()
+        .map(|event| event.name.clone())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    if requested.is_empty() {
+        return Ok(all
+            .into_iter()
+            .filter(|name| !by_pool || has_pool(name))
+            .collect());
+    }
+
+    requested
+        .iter()
+        .map(|name| {
+            let event = all
+                .iter()
+                .find(|event| event.eq_ignore_ascii_case(name))
+                .ok_or(anyhow::anyhow!(
+                    "Unknown event {}, Portfolio emits {}",
+                    name,
+                    all.join(", ")
+                ))?;
+            if by_pool && !has_pool(event) {
+                return Err(anyhow::anyhow!("{} events do not belong to a pool", event));
+            }
+            Ok(event.clone())
+        })
+        .collect()
+}
+
+/// Position of the stream, so replayed logs are only printed once.
+#[derive(Debug, Default)]
+struct Cursor {
+    /// Block of the last handled log, replayed from on reconnection.
+    block: Option<u64>,
+    /// Logs handled in that block.
+    seen: HashSet<(H256, U256)>,
+}
+
+impl Cursor {
+    /// Moves the cursor to the log, returning false if it was already handled.
+    fn advance(&mut self, log: &Log) -> bool {
+        let (Some(block), Some(hash), Some(index)) =
+            (log.block_number, log.transaction_hash, log.log_index)
+        else {
+            return false;
+        };
+        let block = block.as_u64();
+
+        match self.block {
+            Some(last) if block < last => return false,
+            Some(last) if block == last => {}
+            _ => {
+                self.block = Some(block);
+                self.seen.clear();
+            }
+        }
+        self.seen.insert((hash, index))
+    }
+}
+
+struct Tail {
+    filter: Filter,
+    pair: Option<u32>,
+    account: Option<Address>,
+    json: bool,
+    abis: Vec<JsonAbi>,
+    cursor: Cursor,
+    senders: HashMap<H256, Address>,
+}
+
+impl Tail {
+    /// Replays the logs since the cursor, then follows new ones until the subscription ends.
+    async fn follow(
+        &mut self,
+        cfg: &Config,
+        provider: Arc<Provider<Ws>>,
+    ) -> Result<(), anyhow::Error> {
+        // Subscribes before replaying, so no log falls between the two.
+        let mut stream = provider.subscribe_logs(&self.filter).await?;
+        let mut scaler = Scaler::new(cfg, Some(provider.clone()), None)?;
+        let head = provider.get_block_number().await?.as_u64();
+
+        let from = *self.cursor.block.get_or_insert(head + 1);
+        for log in events::logs(&*provider, &self.filter, from, head).await? {
+            self.handle(&provider, &mut scaler, log).await?;
+        }
+
+        while let Some(log) = stream.next().await {
+            self.handle(&provider, &mut scaler, log).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle(
+        &mut self,
+        provider: &Provider<Ws>,
+        scaler: &mut Scaler,
+        log: Log,
+    ) -> Result<(), anyhow::Error> {
+        if log.removed == Some(true) || !self.cursor.advance(&log) {
+            return Ok(());
+        }
+
+        let topics: Vec<B256> = log.topics.iter().map(|topic| B256::from(topic.0)).collect();
+        let Some(mut event) = abi::decode_log(&self.abis, &topics, &log.data) else {
+            return Ok(());
+        };
+        if !self.matches(provider, &log, &event.fields).await? {
+            return Ok(());
+        }
+        scaler.scale(&mut event.fields, None).await;
+
+        let name = event.name.split('(').next().unwrap_or_default();
+        let block = log.block_number.unwrap_or_default();
+        let hash = log.transaction_hash.unwrap_or_default();
+        if self.json {
+            let line = json!({
+                "block": block.as_u64(),
+                "tx_hash": hash,
+                "log_index": log.log_index.unwrap_or_default().as_u64(),
+                "event": name,
+                "fields": event.fields,
+            });
+            println!("{}", serde_json::to_string(&line)?);
+        } else {
+            let fields = event
+                .fields
+                .iter()
+                .map(|(name, value)| match value {
+                    Value::String(value) => format!("{}: {}", name, value),
+                    value => format!("{}: {}", name, value),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let event_msg = format!(
+                "{} {} {} {}",
+                format!("#{}", block).yellow(),
+                name.bold().purple(),
+                fields.purple(),
+                format!("{:?}", hash).blue()
+            );
+            println!("{}", event_msg.on_black());
+        }
+
+        Ok(())
+    }
+
+    /// Applies the filters the node can't: the pair, and the account as a parameter or the sender of the transaction.
+    async fn matches(
+        &mut self,
+        provider: &Provider<Ws>,
+        log: &Log,
+        fields: &Map<String, Value>,
+    ) -> Result<bool, anyhow::Error> {
+        let uint = |name: &str| {
+            fields
+                .get(name)
+                .and_then(|value| value.as_str())
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+
+        if let Some(pair) = self.pair {
+            let pair_id = uint("pairId").or(uint("poolId").map(|id| PoolId(id).pair_id() as u64));
+            if pair_id != Some(pair as u64) {
+                return Ok(false);
+            }
+        }
+
+        let Some(account) = self.account else {
+            return Ok(true);
+        };
+        let in_fields = fields.values().any(|value| {
+            value
+                .as_str()
+                .and_then(|value| value.parse::<Address>().ok())
+                == Some(account)
+        });
+        if in_fields {
+            return Ok(true);
+        }
+
+        let Some(hash) = log.transaction_hash else {
+            return Ok(false);
+        };
+        let sender = match self.senders.get(&hash) {
+            Some(sender) => *sender,
+            None => {
+                let sender = provider
+                    .get_transaction(hash)
+                    .await?
+                    .map(|tx| tx.from)
+                    .unwrap_or_default();
+                if self.senders.len() >= SENDERS {
+                    self.senders.clear();
+                }
+                self.senders.insert(hash, sender);
+                sender
+            }
+        };
+        Ok(sender == account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_skips_replayed_logs() {
+        let log = |block: u64, index: u64| Log {
+            block_number: Some(block.into()),
+            transaction_hash: Some(H256::from_low_u64_be(block)),
+            log_index: Some(index.into()),
+            ..Default::default()
+        };
+        let mut cursor = Cursor::default();
+
+        assert!(cursor.advance(&log(10, 0)));
+        assert!(cursor.advance(&log(10, 1)));
+        // Replayed from block 10 after a reconnection.
+        assert!(!cursor.advance(&log(10, 0)));
+        assert!(!cursor.advance(&log(10, 1)));
+        assert!(cursor.advance(&log(11, 0)));
+        assert!(!cursor.advance(&log(10, 2)));
+        assert_eq!(cursor.block, Some(11));
+    }
+
+    #[test]
+    fn resolves_event_names() {
+        assert_eq!(event_names(&["swap".to_string()], true).unwrap(), ["Swap"]);
+        assert!(event_names(&["Deposit".to_string()], true).is_err());
+        assert!(event_names(&["NotAnEvent".to_string()], false).is_err());
+
+        let by_pool = event_names(&[], true).unwrap();
+        assert!(by_pool.contains(&"Allocate".to_string()));
+        assert!(!by_pool.contains(&"ClaimFees".to_string()));
+    }
+}
@@ -0,0 +1,191 @@
+//! Loads the state of the terminal ui through the same calls as the `list`, `info` and `act` commands.
+use super::state::{Detail, PoolRow, Position, Quote, Ticket, TicketAction};
+use crate::bindings::i_portfolio::IPortfolio;
+use crate::events::{self, Pair, Trade, POOL_EVENTS};
+use crate::info::PoolState;
+use crate::list;
+use crate::tokens::IERC20Permit;
+use crate::utils::{read_block, PoolId};
+use crate::Config;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, parse_ether},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// Blocks searched for the trades charted in the detail pane.
+const BACKFILL_BLOCKS: u64 = 5_000;
+
+fn wad(value: impl Into<U256>) -> f64 {
+    format_ether(value.into()).parse().unwrap_or_default()
+}
+
+/// Loads every pool created on Portfolio, with the symbols of its pair.
+pub async fn load_pools<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+) -> Result<Vec<PoolRow>, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let mut symbols: HashMap<Address, String> = HashMap::new();
+    let mut rows = Vec::new();
+    let block = read_block(cfg, &*client).await?;
+
+    for event in list::created_pools(cfg, client.clone()).await? {
+        let (asset, _, quote, _) = portfolio
+            .pairs(PoolId(event.pool_id).pair_id())
+            .block(block)
+            .call()
+            .await?;
+        for token in [asset, quote] {
+            if !symbols.contains_key(&token) {
+                let symbol = IERC20Permit::new(token, client.clone())
+                    .symbol()
+                    .block(block)
+                    .call()
+                    .await
+                    .unwrap_or_else(|_| format!("{:?}", token));
+                symbols.insert(token, symbol);
+            }
+        }
+
+        let state = PoolState::fetch_at(cfg, client.clone(), event.pool_id, block).await?;
+        rows.push(PoolRow {
+            pool_id: event.pool_id,
+            asset: symbols[&asset].clone(),
+            quote: symbols[&quote].clone(),
+            spot_price: wad(state.spot_price),
+            liquidity: wad(state.pool.liquidity),
+            fee_bps: state.pool.fee_basis_points,
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Loads the state of a pool with its recent trades.
+pub async fn load_detail<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    pool_id: u64,
+) -> Result<Detail, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let state = PoolState::fetch(cfg, client.clone(), pool_id).await?;
+    let pair = Pair::from(
+        portfolio
+            .pairs(PoolId(pool_id).pair_id())
+            .block(state.block)
+            .call()
+            .await?,
+    );
+
+    let from_block = state
+        .block
+        .saturating_sub(BACKFILL_BLOCKS)
+        .max(cfg.start_block);
+    let filter = events::filter(portfolio.address(), POOL_EVENTS, Some(pool_id))
+        .from_block(from_block)
+        .to_block(state.block);
+    let trades = client
+        .get_logs(&filter)
+        .await
+        .map_err(|err| anyhow::anyhow!("tui: Failed to get logs: {}", err))?
+        .iter()
+        .filter_map(|log| Trade::from_log(log, &pair))
+        .collect();
+
+    Ok(Detail {
+        block: state.block,
+        reserve_x: wad(state.pool.virtual_x),
+        reserve_y: wad(state.pool.virtual_y),
+        liquidity: wad(state.pool.liquidity),
+        spot_price: wad(state.spot_price),
+        controller: state.pool.controller,
+        strategy: state.pool.strategy,
+        trades,
+    })
+}
+
+/// Loads the liquidity of `account` in the listed pools, skipping the pools it has none in.
+pub async fn load_positions<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    account: Address,
+    pools: &[PoolRow],
+) -> Result<Vec<Position>, anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    let mut positions = Vec::new();
+
+    for row in pools {
+        let state = PoolState::fetch(cfg, client.clone(), row.pool_id).await?;
+        let liquidity = portfolio
+            .positions(account, row.pool_id)
+            .block(state.block)
+            .call()
+            .await?;
+        if liquidity == 0 {
+            continue;
+        }
+
+        let share = if state.pool.liquidity == 0 {
+            0.0
+        } else {
+            liquidity as f64 / state.pool.liquidity as f64
+        };
+        positions.push(Position {
+            pool_id: row.pool_id,
+            liquidity: wad(liquidity),
+            asset: wad(state.pool.virtual_x) * share,
+            quote: wad(state.pool.virtual_y) * share,
+        });
+    }
+
+    Ok(positions)
+}
+
+/// Quotes the ticket against the current state of the pool.
+pub async fn quote<M: Middleware + 'static>(
+    cfg: &Config,
+    client: Arc<M>,
+    account: Address,
+    pool_id: u64,
+    ticket: &Ticket,
+) -> Result<Option<Quote>, anyhow::Error> {
+    let Some(amount) = ticket.amount() else {
+        return Ok(None);
+    };
+    let block = read_block(cfg, &*client).await?;
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client);
+    let amount = parse_ether(amount)?;
+
+    let quote = match ticket.action {
+        TicketAction::Swap => {
+            let output = portfolio
+                .get_amount_out(pool_id, ticket.sell_asset, amount, account)
+                .block(block)
+                .call()
+                .await?;
+            let (input, output) = (wad(amount), wad(output));
+            Quote::Swap {
+                output,
+                price: match (ticket.sell_asset, output > 0.0) {
+                    (_, false) => 0.0,
+                    (true, true) => output / input,
+                    (false, true) => input / output,
+                },
+            }
+        }
+        TicketAction::Allocate => {
+            let (asset, quote) = portfolio
+                .get_liquidity_deltas(pool_id, amount.as_u128() as i128)
+                .block(block)
+                .call()
+                .await?;
+            Quote::Allocate {
+                asset: wad(asset),
+                quote: wad(quote),
+            }
+        }
+    };
+
+    Ok(Some(quote))
+}
@@ -0,0 +1,191 @@
+//! Terminal ui browsing the pools of Portfolio, the signer's positions, and sending swaps and allocates from an order ticket.
+//! Pure state lives in `state`, rendering in `ui` and rpc calls in `data`, so the ui can be drawn on a test backend.
+mod data;
+mod state;
+mod ui;
+
+use super::utils::signer_client;
+use super::{batch, Config};
+use crossterm::{
+    event::{self, Event, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ethers::prelude::*;
+use ratatui::{backend::CrosstermBackend, Terminal};
+use state::{Command, Tui};
+use std::{
+    io::{self, Stdout},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Time between automatic refreshes of the selected pool.
+const REFRESH: Duration = Duration::from_secs(12);
+/// Time waited for a key press before redrawing.
+const TICK: Duration = Duration::from_millis(250);
+
+type Term = Terminal<CrosstermBackend<Stdout>>;
+
+/// Handles the "Tui" command.
+pub async fn main(cfg: &Config) -> Result<(), anyhow::Error> {
+    let provider = Arc::new(Provider::<Ws>::connect(&cfg.rpc_url).await?);
+    let account = cfg.impersonate.or_else(|| {
+        std::env::var("PRIVATE_KEY")
+            .ok()
+            .and_then(|key| key.parse::<LocalWallet>().ok())
+            .map(|wallet| wallet.address())
+    });
+
+    let mut state = Tui::new(account);
+    let mut terminal = enter()?;
+    let result = run(cfg, &mut terminal, &mut state, provider).await;
+    leave(&mut terminal)?;
+    result
+}
+
+fn enter() -> Result<Term, anyhow::Error> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    Ok(Terminal::new(CrosstermBackend::new(io::stdout()))?)
+}
+
+fn leave(terminal: &mut Term) -> Result<(), anyhow::Error> {
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+async fn run(
+    cfg: &Config,
+    terminal: &mut Term,
+    state: &mut Tui,
+    provider: Arc<Provider<Ws>>,
+) -> Result<(), anyhow::Error> {
+    terminal.draw(|frame| ui::draw(frame, state))?;
+    refresh(cfg, state, provider.clone()).await;
+    let mut refreshed = Instant::now();
+
+    loop {
+        terminal.draw(|frame| ui::draw(frame, state))?;
+
+        if !event::poll(TICK)? {
+            if refreshed.elapsed() > REFRESH {
+                select(cfg, state, provider.clone()).await;
+                refreshed = Instant::now();
+            }
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match state.on_key(key) {
+            Command::None => {}
+            Command::Quit => return Ok(()),
+            Command::Refresh => {
+                refresh(cfg, state, provider.clone()).await;
+                refreshed = Instant::now();
+            }
+            Command::Select => {
+                terminal.draw(|frame| ui::draw(frame, state))?;
+                select(cfg, state, provider.clone()).await;
+            }
+            Command::Quote => quote(cfg, state, provider.clone()).await,
+            Command::Submit(_) if cfg.block.is_some() => {
+                state.status = "Orders can't be sent while reading a past --block".to_string();
+            }
+            Command::Submit(step) => {
+                // The batch prints its simulation and receipt, so it runs outside of the ui.
+                leave(terminal)?;
+                let result = match signer_client(cfg).await {
+                    Ok(client) => batch::execute(cfg, client, &[step], true, false)
+                        .await
+                        .map(|_| ()),
+                    Err(err) => Err(err),
+                };
+                state.status = match &result {
+                    Ok(()) => "Order sent".to_string(),
+                    Err(err) => format!("Order failed: {}", err),
+                };
+                if let Err(err) = result {
+                    eprintln!("{}", err);
+                }
+                println!("Press enter to go back to the ui");
+                let mut line = String::new();
+                io::stdin().read_line(&mut line)?;
+
+                *terminal = enter()?;
+                terminal.clear()?;
+                state.ticket.amount.clear();
+                state.ticket.quote = None;
+                refresh(cfg, state, provider.clone()).await;
+                refreshed = Instant::now();
+            }
+        }
+    }
+}
+
+/// Reloads the pools and positions, then the selected pool. Errors are shown in the status line.
+async fn refresh(cfg: &Config, state: &mut Tui, provider: Arc<Provider<Ws>>) {
+    state.status = "Loading pools...".to_string();
+    match data::load_pools(cfg, provider.clone()).await {
+        Ok(pools) => {
+            state.pools = pools;
+            state.selected = state.selected.min(state.pools.len().saturating_sub(1));
+        }
+        Err(err) => {
+            state.status = format!("Failed to load pools: {}", err);
+            return;
+        }
+    }
+
+    if let Some(account) = state.account {
+        match data::load_positions(cfg, provider.clone(), account, &state.pools).await {
+            Ok(positions) => state.positions = positions,
+            Err(err) => {
+                state.status = format!("Failed to load positions: {}", err);
+                return;
+            }
+        }
+    }
+
+    select(cfg, state, provider).await;
+}
+
+/// Reloads the selected pool and re-quotes the ticket.
+async fn select(cfg: &Config, state: &mut Tui, provider: Arc<Provider<Ws>>) {
+    let Some(pool_id) = state.selected_pool().map(|pool| pool.pool_id) else {
+        state.detail = None;
+        state.status = "No pools found".to_string();
+        return;
+    };
+
+    match data::load_detail(cfg, provider.clone(), pool_id).await {
+        Ok(detail) => {
+            state.detail = Some(detail);
+            state.status = format!("Pool {} loaded", pool_id);
+        }
+        Err(err) => state.status = format!("Failed to load pool {}: {}", pool_id, err),
+    }
+    quote(cfg, state, provider).await;
+}
+
+async fn quote(cfg: &Config, state: &mut Tui, provider: Arc<Provider<Ws>>) {
+    let Some(pool_id) = state.selected_pool().map(|pool| pool.pool_id) else {
+        return;
+    };
+    let account = state.account.unwrap_or_default();
+
+    match data::quote(cfg, provider, account, pool_id, &state.ticket).await {
+        Ok(quote) => state.ticket.quote = quote,
+        Err(err) => {
+            state.ticket.quote = None;
+            state.status = format!("Failed to quote: {}", err);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! State of the terminal ui and its key bindings, kept free of io so it can be driven headlessly.
+use crate::actions::{allocate::AllocateArgs, swap::SwapArgs};
+use crate::batch::Step;
+use crate::events::Trade;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ethers::types::Address;
+
+/// A row of the pool table.
+#[derive(Debug, Clone, Default)]
+pub struct PoolRow {
+    pub pool_id: u64,
+    pub asset: String,
+    pub quote: String,
+    pub spot_price: f64,
+    pub liquidity: f64,
+    pub fee_bps: u16,
+}
+
+/// State of the selected pool.
+#[derive(Debug, Clone, Default)]
+pub struct Detail {
+    pub block: u64,
+    pub reserve_x: f64,
+    pub reserve_y: f64,
+    pub liquidity: f64,
+    pub spot_price: f64,
+    pub controller: Address,
+    pub strategy: Address,
+    pub trades: Vec<Trade>,
+}
+
+/// Liquidity of the account in a pool, with its share of the pool's reserves.
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub pool_id: u64,
+    pub liquidity: f64,
+    pub asset: f64,
+    pub quote: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TicketAction {
+    #[default]
+    Swap,
+    Allocate,
+}
+
+/// Live quote of the order ticket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Quote {
+    Swap { output: f64, price: f64 },
+    Allocate { asset: f64, quote: f64 },
+}
+
+/// Order ticket of the selected pool.
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub action: TicketAction,
+    /// Sells the asset token if true, only used by swaps.
+    pub sell_asset: bool,
+    /// Input amount of swaps, or liquidity of allocates.
+    pub amount: String,
+    pub slippage: f64,
+    pub quote: Option<Quote>,
+    pub confirming: bool,
+}
+
+impl Default for Ticket {
+    fn default() -> Self {
+        Self {
+            action: TicketAction::Swap,
+            sell_asset: true,
+            amount: String::new(),
+            slippage: 0.01,
+            quote: None,
+            confirming: false,
+        }
+    }
+}
+
+impl Ticket {
+    pub fn amount(&self) -> Option<f64> {
+        self.amount
+            .parse::<f64>()
+            .ok()
+            .filter(|amount| *amount > 0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Focus {
+    #[default]
+    Pools,
+    Ticket,
+}
+
+/// What the event loop has to do after a key press.
+#[derive(Debug, Clone)]
+pub enum Command {
+    None,
+    Quit,
+    Refresh,
+    /// Reloads the selected pool and the quote.
+    Select,
+    Quote,
+    Submit(Step),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tui {
+    pub account: Option<Address>,
+    pub pools: Vec<PoolRow>,
+    pub selected: usize,
+    pub detail: Option<Detail>,
+    pub positions: Vec<Position>,
+    pub ticket: Ticket,
+    pub focus: Focus,
+    pub status: String,
+}
+
+impl Tui {
+    pub fn new(account: Option<Address>) -> Self {
+        Self {
+            account,
+            status: "tab: switch pane, r: refresh, q: quit".to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn selected_pool(&self) -> Option<&PoolRow> {
+        self.pools.get(self.selected)
+    }
+
+    /// Order of the ticket, once it has a valid amount and a quote.
+    pub fn step(&self) -> Option<Step> {
+        let pool_id = self.selected_pool()?.pool_id;
+        let amount = self.ticket.amount()?;
+        let slippage = self.ticket.slippage;
+
+        match (self.ticket.action, self.ticket.quote.as_ref()?) {
+            (TicketAction::Swap, Quote::Swap { .. }) => Some(Step::Swap {
+                pool_id,
+                args: SwapArgs {
+                    sell_asset: self.ticket.sell_asset,
+                    amount,
+                    price: 0.0,
+                    slippage,
+                },
+            }),
+            (TicketAction::Allocate, Quote::Allocate { asset, quote }) => Some(Step::Allocate {
+                pool_id,
+                args: AllocateArgs {
+                    liquidity: amount,
+                    max_asset: asset * (1.0 + slippage),
+                    max_quote: quote * (1.0 + slippage),
+                    use_max: false,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Command {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Command::Quit;
+        }
+
+        if self.ticket.confirming {
+            self.ticket.confirming = false;
+            return match (key.code, self.step()) {
+                (KeyCode::Char('y'), Some(step)) => {
+                    self.status = format!("Sending {}...", step.function());
+                    Command::Submit(step)
+                }
+                _ => {
+                    self.status = "Order cancelled".to_string();
+                    Command::None
+                }
+            };
+        }
+
+        match (self.focus, key.code) {
+            (_, KeyCode::Esc) | (Focus::Pools, KeyCode::Char('q')) => Command::Quit,
+            (_, KeyCode::Tab) => {
+                self.focus = match self.focus {
+                    Focus::Pools => Focus::Ticket,
+                    Focus::Ticket => Focus::Pools,
+                };
+                Command::None
+            }
+            (Focus::Pools, KeyCode::Char('r')) => Command::Refresh,
+            (Focus::Pools, KeyCode::Up | KeyCode::Char('k')) if self.selected > 0 => {
+                self.selected -= 1;
+                self.ticket.quote = None;
+                Command::Select
+            }
+            (Focus::Pools, KeyCode::Down | KeyCode::Char('j'))
+                if self.selected + 1 < self.pools.len() =>
+            {
+                self.selected += 1;
+                self.ticket.quote = None;
+                Command::Select
+            }
+            (Focus::Ticket, KeyCode::Char('a')) => {
+                self.ticket.action = match self.ticket.action {
+                    TicketAction::Swap => TicketAction::Allocate,
+                    TicketAction::Allocate => TicketAction::Swap,
+                };
+                self.ticket.quote = None;
+                Command::Quote
+            }
+            (Focus::Ticket, KeyCode::Char('s')) => {
+                self.ticket.sell_asset = !self.ticket.sell_asset;
+                self.ticket.quote = None;
+                Command::Quote
+            }
+            (Focus::Ticket, KeyCode::Char(c)) if c.is_ascii_digit() || c == '.' => {
+                self.ticket.amount.push(c);
+                self.ticket.quote = None;
+                Command::Quote
+            }
+            (Focus::Ticket, KeyCode::Backspace) => {
+                self.ticket.amount.pop();
+                self.ticket.quote = None;
+                Command::Quote
+            }
+            (Focus::Ticket, KeyCode::Enter) => {
+                match self.step() {
+                    Some(step) => {
+                        self.ticket.confirming = true;
+                        self.status =
+                            format!("Send {}? y to confirm, any key to cancel", step.function());
+                    }
+                    None => self.status = "Enter an amount with a quote first".to_string(),
+                }
+                Command::None
+            }
+            _ => Command::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(state: &mut Tui, code: KeyCode) -> Command {
+        state.on_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn ticket_submits_only_after_confirmation() {
+        let mut state = Tui::new(None);
+        state.pools = vec![PoolRow {
+            pool_id: 7,
+            ..Default::default()
+        }];
+
+        assert!(matches!(press(&mut state, KeyCode::Tab), Command::None));
+        assert!(matches!(
+            press(&mut state, KeyCode::Char('2')),
+            Command::Quote
+        ));
+        assert_eq!(state.ticket.amount, "2");
+
+        // Without a quote there is nothing to confirm.
+        press(&mut state, KeyCode::Enter);
+        assert!(!state.ticket.confirming);
+
+        state.ticket.quote = Some(Quote::Swap {
+            output: 3.0,
+            price: 1.5,
+        });
+        press(&mut state, KeyCode::Enter);
+        assert!(state.ticket.confirming);
+        match press(&mut state, KeyCode::Char('y')) {
+            Command::Submit(Step::Swap { pool_id, args }) => {
+                assert_eq!(pool_id, 7);
+                assert_eq!(args.amount, 2.0);
+                assert!(args.sell_asset);
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        press(&mut state, KeyCode::Enter);
+        assert!(matches!(
+            press(&mut state, KeyCode::Char('n')),
+            Command::None
+        ));
+        assert!(!state.ticket.confirming);
+    }
+}
@@ -0,0 +1,277 @@
+//! Rendering of the terminal ui from its state.
+use super::state::{Focus, Quote, TicketAction, Tui};
+use ratatui::{
+    prelude::*,
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Row, Table, TableState},
+};
+
+pub fn draw(frame: &mut Frame, state: &Tui) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(60),
+            Constraint::Min(8),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[0]);
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+
+    draw_pools(frame, state, top[0]);
+    draw_detail(frame, state, top[1]);
+    draw_positions(frame, state, bottom[0]);
+    draw_ticket(frame, state, bottom[1]);
+    frame.render_widget(
+        Paragraph::new(state.status.as_str()).style(Style::default().fg(Color::Yellow)),
+        rows[2],
+    );
+}
+
+fn pane(title: &str, focused: bool) -> Block<'_> {
+    let style = if focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn draw_pools(frame: &mut Frame, state: &Tui, area: Rect) {
+    let header = Row::new(vec!["Pool", "Pair", "Price", "Liquidity", "Fee"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = state.pools.iter().map(|pool| {
+        Row::new(vec![
+            pool.pool_id.to_string(),
+            format!("{}/{}", pool.asset, pool.quote),
+            format!("{:.4}", pool.spot_price),
+            format!("{:.4}", pool.liquidity),
+            format!("{}bp", pool.fee_bps),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header)
+        .block(pane("Pools", state.focus == Focus::Pools))
+        .widths(&[
+            Constraint::Length(16),
+            Constraint::Length(14),
+            Constraint::Length(12),
+            Constraint::Length(14),
+            Constraint::Length(6),
+        ])
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+
+    let mut table_state = TableState::default();
+    table_state.select((!state.pools.is_empty()).then_some(state.selected));
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
+fn draw_detail(frame: &mut Frame, state: &Tui, area: Rect) {
+    let title = match state.selected_pool() {
+        Some(pool) => format!("Pool {}", pool.pool_id),
+        None => "Pool".to_string(),
+    };
+    let Some(detail) = &state.detail else {
+        frame.render_widget(
+            Paragraph::new("Loading...").block(pane(&title, false)),
+            area,
+        );
+        return;
+    };
+
+    let block = pane(&title, false);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(7), Constraint::Min(3)])
+        .split(inner);
+
+    let lines = vec![
+        Line::from(format!("block: {}", detail.block)),
+        Line::from(format!("spot price: {}", detail.spot_price)),
+        Line::from(format!("reserve x: {}", detail.reserve_x)),
+        Line::from(format!("reserve y: {}", detail.reserve_y)),
+        Line::from(format!("liquidity: {}", detail.liquidity)),
+        Line::from(format!("strategy: {:?}", detail.strategy)),
+        Line::from(format!("controller: {:?}", detail.controller)),
+    ];
+    frame.render_widget(Paragraph::new(lines), parts[0]);
+
+    // Trade prices by block, ending with the current spot price.
+    let mut points: Vec<(f64, f64)> = detail
+        .trades
+        .iter()
+        .map(|trade| (trade.block as f64, trade.price))
+        .collect();
+    points.push((detail.block as f64, detail.spot_price));
+
+    let (x_min, x_max) = bounds(points.iter().map(|point| point.0));
+    let (y_min, y_max) = bounds(points.iter().map(|point| point.1));
+    let datasets = vec![Dataset::default()
+        .name("price")
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(Color::Cyan))
+        .data(&points)];
+    let chart = Chart::new(datasets)
+        .x_axis(
+            Axis::default()
+                .title("block")
+                .bounds([x_min, x_max])
+                .labels(vec![
+                    Span::raw(format!("{}", x_min)),
+                    Span::raw(format!("{}", x_max)),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .title("price")
+                .bounds([y_min, y_max])
+                .labels(vec![
+                    Span::raw(format!("{:.2}", y_min)),
+                    Span::raw(format!("{:.2}", y_max)),
+                ]),
+        );
+    frame.render_widget(chart, parts[1]);
+}
+
+/// Bounds of the values, widened when they are all equal so the chart has a range to draw in.
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if min < max {
+        (min, max)
+    } else {
+        (min - 1.0, max + 1.0)
+    }
+}
+
+fn draw_positions(frame: &mut Frame, state: &Tui, area: Rect) {
+    let title = match state.account {
+        Some(account) => format!("Positions of {:?}", account),
+        None => "Positions (set PRIVATE_KEY)".to_string(),
+    };
+    let header = Row::new(vec!["Pool", "Liquidity", "Asset", "Quote"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let rows = state.positions.iter().map(|position| {
+        Row::new(vec![
+            position.pool_id.to_string(),
+            format!("{:.4}", position.liquidity),
+            format!("{:.4}", position.asset),
+            format!("{:.4}", position.quote),
+        ])
+    });
+    let table = Table::new(rows)
+        .header(header)
+        .block(pane(&title, false))
+        .widths(&[
+            Constraint::Length(16),
+            Constraint::Length(14),
+            Constraint::Length(14),
+            Constraint::Length(14),
+        ]);
+    frame.render_widget(table, area);
+}
+
+fn draw_ticket(frame: &mut Frame, state: &Tui, area: Rect) {
+    let ticket = &state.ticket;
+    let (asset, quote) = match state.selected_pool() {
+        Some(pool) => (pool.asset.as_str(), pool.quote.as_str()),
+        None => ("asset", "quote"),
+    };
+
+    let mut lines = vec![Line::from(format!(
+        "action: {} (a to switch)",
+        match ticket.action {
+            TicketAction::Swap => "swap",
+            TicketAction::Allocate => "allocate",
+        }
+    ))];
+    match ticket.action {
+        TicketAction::Swap => {
+            let (input, output) = if ticket.sell_asset {
+                (asset, quote)
+            } else {
+                (quote, asset)
+            };
+            lines.push(Line::from(format!(
+                "side: sell {} for {} (s to switch)",
+                input, output
+            )));
+            lines.push(Line::from(format!("amount in: {}_", ticket.amount)));
+        }
+        TicketAction::Allocate => {
+            lines.push(Line::from(format!("liquidity: {}_", ticket.amount)));
+        }
+    }
+    lines.push(Line::from(format!(
+        "slippage: {}%",
+        ticket.slippage * 100.0
+    )));
+    lines.push(Line::from(match &ticket.quote {
+        Some(Quote::Swap { output, price }) => format!("quote: {} out @ {}", output, price),
+        Some(Quote::Allocate { asset: a, quote: q }) => {
+            format!("quote: pays {} {} and {} {}", a, asset, q, quote)
+        }
+        None if ticket.amount().is_some() => "quote: ...".to_string(),
+        None => "quote: enter an amount".to_string(),
+    }));
+    lines.push(Line::from("enter: send"));
+
+    frame.render_widget(
+        Paragraph::new(lines).block(pane("Order ticket", state.focus == Focus::Ticket)),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::state::{Detail, PoolRow};
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn draws_pools_detail_and_ticket() {
+        let mut state = Tui::new(None);
+        state.pools = vec![PoolRow {
+            pool_id: 4294967297,
+            asset: "WETH".to_string(),
+            quote: "USDC".to_string(),
+            spot_price: 1800.0,
+            liquidity: 10.0,
+            fee_bps: 30,
+        }];
+        state.detail = Some(Detail {
+            block: 100,
+            spot_price: 1800.0,
+            ..Default::default()
+        });
+        state.ticket.amount = "1.5".to_string();
+
+        let mut terminal = Terminal::new(TestBackend::new(140, 40)).unwrap();
+        terminal.draw(|frame| draw(frame, &state)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("4294967297"));
+        assert!(screen.contains("WETH/USDC"));
+        assert!(screen.contains("sell WETH for USDC"));
+        assert!(screen.contains("amount in: 1.5"));
+    }
+}
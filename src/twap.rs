@@ -0,0 +1,560 @@
+//! Time-weighted execution of a large swap, split into slices sent over a duration.
+//! Each slice is re-quoted and sent only while the spot and quoted prices are within the `[swap]` price band,
+//! and the schedule is saved after every step so a crashed run resumes where it stopped.
+use super::actions::{approve, swap::SwapArgs};
+use super::batch::{self, Step};
+use super::bindings::i_portfolio::IPortfolio;
+use super::events::{self, Pair, Trade};
+use super::history::parse_interval;
+use super::utils::{round_wad, signer_client, Client, PoolId, Rounding};
+use super::{compat, tx, Config, TwapArgs};
+use anyhow::Context;
+use colored::Colorize;
+use ethers::{
+    prelude::*,
+    utils::{format_ether, parse_ether},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How often a paused or failed slice checks the pool again.
+const POLL: Duration = Duration::from_secs(12);
+
+/// Trade being split, which a saved state must match to be resumed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub pool_id: u64,
+    pub sell_asset: bool,
+    /// Total amount of input tokens to sell.
+    pub amount: f64,
+    pub slices: u32,
+    /// Seconds between two slices.
+    pub interval: u64,
+}
+
+impl Schedule {
+    /// Amount of the next slice after `filled` slices selling `sold`, the last one taking the remainder.
+    pub fn slice(&self, filled: usize, sold: f64) -> f64 {
+        if filled + 1 >= self.slices as usize {
+            round_wad(self.amount - sold, Rounding::Floor).max(0.0)
+        } else {
+            round_wad(self.amount / self.slices as f64, Rounding::Floor)
+        }
+    }
+}
+
+/// A slice seen mined, with the price it traded at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fill {
+    pub index: u32,
+    pub amount: f64,
+    pub output: f64,
+    pub price: f64,
+    pub block: u64,
+}
+
+impl Fill {
+    fn of(index: u32, trade: &Trade) -> Self {
+        Self {
+            index,
+            amount: trade.input,
+            output: trade.output,
+            price: trade.price,
+            block: trade.block,
+        }
+    }
+}
+
+/// A slice sent but not seen mined yet, settled from its receipt before another slice is sent,
+/// or from the signer's swap of its `nonce` mined from `from_block` if its transaction was replaced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pending {
+    pub index: u32,
+    pub amount: f64,
+    pub from_block: u64,
+    /// Transaction of the slice, once broadcast.
+    #[serde(default)]
+    pub tx_hash: Option<H256>,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+}
+
+/// Progress of a schedule, persisted to its state file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwapState {
+    pub schedule: Schedule,
+    pub fills: Vec<Fill>,
+    /// Unix time before which the next slice is not sent.
+    pub next_at: u64,
+    pub paused: bool,
+    pub pending: Option<Pending>,
+}
+
+impl TwapState {
+    /// Resumes the state saved at `path`, or starts the schedule if there is none.
+    pub fn open(path: &Path, schedule: Schedule) -> Result<Self, anyhow::Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                return Ok(Self {
+                    schedule,
+                    fills: Vec::new(),
+                    next_at: 0,
+                    paused: false,
+                    pending: None,
+                })
+            }
+        };
+        let state: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("twap.rs: Invalid state file {}", path.display()))?;
+        if state.schedule != schedule {
+            return Err(anyhow::anyhow!(
+                "{} holds another twap of this pool, finish it with the same arguments, or delete it",
+                path.display()
+            ));
+        }
+        Ok(state)
+    }
+
+    /// Writes the state to a temporary file renamed over it, so a crash never leaves it half written.
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .context("twap.rs: Failed to write state")?;
+        fs::rename(&tmp, path).context("twap.rs: Failed to replace state")
+    }
+
+    /// Input tokens sold by the filled slices.
+    pub fn sold(&self) -> f64 {
+        self.fills.iter().map(|fill| fill.amount).sum()
+    }
+
+    fn record(&mut self, fill: Fill) {
+        self.fills.push(fill);
+        self.pending = None;
+        self.next_at = now() + self.schedule.interval;
+    }
+}
+
+/// Handles the "Twap" command.
+pub async fn main(cfg: &Config, args: &TwapArgs) -> Result<(), anyhow::Error> {
+    if args.amount <= 0.0 || args.slices == 0 {
+        return Err(anyhow::anyhow!("Amount and slices must be positive"));
+    }
+    let schedule = Schedule {
+        pool_id: args.pool_id,
+        sell_asset: args.sell_asset,
+        amount: args.amount,
+        slices: args.slices,
+        interval: parse_interval(&args.duration)? / args.slices as u64,
+    };
+    let path = match &args.state {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let dir = PathBuf::from(&cfg.state_dir).join("twap");
+            fs::create_dir_all(&dir).context("twap.rs: Failed to create state dir")?;
+            dir.join(format!("{}.json", args.pool_id))
+        }
+    };
+
+    approve::require_policy(cfg, "twap")?;
+    let client = signer_client(cfg).await?;
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+    compat::detect(cfg, &portfolio)
+        .await?
+        .require_all(&["getAmountOut", "pairs", "swap"])?;
+    let pair = Pair::from(
+        portfolio
+            .pairs(PoolId(args.pool_id).pair_id())
+            .call()
+            .await
+            .context("twap.rs: Failed to get pair")?,
+    );
+
+    let mut state = TwapState::open(&path, schedule)?;
+    let start_msg = format!(
+        "{} {} {} {} {} {} {}",
+        if state.fills.is_empty() {
+            "Starting twap of".yellow().bold()
+        } else {
+            "Resuming twap of".yellow().bold()
+        },
+        format!(
+            "{} {}",
+            args.amount,
+            if args.sell_asset { "asset" } else { "quote" }
+        )
+        .bold()
+        .magenta(),
+        "in pool".yellow(),
+        args.pool_id.to_string().bold().magenta(),
+        format!(
+            "over {} slices every {}s, {} filled, state in",
+            args.slices,
+            state.schedule.interval,
+            state.fills.len()
+        )
+        .yellow(),
+        path.display().to_string().bold().yellow(),
+        if cfg.swap.max_price > 0.0 {
+            format!("band {}-{}", cfg.swap.min_price, cfg.swap.max_price).yellow()
+        } else {
+            "no price band".yellow()
+        }
+    );
+    println!("{}", start_msg.on_black());
+    resume_unsaved(&client, &portfolio, &pair, &mut state, &path).await?;
+
+    while state.fills.len() < state.schedule.slices as usize {
+        // A slice sent earlier, maybe by a crashed run, is settled before another one is sent.
+        if state.pending.is_some() {
+            let filled = state.fills.len();
+            match settle(&client, &portfolio, &pair, &mut state).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    tokio::time::sleep(POLL).await;
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("{} {}", "Twap reconcile failed:".red(), err);
+                    tokio::time::sleep(POLL).await;
+                    continue;
+                }
+            }
+            state.save(&path)?;
+            if state.fills.len() > filled {
+                print_fill(&state);
+            }
+            continue;
+        }
+
+        let wait = state.next_at.saturating_sub(now());
+        if wait > 0 {
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        }
+
+        let index = state.fills.len() as u32;
+        let amount = state.schedule.slice(state.fills.len(), state.sold());
+        if amount <= 0.0 {
+            break;
+        }
+
+        let (spot, price) = match quote(&client, &portfolio, &state.schedule, amount).await {
+            Ok(prices) => prices,
+            Err(err) => {
+                eprintln!("{} {}", "Twap quote failed:".red(), err);
+                tokio::time::sleep(POLL).await;
+                continue;
+            }
+        };
+        if !cfg.swap.in_band(spot) || !cfg.swap.in_band(price) {
+            if !state.paused {
+                state.paused = true;
+                state.save(&path)?;
+                let paused_msg = format!(
+                    "   - {} {} {} {}",
+                    "paused, spot".purple(),
+                    spot.to_string().bold().purple(),
+                    "quote".purple(),
+                    format!("{} outside the swap price band", price).purple()
+                );
+                println!("{}", paused_msg.on_black());
+            }
+            tokio::time::sleep(POLL).await;
+            continue;
+        }
+        if state.paused {
+            state.paused = false;
+            println!("{}", "   - back in the band, resuming".purple().on_black());
+        }
+
+        // The slice can't be mined in a block already produced.
+        let from_block = client.get_block_number().await?.as_u64() + 1;
+        state.pending = Some(Pending {
+            index,
+            amount,
+            from_block,
+            tx_hash: None,
+            nonce: None,
+        });
+        state.save(&path)?;
+
+        let step = Step::Swap {
+            pool_id: state.schedule.pool_id,
+            args: SwapArgs {
+                sell_asset: state.schedule.sell_asset,
+                amount,
+                price: spot,
+                slippage: cfg.swap.slippage,
+            },
+        };
+        let (sent, nonce) = match batch::send(cfg, &client, &[step], true).await {
+            Ok(Some(sent)) => sent,
+            Ok(None) => {
+                state.pending = None;
+                state.save(&path)?;
+                return Err(anyhow::anyhow!(
+                    "twap.rs: Slice #{} was not sent",
+                    index + 1
+                ));
+            }
+            Err(err) => {
+                // Nothing was broadcast, so the slice is sent again.
+                state.pending = None;
+                eprintln!("{} {}", "Twap slice failed:".red(), err);
+                state.save(&path)?;
+                tokio::time::sleep(POLL).await;
+                continue;
+            }
+        };
+        if let Some(pending) = &mut state.pending {
+            pending.tx_hash = Some(sent.tx_hash());
+            pending.nonce = Some(nonce.as_u64());
+        }
+        state.save(&path)?;
+        // The slice stays pending whatever happens to it here, and is settled at the top of the loop.
+        if let Err(err) = tx::track(cfg, &client, sent, false).await {
+            eprintln!("{} {}", "Twap slice failed after it was sent:".red(), err);
+        }
+    }
+
+    let (input, output) = state
+        .fills
+        .iter()
+        .fold((0.0, 0.0), |(input, output), fill| {
+            (input + fill.amount, output + fill.output)
+        });
+    let average = match (state.schedule.sell_asset, input > 0.0 && output > 0.0) {
+        (_, false) => 0.0,
+        (true, true) => output / input,
+        (false, true) => input / output,
+    };
+    fs::remove_file(&path).ok();
+    let done_msg = format!(
+        "{} {} {} {}",
+        "Twap complete:".bold().green(),
+        format!("{} slices filled", state.fills.len()).green(),
+        "at an average price of".green(),
+        average.to_string().bold().green()
+    );
+    println!("{}", done_msg.on_black());
+    Ok(())
+}
+
+/// Spot price of the pool and price of the slice quoted by Portfolio.
+async fn quote(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    schedule: &Schedule,
+    amount: f64,
+) -> Result<(f64, f64), anyhow::Error> {
+    let spot: f64 = format_ether(
+        portfolio
+            .get_spot_price(schedule.pool_id)
+            .call()
+            .await
+            .context("twap.rs: Failed to get spot price")?,
+    )
+    .parse()?;
+    let output: f64 = format_ether(
+        portfolio
+            .get_amount_out(
+                schedule.pool_id,
+                schedule.sell_asset,
+                parse_ether(amount)?,
+                client.address(),
+            )
+            .call()
+            .await
+            .context("twap.rs: Failed to get amount out")?,
+    )
+    .parse()?;
+    if output <= 0.0 {
+        return Err(anyhow::anyhow!("The pool quotes no output for {}", amount));
+    }
+    let price = if schedule.sell_asset {
+        output / amount
+    } else {
+        amount / output
+    };
+    Ok((spot, price))
+}
+
+fn print_fill(state: &TwapState) {
+    let Some(fill) = state.fills.last() else {
+        return;
+    };
+    let fill_msg = format!(
+        "   - {} {} {} {} {}",
+        format!("#{}/{}", fill.index + 1, state.schedule.slices).purple(),
+        if state.schedule.sell_asset {
+            format!("sold {} asset", fill.amount).red()
+        } else {
+            format!("bought with {} quote", fill.amount).green()
+        },
+        "at".purple(),
+        fill.price.to_string().bold().purple(),
+        format!("in block {}", fill.block).purple()
+    );
+    println!("{}", fill_msg.on_black());
+}
+
+/// Settles the pending slice: records its fill once it is mined, or drops it once it can no longer be,
+/// so it is sent again. Returns whether it is still in flight.
+async fn settle(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    pair: &Pair,
+    state: &mut TwapState,
+) -> Result<bool, anyhow::Error> {
+    let Some(pending) = state.pending.clone() else {
+        return Ok(false);
+    };
+    let (Some(hash), Some(nonce)) = (pending.tx_hash, pending.nonce) else {
+        // Left by a crash before it was sent, which `resume_unsaved` checked.
+        state.pending = None;
+        return Ok(false);
+    };
+    if let Some(receipt) = client.get_transaction_receipt(hash).await? {
+        let trade = receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == portfolio.address())
+            .find_map(|log| Trade::from_log(log, pair));
+        match trade {
+            Some(trade) => state.record(Fill::of(pending.index, &trade)),
+            // Reverted.
+            None => state.pending = None,
+        }
+        return Ok(false);
+    }
+    if client.get_transaction(hash).await?.is_some() {
+        return Ok(true);
+    }
+    // Dropped or replaced, a replacement is the signer's swap of the same nonce.
+    match signer_swap(client, portfolio, pair, state, Some(nonce)).await? {
+        Some(trade) => state.record(Fill::of(pending.index, &trade)),
+        None => state.pending = None,
+    }
+    Ok(false)
+}
+
+/// Checks a slice whose transaction was not saved, left by a crash around its sending. It was not sent unless
+/// the signer swapped the pool since, in which case that swap can't be told apart from one sent by another command,
+/// so the run stops for it to be checked by hand.
+async fn resume_unsaved(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    pair: &Pair,
+    state: &mut TwapState,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let Some(pending) = state.pending.clone() else {
+        return Ok(());
+    };
+    if pending.tx_hash.is_some() {
+        return Ok(());
+    }
+    if let Some(trade) = signer_swap(client, portfolio, pair, state, None).await? {
+        return Err(anyhow::anyhow!(
+            "Slice #{} may have been sent before a crash: the signer swapped the pool in {:?} since. Add it to the fills of {} if it is the slice, or remove the pending slice otherwise",
+            pending.index + 1,
+            trade.tx_hash,
+            path.display()
+        ));
+    }
+    state.pending = None;
+    state.save(path)
+}
+
+/// Signer's swap of the pool in the direction of the schedule mined since the pending slice was sent,
+/// the one of `nonce` if set.
+async fn signer_swap(
+    client: &Arc<Client>,
+    portfolio: &IPortfolio<Client>,
+    pair: &Pair,
+    state: &TwapState,
+    nonce: Option<u64>,
+) -> Result<Option<Trade>, anyhow::Error> {
+    let Some(pending) = &state.pending else {
+        return Ok(None);
+    };
+    let latest = client.get_block_number().await?.as_u64();
+    let filter = events::filter(portfolio.address(), &["Swap"], Some(state.schedule.pool_id));
+    for log in events::logs(&**client, &filter, pending.from_block, latest).await? {
+        let Some(trade) = Trade::from_log(&log, pair) else {
+            continue;
+        };
+        if trade.sell_asset != state.schedule.sell_asset {
+            continue;
+        }
+        let Some(tx) = client.get_transaction(trade.tx_hash).await? else {
+            continue;
+        };
+        if tx.from == client.address() && nonce.map_or(true, |nonce| tx.nonce == nonce.into()) {
+            return Ok(Some(trade));
+        }
+    }
+    Ok(None)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule() -> Schedule {
+        Schedule {
+            pool_id: 1,
+            sell_asset: true,
+            amount: 1.0,
+            slices: 3,
+            interval: 60,
+        }
+    }
+
+    #[test]
+    fn last_slice_takes_the_remainder() {
+        let schedule = schedule();
+        let first = schedule.slice(0, 0.0);
+        assert_eq!(first, 0.333333333333);
+        assert_eq!(schedule.slice(1, first), first);
+        let last = schedule.slice(2, first * 2.0);
+        assert!((first * 2.0 + last - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn resumes_only_the_same_schedule() {
+        let path =
+            std::env::temp_dir().join(format!("portfolio-rs-twap-{}.json", std::process::id()));
+        let mut state = TwapState::open(&path, schedule()).unwrap();
+        state.record(Fill {
+            index: 0,
+            amount: 0.333333333333,
+            output: 333.0,
+            price: 999.0,
+            block: 10,
+        });
+        state.save(&path).unwrap();
+
+        assert_eq!(TwapState::open(&path, schedule()).unwrap().fills.len(), 1);
+        assert!(!path.with_extension("json.tmp").exists());
+        let other = Schedule {
+            slices: 4,
+            ..schedule()
+        };
+        assert!(TwapState::open(&path, other).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
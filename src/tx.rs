@@ -0,0 +1,395 @@
+use super::events::{address, uint, Pair, Trade};
+use super::nonce::NonceManager;
+use super::utils::{signer_client, Client, PoolId};
+use super::{gas, Config, TxArgs, TxCommands};
+use crate::bindings::i_portfolio::{IPortfolio, IPORTFOLIO_ABI};
+use anyhow::{self, Context};
+use colored::Colorize;
+use ethers::{
+    abi::{LogParam, RawLog, Token},
+    prelude::*,
+    types::transaction::eip2718::TypedTransaction,
+    utils::format_units,
+};
+use std::sync::Arc;
+
+/// Handles the "Tx" command.
+pub async fn main(cfg: &Config, args: &TxArgs) -> Result<(), anyhow::Error> {
+    let client = signer_client(cfg).await?;
+    let (hash, cancel) = match &args.command {
+        TxCommands::Speedup { hash } => (hash, false),
+        TxCommands::Cancel { hash } => (hash, true),
+    };
+    let hash = hash.parse::<H256>()?;
+
+    let replacement = replace(cfg, &client, hash, cancel).await?;
+    let nonce = replacement.nonce().cloned().unwrap_or_default();
+
+    let mut nonces = NonceManager::open(cfg, &client).await?;
+    let pending = broadcast(&client, replacement)
+        .await
+        .context("tx.rs: Failed to send replacement")?;
+    nonces.record(nonce, pending.tx_hash())?;
+    drop(nonces);
+
+    track(cfg, &client, pending, false).await?;
+
+    Ok(())
+}
+
+/// Signs and sends `tx`, or sends it unsigned for the node to sign when the signer is impersonated on a fork.
+async fn broadcast<'a>(
+    client: &'a Arc<Client>,
+    mut tx: TypedTransaction,
+) -> Result<PendingTransaction<'a, Ws>, anyhow::Error> {
+    if client.signer().is_impersonated() {
+        tx.set_from(client.address());
+        return Ok(client.inner().send_transaction(tx, None).await?);
+    }
+    Ok(client.send_transaction(tx, None).await?)
+}
+
+/// Fills the fees and the next local nonce of `tx` and sends it.
+/// The nonce state stays locked until the node accepted the transaction, so concurrent runs never share a nonce.
+pub async fn send<'a>(
+    cfg: &Config,
+    client: &'a Arc<Client>,
+    tx: TypedTransaction,
+) -> Result<PendingTransaction<'a, Ws>, anyhow::Error> {
+    Ok(send_with_nonce(cfg, client, tx).await?.0)
+}
+
+/// Sends `tx` like `send`, also returning its nonce, which identifies it even if it is replaced.
+pub async fn send_with_nonce<'a>(
+    cfg: &Config,
+    client: &'a Arc<Client>,
+    mut tx: TypedTransaction,
+) -> Result<(PendingTransaction<'a, Ws>, U256), anyhow::Error> {
+    gas::prepare(cfg, client, &mut tx).await?;
+
+    let mut nonces = NonceManager::open(cfg, client).await?;
+    let nonce = nonces.reserve()?;
+    tx.set_nonce(nonce);
+
+    match broadcast(client, tx).await {
+        Ok(pending) => {
+            nonces.record(nonce, pending.tx_hash())?;
+            Ok((pending, nonce))
+        }
+        Err(e) => {
+            nonces.release(nonce)?;
+            Err(e)
+        }
+    }
+}
+
+/// Builds a transaction reusing the nonce of the stuck `hash` with bumped fees.
+/// Cancelling replaces it with an empty transfer to the signer.
+async fn replace(
+    cfg: &Config,
+    client: &Arc<Client>,
+    hash: H256,
+    cancel: bool,
+) -> Result<TypedTransaction, anyhow::Error> {
+    let stuck = client
+        .get_transaction(hash)
+        .await?
+        .ok_or(anyhow::anyhow!("Transaction {:?} not found", hash))?;
+    if client.get_transaction_receipt(hash).await?.is_some() {
+        return Err(anyhow::anyhow!("Transaction {:?} is already mined", hash));
+    }
+    if stuck.from != client.address() {
+        return Err(anyhow::anyhow!(
+            "Transaction {:?} was not sent by the signer {:?}",
+            hash,
+            client.address()
+        ));
+    }
+
+    let stuck_max_fee = stuck
+        .max_fee_per_gas
+        .or(stuck.gas_price)
+        .unwrap_or_default();
+    let stuck_priority_fee = stuck
+        .max_priority_fee_per_gas
+        .or(stuck.gas_price)
+        .unwrap_or_default();
+    let (estimated_max_fee, estimated_priority_fee) = client.estimate_eip1559_fees(None).await?;
+
+    let max_fee = bump(stuck_max_fee, cfg.fee_bump).max(estimated_max_fee);
+    let priority_fee = bump(stuck_priority_fee, cfg.fee_bump)
+        .max(estimated_priority_fee)
+        .min(max_fee);
+
+    let request = Eip1559TransactionRequest::new()
+        .from(client.address())
+        .nonce(stuck.nonce)
+        .max_fee_per_gas(max_fee)
+        .max_priority_fee_per_gas(priority_fee)
+        .chain_id(client.signer().chain_id());
+    let request = if cancel {
+        request.to(client.address()).value(0).gas(21_000)
+    } else {
+        let request = request.data(stuck.input).value(stuck.value).gas(stuck.gas);
+        match stuck.to {
+            Some(to) => request.to(to),
+            None => request,
+        }
+    };
+
+    let replace_msg = format!(
+        "{} {} {} {} {} {}",
+        if cancel {
+            "Cancelling".yellow()
+        } else {
+            "Speeding up".yellow()
+        },
+        format!("{:?}", hash).bold().yellow(),
+        "with nonce".yellow(),
+        stuck.nonce.to_string().bold().yellow(),
+        "and max fee (gwei)".yellow(),
+        format_units(max_fee, "gwei")?.bold().yellow(),
+    );
+    println!("{}", replace_msg.on_black());
+
+    Ok(request.into())
+}
+
+/// Raises a fee by `percent`, rounding up so the node accepts it as a replacement.
+fn bump(fee: U256, percent: f64) -> U256 {
+    let bps = U256::from((percent * 100.0) as u64);
+    fee + (fee * bps + U256::from(9_999)) / U256::from(10_000)
+}
+
+/// Waits for a sent transaction to reach the configured confirmations and prints its decoded events.
+/// With zero confirmations the transaction is left pending, so scripted runs can queue the next one.
+pub async fn track(
+    cfg: &Config,
+    client: &Arc<Client>,
+    pending: PendingTransaction<'_, Ws>,
+    verbose: bool,
+) -> Result<Option<TransactionReceipt>, anyhow::Error> {
+    if cfg.confirmations == 0 {
+        let sent_msg = format!(
+            "{} {}",
+            "Sent tx".yellow(),
+            format!("{:?}", pending.tx_hash()).bold().yellow()
+        );
+        println!("{}", sent_msg.on_black());
+        return Ok(None);
+    }
+
+    let pending_msg = format!(
+        "{} {} {} {} {}",
+        "Sent tx".yellow(),
+        format!("{:?}", pending.tx_hash()).bold().yellow(),
+        "waiting for".yellow(),
+        cfg.confirmations.to_string().bold().yellow(),
+        "confirmations... please be patient".yellow(),
+    );
+    println!("{}", pending_msg.on_black());
+
+    let receipt = pending
+        .confirmations(cfg.confirmations)
+        .await
+        .context("tx.rs: Failed to confirm transaction")?
+        .ok_or(anyhow::anyhow!("Transaction was dropped from the mempool"))?;
+
+    let succeeded = receipt.status == Some(U64::from(1));
+    let receipt_msg = format!(
+        "{} {} {} {} {} {}",
+        if succeeded {
+            "Confirmed in block".bold().green()
+        } else {
+            "Reverted in block".bold().red()
+        },
+        receipt
+            .block_number
+            .unwrap_or_default()
+            .to_string()
+            .bold()
+            .green(),
+        "\nGas Used:".purple(),
+        receipt.gas_used.unwrap_or_default().to_string().purple(),
+        "\nEffective Gas Price (gwei):".purple(),
+        format_units(receipt.effective_gas_price.unwrap_or_default(), "gwei")?.purple(),
+    );
+    println!("{}", receipt_msg.on_black());
+
+    print_events(cfg, client, &receipt, verbose).await?;
+
+    if !succeeded {
+        return Err(anyhow::anyhow!(
+            "Transaction {:?} reverted",
+            receipt.transaction_hash
+        ));
+    }
+
+    Ok(Some(receipt))
+}
+
+/// Prints the Portfolio events emitted in a receipt. Verbose mode also prints every other raw log.
+async fn print_events(
+    cfg: &Config,
+    client: &Arc<Client>,
+    receipt: &TransactionReceipt,
+    verbose: bool,
+) -> Result<(), anyhow::Error> {
+    let portfolio = IPortfolio::new(cfg.portfolio_address.parse::<Address>()?, client.clone());
+
+    for log in &receipt.logs {
+        let decoded = if log.address == portfolio.address() {
+            decode_log(log)
+        } else {
+            None
+        };
+
+        match decoded {
+            Some((name, params)) => {
+                let pair = match uint(&params, "poolId") {
+                    Some(pool_id) => Some(Pair::from(
+                        portfolio
+                            .pairs(PoolId(pool_id.as_u64()).pair_id())
+                            .call()
+                            .await?,
+                    )),
+                    None => None,
+                };
+                let fields = params
+                    .iter()
+                    .map(|param| {
+                        format!(
+                            "{}: {}",
+                            param.name,
+                            format_param(param, &params, pair.as_ref())
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let event_msg = format!("   - {} {}", name.bold().purple(), fields.purple());
+                println!("{}", event_msg.on_black());
+
+                if let Some(pair) = pair {
+                    if let Some(trade) = Trade::from_log(log, &pair) {
+                        let price_msg = format!(
+                            "     {} {}",
+                            "Effective Price:".bold().blue(),
+                            trade.price.to_string().bold().blue()
+                        );
+                        println!("{}", price_msg.on_black());
+                    }
+                }
+            }
+            None if verbose => {
+                let raw_msg = format!(
+                    "   - {} {:?} {} {:?} {} {}",
+                    "log from".purple(),
+                    log.address,
+                    "topics:".purple(),
+                    log.topics,
+                    "data:".purple(),
+                    log.data
+                );
+                println!("{}", raw_msg.on_black());
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Decodes a Portfolio log into its event name and parameters.
+pub fn decode_log(log: &Log) -> Option<(String, Vec<LogParam>)> {
+    let topic = log.topics.first()?;
+    let event = IPORTFOLIO_ABI
+        .events()
+        .find(|event| event.signature() == *topic)?;
+    let parsed = event
+        .parse_log(RawLog {
+            topics: log.topics.clone(),
+            data: log.data.to_vec(),
+        })
+        .ok()?;
+
+    Some((event.name.clone(), parsed.params))
+}
+
+/// Formats a decoded event parameter of `params`, scaling token amounts by the decimals of their token in `pair`,
+/// the pool's pair if the event has one, and other amounts as wad, leaving ids, fees and decimals as is.
+pub fn format_param(param: &LogParam, params: &[LogParam], pair: Option<&Pair>) -> String {
+    let is_amount = !(param.name.ends_with("Id")
+        || param.name.contains("BasisPoints")
+        || param.name.contains("decimals"));
+
+    match &param.value {
+        Token::Uint(amount) if is_amount => {
+            let decimals = pair
+                .and_then(|pair| amount_decimals(&param.name, params, pair))
+                .unwrap_or(18);
+            format_units(*amount, decimals as u32).unwrap_or_else(|_| amount.to_string())
+        }
+        Token::Address(address) => format!("{:?}", address),
+        token => token.to_string(),
+    }
+}
+
+/// Decimals of the token an amount parameter of a Portfolio event is paid in, if it is a token of `pair`.
+fn amount_decimals(name: &str, params: &[LogParam], pair: &Pair) -> Option<u8> {
+    let token = match name {
+        "deltaAsset" => pair.asset,
+        "deltaQuote" => pair.quote,
+        "input" | "feeAmountDec" => address(params, "tokenIn")?,
+        "output" => address(params, "tokenOut")?,
+        "amount" => address(params, "token")?,
+        _ => return None,
+    };
+
+    if token == pair.asset {
+        Some(pair.asset_decimals)
+    } else if token == pair.quote {
+        Some(pair.quote_decimals)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_amounts_in_units_of_their_token() {
+        let pair = Pair {
+            asset: Address::repeat_byte(1),
+            asset_decimals: 18,
+            quote: Address::repeat_byte(2),
+            quote_decimals: 6,
+        };
+        let param = |name: &str, value: Token| LogParam {
+            name: name.to_string(),
+            value,
+        };
+        let params = vec![
+            param("poolId", Token::Uint(U256::from(1099511627777u64))),
+            param("price", Token::Uint(U256::exp10(18) * 2000)),
+            param("tokenIn", Token::Address(pair.quote)),
+            param("input", Token::Uint(U256::from(2_000_000_000u64))),
+            param("tokenOut", Token::Address(pair.asset)),
+            param("output", Token::Uint(U256::exp10(18))),
+        ];
+        let formatted: Vec<String> = params
+            .iter()
+            .map(|param| format_param(param, &params, Some(&pair)))
+            .collect();
+
+        assert_eq!(formatted[0], "1099511627777");
+        assert_eq!(formatted[1], "2000.000000000000000000");
+        assert_eq!(formatted[3], "2000.000000");
+        assert_eq!(formatted[5], "1.000000000000000000");
+        assert_eq!(
+            format_param(&params[3], &params, None),
+            "0.000000002000000000"
+        );
+    }
+}
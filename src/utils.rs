@@ -1 +1,359 @@
+use super::Config;
+use anyhow::Context;
+use async_trait::async_trait;
+use ethers::{
+    prelude::*,
+    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+};
+use fs2::FileExt;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+/// Signing client used for every state changing call on Portfolio.
+pub type Client = SignerMiddleware<Provider<Ws>, Account>;
+
+/// Account acting for the cli: the `PRIVATE_KEY` wallet, or an account impersonated on a `--fork`,
+/// whose transactions are sent unsigned for the node to sign.
+#[derive(Debug, Clone)]
+pub enum Account {
+    Wallet(LocalWallet),
+    Impersonated { address: Address, chain_id: u64 },
+}
+
+/// Signing failures of an `Account`.
+#[derive(Debug)]
+pub enum AccountError {
+    Wallet(WalletError),
+    /// An impersonated account was asked to sign.
+    NoKey(Address),
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::Wallet(err) => err.fmt(f),
+            AccountError::NoKey(address) => write!(
+                f,
+                "{:?} is impersonated on the fork and has no key to sign with",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+impl From<WalletError> for AccountError {
+    fn from(err: WalletError) -> Self {
+        AccountError::Wallet(err)
+    }
+}
+
+impl Account {
+    pub fn is_impersonated(&self) -> bool {
+        matches!(self, Account::Impersonated { .. })
+    }
+
+    /// Signs a raw digest, such as an EIP-712 permit.
+    pub fn sign_hash(&self, hash: H256) -> Result<Signature, anyhow::Error> {
+        match self {
+            Account::Wallet(wallet) => Ok(wallet.sign_hash(hash)?),
+            Account::Impersonated { address, .. } => Err(AccountError::NoKey(*address).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for Account {
+    type Error = AccountError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Account::Wallet(wallet) => Ok(wallet.sign_message(message).await?),
+            Account::Impersonated { address, .. } => Err(AccountError::NoKey(*address)),
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            Account::Wallet(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            Account::Impersonated { address, .. } => Err(AccountError::NoKey(*address)),
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Account::Wallet(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            Account::Impersonated { address, .. } => Err(AccountError::NoKey(*address)),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            Account::Wallet(wallet) => wallet.address(),
+            Account::Impersonated { address, .. } => *address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Account::Wallet(wallet) => wallet.chain_id(),
+            Account::Impersonated { chain_id, .. } => *chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Account::Wallet(wallet) => Account::Wallet(wallet.with_chain_id(chain_id)),
+            Account::Impersonated { address, .. } => Account::Impersonated {
+                address,
+                chain_id: chain_id.into(),
+            },
+        }
+    }
+}
+
+/// Connects to the configured rpc and wraps the provider with the `PRIVATE_KEY` wallet,
+/// or with the account impersonated by `--fork`, which needs no key.
+pub async fn signer_client(cfg: &Config) -> Result<Arc<Client>, anyhow::Error> {
+    let ws_provider = Provider::<Ws>::connect(&cfg.rpc_url).await?;
+
+    let chain_id = ws_provider.get_chainid().await?.as_u64();
+    let account = match cfg.impersonate {
+        Some(address) => Account::Impersonated { address, chain_id },
+        None => Account::Wallet(
+            std::env::var("PRIVATE_KEY")
+                .context("utils.rs: PRIVATE_KEY env var not set")?
+                .parse::<LocalWallet>()?
+                .with_chain_id(chain_id),
+        ),
+    };
+
+    Ok(Arc::new(SignerMiddleware::new(ws_provider, account)))
+}
+
+/// Parses a block number, hash or tag such as `latest`, `finalized` or `safe`.
+pub fn parse_block(block: &str) -> Result<BlockId, anyhow::Error> {
+    let block = block.trim();
+    if block.starts_with("0x") && block.len() == 66 {
+        return Ok(BlockId::Hash(block.parse::<H256>()?));
+    }
+    if let Ok(number) = block.parse::<u64>() {
+        return Ok(BlockId::Number(number.into()));
+    }
+    if let Some(hex) = block.strip_prefix("0x") {
+        return Ok(BlockId::Number(u64::from_str_radix(hex, 16)?.into()));
+    }
+
+    let tag = match block {
+        "latest" => BlockNumber::Latest,
+        "earliest" => BlockNumber::Earliest,
+        "pending" => BlockNumber::Pending,
+        "finalized" => BlockNumber::Finalized,
+        "safe" => BlockNumber::Safe,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid block {}, expected a number, a hash or a tag such as latest",
+                block
+            ))
+        }
+    };
+    Ok(BlockId::Number(tag))
+}
+
+/// Block read by the commands, the `--block` flag or the latest one.
+/// It is resolved to a number, so every call of a command reads the same state.
+pub async fn read_block<M: Middleware>(cfg: &Config, client: &M) -> Result<u64, anyhow::Error> {
+    let block = match &cfg.block {
+        Some(block) => parse_block(block)?,
+        None => BlockId::Number(BlockNumber::Latest),
+    };
+    if let BlockId::Number(BlockNumber::Number(number)) = block {
+        return Ok(number.as_u64());
+    }
+
+    client
+        .get_block(block)
+        .await
+        .map_err(|err| anyhow::anyhow!("utils.rs: Failed to get block: {}", err))?
+        .and_then(|block| block.number)
+        .map(|number| number.as_u64())
+        .ok_or(anyhow::anyhow!("Block {:?} not found", block))
+}
+
+/// Exclusive OS advisory lock on a file, released when dropped or when the process exits, even if it crashes.
+/// The file itself is left in place, as removing it would let another process lock a new file meanwhile.
+pub struct FileLock(File);
+
+impl FileLock {
+    /// Locks `path`, creating it if needed, waiting up to `timeout` while another process holds it.
+    pub async fn acquire(path: &Path, timeout: Duration) -> Result<Self, anyhow::Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("utils.rs: Failed to open lock {}", path.display()))?;
+        let started = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self(file)),
+                Err(_) if started.elapsed() < timeout => {
+                    tokio::time::sleep(Duration::from_millis(100)).await
+                }
+                Err(err) => {
+                    return Err(anyhow::anyhow!(
+                        "{} is locked by another process: {}",
+                        path.display(),
+                        err
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Direction `round_wad` rounds in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+}
+
+/// Rounds an amount to 12 decimals, so it parses as wad. Inputs and minimums are rounded down, maximums up.
+pub fn round_wad(amount: f64, rounding: Rounding) -> f64 {
+    let scaled = amount * 1e12;
+    let rounded = match rounding {
+        Rounding::Floor => scaled.floor(),
+        Rounding::Ceil => scaled.ceil(),
+    };
+    rounded / 1e12
+}
+
+/// Converts a wad amount to units of a token with `decimals`, rounding up as Portfolio does for the tokens it pulls.
+/// Allowances and balances are in these units, while Portfolio's amounts are wad whatever the decimals.
+pub fn wad_to_units(wad: U256, decimals: u8) -> U256 {
+    if decimals >= 18 {
+        return wad.saturating_mul(U256::exp10(decimals as usize - 18));
+    }
+    let scale = U256::exp10(18 - decimals as usize);
+    let units = wad / scale;
+    if wad % scale == U256::zero() {
+        units
+    } else {
+        units + 1
+    }
+}
+
+/// Implements useful methods for pool-ids.
+pub struct PoolId(pub u64);
+
+impl PoolId {
+    /// Decodes the key information embedded into the poolId.
+    /// Pool ids are 64-bits, with the following information encoded:
+    /// - 0-4 bits: altered? bool
+    /// - 4-8 bits: controlled? bool
+    /// - 8-32 bits: pair nonce u24
+    /// - 32-64 bits: pool nonce u32
+    pub fn decode(&self) -> (bool, bool, u32, u32) {
+        let altered = self.0 & 0b0000_0000_0000_0000_0000_0000_0000_0001 != 0;
+        let controlled = self.0 & 0b0000_0000_0000_0000_0000_0000_0000_0010 != 0;
+        let pair_nonce = (self.0 & 0b0000_0000_0000_0000_0000_0011_1111_1100) >> 2;
+        let pool_nonce = (self.0 & 0b1111_1111_1111_1111_1111_1100_0000_0000) >> 10;
+
+        (altered, controlled, pair_nonce as u32, pool_nonce as u32)
+    }
+
+    /// Pair id of the pool, stored in the upper 24 bits of the id.
+    pub fn pair_id(&self) -> u32 {
+        (self.0 >> 40) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::utils::parse_ether;
+
+    #[test]
+    fn parses_block_numbers_hashes_and_tags() {
+        assert_eq!(
+            parse_block("18000000").unwrap(),
+            BlockId::Number(18_000_000u64.into())
+        );
+        assert_eq!(parse_block("0x10").unwrap(), BlockId::Number(16u64.into()));
+        assert_eq!(
+            parse_block("finalized").unwrap(),
+            BlockId::Number(BlockNumber::Finalized)
+        );
+        assert_eq!(
+            parse_block(&format!("{:?}", H256::repeat_byte(1))).unwrap(),
+            BlockId::Hash(H256::repeat_byte(1))
+        );
+        assert!(parse_block("yesterday").is_err());
+    }
+
+    #[tokio::test]
+    async fn file_locks_are_exclusive_until_dropped() {
+        let path = std::env::temp_dir().join(format!("portfolio-rs-lock-{}", std::process::id()));
+
+        let lock = FileLock::acquire(&path, Duration::ZERO).await.unwrap();
+        assert!(FileLock::acquire(&path, Duration::from_millis(200))
+            .await
+            .is_err());
+        drop(lock);
+        assert!(FileLock::acquire(&path, Duration::ZERO).await.is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rounds_amounts_to_wad() {
+        assert_eq!(round_wad(1.000_000_000_000_9, Rounding::Floor), 1.0);
+        assert_eq!(
+            round_wad(1.000_000_000_000_1, Rounding::Ceil),
+            1.000_000_000_001
+        );
+        assert_eq!(round_wad(2.5, Rounding::Floor), 2.5);
+        assert_eq!(
+            parse_ether(round_wad(1.0 / 3.0, Rounding::Floor)).unwrap(),
+            U256::from(333_333_333_333_000_000u64)
+        );
+    }
+
+    #[test]
+    fn scales_wad_to_token_units() {
+        assert_eq!(
+            wad_to_units(parse_ether(1.5).unwrap(), 6),
+            U256::from(1_500_000)
+        );
+        // Dust below a unit still needs a whole unit.
+        assert_eq!(wad_to_units(U256::from(1), 6), U256::from(1));
+        assert_eq!(
+            wad_to_units(parse_ether(2).unwrap(), 18),
+            parse_ether(2).unwrap()
+        );
+        assert_eq!(wad_to_units(U256::MAX, 6), U256::MAX / U256::exp10(12) + 1);
+    }
+
+    #[test]
+    fn reads_the_pair_of_pool_ids() {
+        assert_eq!(PoolId(1099511627777).pair_id(), 1);
+        assert_eq!(PoolId((2 << 40) + (1 << 32) + 3).pair_id(), 2);
+    }
+}
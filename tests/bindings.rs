@@ -0,0 +1,8 @@
+//! Without the forge bindings `tests/integration.rs` is compiled out, so `cargo test -- --ignored`
+//! fails here instead of passing without running any end to end test.
+#[cfg(runtime_abi)]
+#[test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+fn integration_tests_need_the_forge_bindings() {
+    panic!("The integration tests need the forge bindings in out/bindings, generate them with ./setup.sh");
+}
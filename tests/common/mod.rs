@@ -0,0 +1,299 @@
+//! Local Portfolio deployment on anvil, shared by the integration tests.
+//! Contracts are deployed from the forge generated `bindings`, so the tests run fully offline.
+#![allow(dead_code)]
+#[path = "../../out/bindings/src/lib.rs"]
+mod bindings;
+
+use bindings::{
+    mock_token::MockToken,
+    mock_weth::MockWeth,
+    normal_strategy::{NORMALSTRATEGY_ABI, NORMALSTRATEGY_BYTECODE},
+    portfolio::{PORTFOLIO_ABI, PORTFOLIO_BYTECODE},
+};
+use ethers::{
+    abi::{Abi, ParamType, Token},
+    prelude::*,
+    utils::{hex, parse_ether, Anvil, AnvilInstance},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    process::Command,
+    sync::Arc,
+};
+
+pub type Client = SignerMiddleware<Provider<Ws>, LocalWallet>;
+
+/// Strike and initial price of the test pool, in quote tokens per WETH.
+pub const PRICE: f64 = 1000.0;
+
+/// Anvil node with WETH, a quote token, Portfolio, NormalStrategy and one funded pool.
+pub struct Harness {
+    pub anvil: AnvilInstance,
+    pub client: Arc<Client>,
+    pub private_key: String,
+    pub weth: MockWeth<Client>,
+    pub quote: MockToken<Client>,
+    pub portfolio: Contract<Client>,
+    pub strategy: Address,
+    pub pool_id: u64,
+    pub workdir: PathBuf,
+}
+
+impl Harness {
+    /// Spawns anvil, deploys the protocol and creates a WETH/quote pair and pool.
+    /// Panics when `anvil` is not on the `PATH`, as the tests were opted into.
+    pub async fn new() -> Self {
+        if Command::new("anvil").arg("--version").output().is_err() {
+            panic!("`anvil` from foundry is not on the PATH, install it with foundryup");
+        }
+        let anvil = Anvil::new().spawn();
+        let key = anvil.keys()[0].clone();
+        let private_key = hex::encode(key.to_bytes());
+        let wallet = LocalWallet::from(key).with_chain_id(anvil.chain_id());
+        let provider = Provider::<Ws>::connect(anvil.ws_endpoint()).await.unwrap();
+        let client = Arc::new(SignerMiddleware::new(provider, wallet));
+        let signer = client.address();
+
+        let weth = MockWeth::deploy(client.clone(), ())
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+        let quote = MockToken::deploy(
+            client.clone(),
+            ("Quote".to_string(), "QUOTE".to_string(), 18u8),
+        )
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+
+        // Portfolio takes WETH first, then the registry and position renderer, both the signer here.
+        let portfolio_address = deploy(&client, &PORTFOLIO_ABI, &PORTFOLIO_BYTECODE, |i| {
+            if i == 0 {
+                weth.address()
+            } else {
+                signer
+            }
+        })
+        .await;
+        let strategy = deploy(
+            &client,
+            &NORMALSTRATEGY_ABI,
+            &NORMALSTRATEGY_BYTECODE,
+            |_| portfolio_address,
+        )
+        .await;
+        let portfolio = Contract::new(portfolio_address, PORTFOLIO_ABI.clone(), client.clone());
+
+        weth.deposit()
+            .value(parse_ether(100).unwrap())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+        quote
+            .mint(signer, parse_ether(1_000_000).unwrap())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+        for token in [weth.address(), quote.address()] {
+            MockToken::new(token, client.clone())
+                .approve(portfolio_address, U256::MAX)
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        }
+
+        let pool_id = create_pool(
+            &client,
+            &portfolio,
+            strategy,
+            weth.address(),
+            quote.address(),
+        )
+        .await;
+
+        let workdir = std::env::temp_dir().join(format!("portfolio-rs-{}", anvil.port()));
+        std::fs::create_dir_all(&workdir).unwrap();
+
+        Self {
+            anvil,
+            client,
+            private_key,
+            weth,
+            quote,
+            portfolio,
+            strategy,
+            pool_id,
+            workdir,
+        }
+    }
+
+    /// Runs the `port` binary against the local deployment, returning whether it succeeded and its output.
+    pub fn port(&self, args: &[&str]) -> (bool, String) {
+        let output = Command::new(env!("CARGO_BIN_EXE_port"))
+            .args(args)
+            .current_dir(&self.workdir)
+            .env("NO_COLOR", "1")
+            .env("PRIVATE_KEY", &self.private_key)
+            .env("PORTFOLIO_RPC_URL", self.anvil.ws_endpoint())
+            .env(
+                "PORTFOLIO_PORTFOLIO_ADDRESS",
+                format!("{:?}", self.portfolio.address()),
+            )
+            .env("PORTFOLIO_START_BLOCK", "0")
+            .env("PORTFOLIO_STATE_DIR", self.workdir.join("state"))
+            .output()
+            .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status.success(), format!("{}{}", stdout, stderr))
+    }
+
+    /// Provides `liquidity` to the test pool through a one step `port batch`, panicking if it fails.
+    pub fn allocate(&self, liquidity: f64) {
+        self.write(
+            "plan.toml",
+            &format!(
+                "[[steps]]\naction = \"allocate\"\npool_id = {}\nliquidity = {:?}\nmax_asset = 50.0\nmax_quote = 100000.0\n",
+                self.pool_id, liquidity
+            ),
+        );
+        let (success, output) = self.port(&["batch", "plan.toml", "--yes"]);
+        assert!(success, "{}", output);
+    }
+
+    /// NormalStrategy contract of the test pool.
+    pub fn normal_strategy(&self) -> Contract<Client> {
+        Contract::new(
+            self.strategy,
+            NORMALSTRATEGY_ABI.clone(),
+            self.client.clone(),
+        )
+    }
+
+    /// Writes a file into the working directory of the binary.
+    pub fn write(&self, name: &str, contents: &str) {
+        std::fs::write(self.workdir.join(name), contents).unwrap();
+    }
+}
+
+/// Serves `body` as json to every request on a local port, returning its url.
+/// Runs on its own thread, as the tests block on the `port` binary.
+pub fn serve_json(body: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/price", listener.local_addr().unwrap());
+    let body = body.to_string();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+        }
+    });
+    url
+}
+
+/// Deploys a contract whose constructor only takes addresses, resolving each by its position,
+/// since parameter names are not part of the interface.
+async fn deploy(
+    client: &Arc<Client>,
+    abi: &Abi,
+    bytecode: &Bytes,
+    address_at: impl Fn(usize) -> Address,
+) -> Address {
+    let args = abi
+        .constructor()
+        .map(|constructor| {
+            constructor
+                .inputs
+                .iter()
+                .enumerate()
+                .map(|(i, input)| {
+                    assert_eq!(
+                        input.kind,
+                        ParamType::Address,
+                        "constructor parameter {} is not an address",
+                        i
+                    );
+                    Token::Address(address_at(i))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    ContractFactory::new(abi.clone(), bytecode.clone(), client.clone())
+        .deploy_tokens(args)
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .address()
+}
+
+/// Creates the WETH/quote pair and a NormalStrategy pool struck at `PRICE`.
+async fn create_pool(
+    client: &Arc<Client>,
+    portfolio: &Contract<Client>,
+    strategy: Address,
+    asset: Address,
+    quote: Address,
+) -> u64 {
+    let create_pair = portfolio
+        .method::<_, u32>("createPair", (asset, quote))
+        .unwrap();
+    let pair_id = create_pair.call().await.unwrap();
+    create_pair.send().await.unwrap().await.unwrap();
+
+    let normal_strategy = Contract::new(strategy, NORMALSTRATEGY_ABI.clone(), client.clone());
+    let price = parse_ether(PRICE).unwrap();
+    let (strategy_data, initial_x, initial_y) = normal_strategy
+        .method::<_, (Bytes, U256, U256)>(
+            "getStrategyData",
+            (
+                price,
+                U256::from(1_000),
+                U256::from(365 * 24 * 60 * 60),
+                false,
+                price,
+            ),
+        )
+        .unwrap()
+        .call()
+        .await
+        .unwrap();
+
+    let create_pool = portfolio
+        .method::<_, u64>(
+            "createPool",
+            (
+                U256::from(pair_id),
+                initial_x,
+                initial_y,
+                U256::from(30),
+                U256::from(10),
+                Address::zero(),
+                strategy,
+                strategy_data,
+            ),
+        )
+        .unwrap();
+    let pool_id = create_pool.call().await.unwrap();
+    create_pool.send().await.unwrap().await.unwrap();
+
+    pool_id
+}
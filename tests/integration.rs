@@ -0,0 +1,357 @@
+//! End to end tests of the `port` commands against a local anvil deployment.
+//! They need `anvil` from foundry on the `PATH` and the forge generated bindings for the contract bytecode,
+//! so they are opted into with `cargo test -- --ignored` and fail when either is missing.
+#![cfg(not(runtime_abi))]
+mod common;
+
+use common::Harness;
+use ethers::{prelude::*, utils::parse_ether};
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn info_prints_pool_state() {
+    let harness = Harness::new().await;
+    let pool_id = harness.pool_id.to_string();
+
+    let (success, output) = harness.port(&["info", "-p", &pool_id]);
+
+    assert!(success, "{}", output);
+    // The deployed version is resolved to the ABI bundled with the cli, none is registered in `abi_dir`.
+    assert!(
+        output.contains("Portfolio version: v1.3.0-beta"),
+        "{}",
+        output
+    );
+    assert!(output.contains("Pool Info:"), "{}", output);
+    assert!(output.contains(&pool_id), "{}", output);
+    assert!(
+        output.contains(&format!("{:?}", harness.strategy)),
+        "{}",
+        output
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn list_finds_created_pool() {
+    let harness = Harness::new().await;
+
+    let (success, output) = harness.port(&["list"]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("Found"), "{}", output);
+    assert!(output.contains(&harness.pool_id.to_string()), "{}", output);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn batch_allocates_liquidity() {
+    let harness = Harness::new().await;
+    let signer = harness.client.address();
+    harness.write(
+        "plan.toml",
+        &format!(
+            "[[steps]]\naction = \"allocate\"\npool_id = {}\nliquidity = 1.0\nmax_asset = 10.0\nmax_quote = 10000.0\n",
+            harness.pool_id
+        ),
+    );
+    let quote_before = harness.quote.balance_of(signer).call().await.unwrap();
+
+    let (success, output) = harness.port(&["batch", "plan.toml", "--yes"]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("Batch successful"), "{}", output);
+    let quote_after = harness.quote.balance_of(signer).call().await.unwrap();
+    assert!(quote_after < quote_before);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn swap_sells_asset() {
+    let harness = Harness::new().await;
+    let signer = harness.client.address();
+    harness.allocate(10.0);
+
+    let weth_before = harness.weth.balance_of(signer).call().await.unwrap();
+    let quote_before = harness.quote.balance_of(signer).call().await.unwrap();
+
+    let pool_id = harness.pool_id.to_string();
+    let (success, output) = harness.port(&[
+        "act", "-f", "swap", "-p", &pool_id, "-a", "true", "0.1", "0", "0.01",
+    ]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("Swap successful"), "{}", output);
+    let weth_after = harness.weth.balance_of(signer).call().await.unwrap();
+    let quote_after = harness.quote.balance_of(signer).call().await.unwrap();
+    assert_eq!(weth_before - weth_after, parse_ether(0.1).unwrap());
+    assert!(quote_after > quote_before);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn encode_matches_abi() {
+    let harness = Harness::new().await;
+    let signer = harness.client.address();
+    let pool_id = harness.pool_id.to_string();
+
+    let (success, output) = harness.port(&[
+        "encode",
+        "allocate",
+        "--pool-id",
+        &pool_id,
+        "--liquidity",
+        "1",
+        "--max-asset",
+        "10",
+        "--max-quote",
+        "10000",
+    ]);
+
+    assert!(success, "{}", output);
+    let expected = harness
+        .portfolio
+        .encode(
+            "allocate",
+            (
+                false,
+                signer,
+                harness.pool_id,
+                parse_ether(1).unwrap().as_u128(),
+                parse_ether(10).unwrap().as_u128(),
+                parse_ether(10_000).unwrap().as_u128(),
+            ),
+        )
+        .unwrap();
+    assert!(output.contains(&expected.to_string()), "{}", output);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn encode_quotes_swaps_and_pool_creations() {
+    let harness = Harness::new().await;
+    let signer = harness.client.address();
+    let pool_id = harness.pool_id.to_string();
+
+    let (success, output) = harness.port(&[
+        "encode",
+        "swap",
+        "--pool-id",
+        &pool_id,
+        "--sell-asset",
+        "--amount",
+        "0.1",
+    ]);
+
+    assert!(success, "{}", output);
+    let input = parse_ether(0.1).unwrap();
+    let output_amount = harness
+        .portfolio
+        .method::<_, U256>("getAmountOut", (harness.pool_id, true, input, signer))
+        .unwrap()
+        .call()
+        .await
+        .unwrap();
+    let expected = harness
+        .portfolio
+        .encode(
+            "swap",
+            ((
+                input.as_u128(),
+                output_amount.as_u128(),
+                false,
+                harness.pool_id,
+                true,
+            ),),
+        )
+        .unwrap();
+    assert!(output.contains(&expected.to_string()), "{}", output);
+
+    let strategy = format!("{:?}", harness.strategy);
+    let (success, output) = harness.port(&[
+        "encode",
+        "create-pool",
+        "--strategy",
+        &strategy,
+        "--strike",
+        "1000",
+        "--volatility-bps",
+        "1000",
+        "--duration-days",
+        "365",
+        "--fee-bps",
+        "30",
+    ]);
+
+    assert!(success, "{}", output);
+    let price = parse_ether(common::PRICE).unwrap();
+    let (strategy_data, reserve_x, reserve_y) = harness
+        .normal_strategy()
+        .method::<_, (Bytes, U256, U256)>(
+            "getStrategyData",
+            (
+                price,
+                U256::from(1_000),
+                U256::from(365 * 24 * 60 * 60),
+                false,
+                price,
+            ),
+        )
+        .unwrap()
+        .call()
+        .await
+        .unwrap();
+    let expected = harness
+        .portfolio
+        .encode(
+            "createPool",
+            (
+                0u32,
+                reserve_x,
+                reserve_y,
+                30u16,
+                0u16,
+                Address::zero(),
+                harness.strategy,
+                strategy_data,
+            ),
+        )
+        .unwrap();
+    assert!(output.contains(&expected.to_string()), "{}", output);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn bot_arb_trades_pool_to_reference_price() {
+    let harness = Harness::new().await;
+    harness.allocate(10.0);
+
+    let url = common::serve_json(r#"{"data": {"price": "1100"}}"#);
+    harness.write(
+        "portfolio.toml",
+        &format!(
+            "[default.arb]\nsource = \"http\"\nurl = \"{}\"\nprice_path = \"data.price\"\nmin_profit = 1.0\nnative_price = 1000.0\n",
+            url
+        ),
+    );
+
+    let pool_id = harness.pool_id.to_string();
+    let (success, output) = harness.port(&[
+        "bot",
+        "arb",
+        "-p",
+        &pool_id,
+        "--once",
+        "--approval",
+        "exact",
+    ]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("Batch successful"), "{}", output);
+    let spot_price = harness
+        .portfolio
+        .method::<_, U256>("getSpotPrice", harness.pool_id)
+        .unwrap()
+        .call()
+        .await
+        .unwrap();
+    assert!(spot_price > parse_ether(common::PRICE * 1.05).unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn bot_rebalance_moves_position_into_a_new_pool() {
+    let harness = Harness::new().await;
+    harness.allocate(10.0);
+    // The pool matures in a year, so it breaks the policy, and no other pool of the pair meets it.
+    harness.write(
+        "policy.toml",
+        &format!(
+            "min_days_to_maturity = 400.0\n\n[new_pool]\nstrategy = \"{:?}\"\nvolatility_bps = 1000\nduration_days = 30\nfee_bps = 30\n",
+            harness.strategy
+        ),
+    );
+
+    let pool_id = harness.pool_id.to_string();
+    let (success, output) = harness.port(&[
+        "bot",
+        "rebalance",
+        "-p",
+        &pool_id,
+        "--policy",
+        "policy.toml",
+        "--once",
+        "--approval",
+        "exact",
+    ]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("into a new pool"), "{}", output);
+    assert!(output.contains("Batch successful"), "{}", output);
+    let mut positions = Vec::new();
+    // The second pool of the pair is the one created by the multicall and allocated to as pool id zero.
+    for pool_id in [harness.pool_id, harness.pool_id + 1] {
+        let position = harness
+            .portfolio
+            .method::<_, u128>("positions", (harness.client.address(), pool_id))
+            .unwrap()
+            .call()
+            .await
+            .unwrap();
+        positions.push(position);
+    }
+    assert_eq!(positions[0], 0);
+    assert!(positions[1] > 0);
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and the forge bindings, run with --ignored"]
+async fn orders_watch_fills_executable_limit_orders() {
+    let harness = Harness::new().await;
+    harness.allocate(10.0);
+
+    let pool_id = harness.pool_id.to_string();
+    for price in ["900", "2000"] {
+        let (success, output) = harness.port(&[
+            "orders",
+            "place",
+            "-p",
+            &pool_id,
+            "--sell-asset",
+            "--amount",
+            "0.1",
+            "--price",
+            price,
+        ]);
+        assert!(success, "{}", output);
+    }
+    let weth_before = harness
+        .weth
+        .balance_of(harness.client.address())
+        .call()
+        .await
+        .unwrap();
+
+    // Watching can't prompt for approvals, so it needs a policy.
+    let (success, output) = harness.port(&["orders", "watch", "--once"]);
+    assert!(!success);
+    assert!(output.contains("--approval"), "{}", output);
+
+    let (success, output) = harness.port(&["orders", "watch", "--once", "--approval", "exact"]);
+
+    assert!(success, "{}", output);
+    assert!(output.contains("Filled order #1"), "{}", output);
+    assert!(!output.contains("Filled order #2"), "{}", output);
+    let weth_after = harness
+        .weth
+        .balance_of(harness.client.address())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(weth_before - weth_after, parse_ether(0.1).unwrap());
+
+    let (success, output) = harness.port(&["orders", "list"]);
+    assert!(success, "{}", output);
+    assert!(output.contains("filled for"), "{}", output);
+    assert!(output.contains("open, min output 200"), "{}", output);
+}